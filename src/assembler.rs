@@ -0,0 +1,749 @@
+// https://cc65.github.io/doc/ca65.html
+//
+// 6502 assembler for a subset of the ca65 syntax
+// Labels ("loop:"), local labels ("@loop:") scoped to the previous label, constants ("name = expr"),
+// .org, .byte/.db, .word/.dw, .res and expressions with "*" as the current address.
+// Unofficial opcodes are available as e.g. "LAX" or "*NOP", like the disassembler prints them.
+// The opcodes come from the same table the CPU decodes, looked up through the disassembler.
+
+use crate::cpu::instructions::{INSTRUCTIONS, INSTRUCTION_MAP};
+use crate::disassembler::{disassemble_one, Operand};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::mem::{discriminant, Discriminant};
+
+lazy_static! {
+    /// Opcode of every mnemonic and operand kind
+    static ref OPCODES: HashMap<(String, Discriminant<Operand>), u8> = {
+        let mut map = HashMap::new();
+        for ins in INSTRUCTIONS.iter() {
+            let decoded = disassemble_one(&[ins.address, 0, 0], 0, 0);
+            if let Some(mnemonic) = decoded.mnemonic {
+                map.entry((mnemonic, discriminant(&decoded.operand))).or_insert(ins.address);
+            }
+        }
+        map
+    };
+}
+
+/// Error with the line it occurred on, counted from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Class representing assembled code, the bytes start at the origin and gaps between .org are zero filled
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    labels: HashMap<String, i64>,
+}
+
+impl Assembly {
+    /// Value of a label or constant, local labels are named "scope@label"
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).map(|val| *val as u16)
+    }
+
+    /// Function that writes the bytes into data that is mapped at base, e.g. a PRG bank
+    pub fn patch(&self, data: &mut [u8], base: u16) -> Result<(), &'static str> {
+        let offset = self.origin.checked_sub(base).ok_or("Patch starts before the data")? as usize;
+        if offset + self.bytes.len() > data.len() {
+            return Err("Patch ends after the data");
+        }
+
+        data[offset .. offset + self.bytes.len()].copy_from_slice(&self.bytes);
+        Ok(())
+    }
+}
+
+/// A single statement of the source
+#[derive(Debug, Clone)]
+enum Statement {
+    Label(String),
+    Constant(String, String),
+    Org(String),
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Reserve(String, Option<String>),
+    Instruction(String, String),
+}
+
+/// Operand as written in the source
+enum Syntax<'a> {
+    Implied,
+    Accumulator,
+    Immediate(&'a str),
+    Direct(&'a str),
+    IndexedX(&'a str),
+    IndexedY(&'a str),
+    Indirect(&'a str),
+    IndirectX(&'a str),
+    IndirectY(&'a str),
+}
+
+/// Function that assembles the source
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    let mut lines = vec![];
+    let mut scope = String::new();
+
+    for (i, line) in source.lines().enumerate() {
+        for statement in parse_line(line).map_err(|message| AsmError { line: i + 1, message })? {
+            if let Statement::Label(name) = &statement {
+                if !name.starts_with('@') {
+                    scope = name.clone();
+                }
+            }
+            lines.push((i + 1, scope.clone(), statement));
+        }
+    }
+
+    // the first pass picks the instruction sizes and places the labels, the second one emits the bytes
+    let mut pass = Pass::new(false);
+    pass.run(&lines)?;
+
+    let mut last = Pass::new(true);
+    last.labels = pass.labels;
+    last.opcodes = pass.opcodes;
+    last.run(&lines)?;
+
+    Ok(Assembly {
+        origin: last.origin.unwrap_or(last.pc),
+        bytes: last.bytes,
+        labels: last.labels,
+    })
+}
+
+/// Function that splits a line into statements
+fn parse_line(line: &str) -> Result<Vec<Statement>, String> {
+    let mut line = strip_comment(line).trim();
+    let mut res = vec![];
+
+    // labels, possibly followed by a statement
+    while let Some((name, rest)) = line.split_once(':') {
+        if !is_identifier(name.trim()) || name.contains(char::is_whitespace) {
+            break;
+        }
+        res.push(Statement::Label(name.trim().to_string()));
+        line = rest.trim();
+    }
+
+    if line.is_empty() {
+        return Ok(res);
+    }
+
+    if let Some((name, val)) = line.split_once('=') {
+        if is_identifier(name.trim()) {
+            res.push(Statement::Constant(name.trim().to_string(), val.trim().to_string()));
+            return Ok(res);
+        }
+    }
+
+    let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+
+    let statement = match word.to_lowercase().as_str() {
+        ".org" => Statement::Org(rest.to_string()),
+        ".byte" | ".db" | ".byt" => Statement::Bytes(split_args(rest)),
+        ".word" | ".dw" | ".addr" => Statement::Words(split_args(rest)),
+        ".res" | ".ds" => {
+            let args = split_args(rest);
+            match args.len() {
+                1 | 2 => Statement::Reserve(args[0].clone(), args.get(1).cloned()),
+                _ => return Err(".res expects a size and an optional fill value".to_string()),
+            }
+        },
+        directive if directive.starts_with('.') => return Err(format!("unknown directive {}", word)),
+        _ => Statement::Instruction(word.to_uppercase(), rest.to_string()),
+    };
+
+    res.push(statement);
+    Ok(res)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = None;
+    for (i, c) in line.char_indices() {
+        match (quoted, c) {
+            (None, ';') => return &line[.. i],
+            (None, '"') | (None, '\'') => quoted = Some(c),
+            (Some(quote), _) if c == quote => quoted = None,
+            _ => {},
+        }
+    }
+    line
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '@')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Function that splits arguments on commas outside of strings and parentheses
+fn split_args(text: &str) -> Vec<String> {
+    let mut res = vec![];
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut quoted = None;
+
+    for c in text.chars() {
+        match (quoted, c) {
+            (None, ',') if depth == 0 => {
+                res.push(current.trim().to_string());
+                current.clear();
+                continue;
+            },
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, '"') | (None, '\'') => quoted = Some(c),
+            (Some(quote), _) if c == quote => quoted = None,
+            _ => {},
+        }
+        current.push(c);
+    }
+
+    if !current.trim().is_empty() || !res.is_empty() {
+        res.push(current.trim().to_string());
+    }
+    res
+}
+
+/// Function that recognizes the addressing syntax of an operand
+fn parse_operand(text: &str) -> Syntax<'_> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return Syntax::Implied;
+    }
+    if text.eq_ignore_ascii_case("a") {
+        return Syntax::Accumulator;
+    }
+    if let Some(val) = text.strip_prefix('#') {
+        return Syntax::Immediate(val);
+    }
+
+    if text.starts_with('(') {
+        if let Some(close) = matching_paren(text) {
+            let inner = text[1 .. close].trim();
+            let rest = text[close + 1 ..].trim();
+
+            if rest.is_empty() {
+                return match index_of(inner) {
+                    Some((val, 'X')) => Syntax::IndirectX(val),
+                    _ => Syntax::Indirect(inner),
+                };
+            }
+            if rest.strip_prefix(',').is_some_and(|index| index.trim().eq_ignore_ascii_case("y")) {
+                return Syntax::IndirectY(inner);
+            }
+        }
+    }
+
+    match index_of(text) {
+        Some((val, 'X')) => Syntax::IndexedX(val),
+        Some((val, _)) => Syntax::IndexedY(val),
+        None => Syntax::Direct(text),
+    }
+}
+
+fn matching_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Function that splits "expr,X" or "expr,Y"
+fn index_of(text: &str) -> Option<(&str, char)> {
+    let (val, index) = text.rsplit_once(',')?;
+    match index.trim().to_ascii_uppercase().as_str() {
+        "X" => Some((val.trim(), 'X')),
+        "Y" => Some((val.trim(), 'Y')),
+        _ => None,
+    }
+}
+
+/// Class representing one pass over the statements
+struct Pass {
+    final_pass: bool,
+    labels: HashMap<String, i64>,
+    pc: u16,
+    origin: Option<u16>,
+    bytes: Vec<u8>,
+
+    /// Opcode picked for each instruction in the first pass, in order
+    opcodes: Vec<u8>,
+    instruction: usize,
+}
+
+impl Pass {
+    fn new(final_pass: bool) -> Self {
+        Pass {
+            final_pass,
+            labels: HashMap::new(),
+            pc: 0,
+            origin: None,
+            bytes: vec![],
+            opcodes: vec![],
+            instruction: 0,
+        }
+    }
+
+    fn run(&mut self, lines: &[(usize, String, Statement)]) -> Result<(), AsmError> {
+        for (line, scope, statement) in lines {
+            self.statement(scope, statement).map_err(|message| AsmError { line: *line, message })?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, scope: &str, statement: &Statement) -> Result<(), String> {
+        match statement {
+            Statement::Label(name) => self.define(scope, name, self.pc as i64),
+            Statement::Constant(name, val) => match self.eval(val, scope)? {
+                Some(val) => self.define(scope, name, val),
+                None => Ok(()),
+            },
+            Statement::Org(addr) => {
+                let addr = self.eval(addr, scope)?.ok_or(".org needs a known address")?;
+                let addr = u16::try_from(addr).map_err(|_| format!(".org address {} out of range", addr))?;
+
+                match self.origin {
+                    Some(_) if addr < self.pc => return Err(format!(".org ${:04X} moves backwards", addr)),
+                    Some(_) => {
+                        while self.pc < addr {
+                            self.emit(0);
+                        }
+                    },
+                    None => self.pc = addr,
+                }
+                Ok(())
+            },
+            Statement::Bytes(args) => {
+                for arg in args {
+                    if let Some(text) = arg.strip_prefix('"').and_then(|arg| arg.strip_suffix('"')) {
+                        text.bytes().for_each(|byte| self.emit(byte));
+                    } else {
+                        let val = self.eval(arg, scope)?;
+                        self.emit(self.byte(val)?);
+                    }
+                }
+                Ok(())
+            },
+            Statement::Words(args) => {
+                for arg in args {
+                    let val = self.word(self.eval(arg, scope)?)?;
+                    val.to_le_bytes().into_iter().for_each(|byte| self.emit(byte));
+                }
+                Ok(())
+            },
+            Statement::Reserve(len, fill) => {
+                let len = self.eval(len, scope)?.ok_or(".res needs a known size")?;
+                let fill = match fill {
+                    Some(fill) => self.byte(self.eval(fill, scope)?)?,
+                    None => 0,
+                };
+                for _ in 0 .. len {
+                    self.emit(fill);
+                }
+                Ok(())
+            },
+            Statement::Instruction(mnemonic, operand) => self.instruction(scope, mnemonic, operand),
+        }
+    }
+
+    fn instruction(&mut self, scope: &str, mnemonic: &str, operand: &str) -> Result<(), String> {
+        let syntax = parse_operand(operand);
+        let (expr, forced) = match syntax {
+            Syntax::Implied | Syntax::Accumulator => (None, false),
+            Syntax::Immediate(val) | Syntax::Indirect(val) | Syntax::IndirectX(val) | Syntax::IndirectY(val) => {
+                (Some(val), false)
+            },
+            Syntax::Direct(val) | Syntax::IndexedX(val) | Syntax::IndexedY(val) => match val.strip_prefix("a:") {
+                Some(val) => (Some(val), true),
+                None => (Some(val), false),
+            },
+        };
+        let val = match expr {
+            Some(expr) => self.eval(expr, scope)?,
+            None => None,
+        };
+
+        let opcode = if self.final_pass {
+            self.opcodes[self.instruction]
+        } else {
+            let zero_page = !forced && matches!(val, Some(0 ..= 0xFF));
+            let candidates = match syntax {
+                Syntax::Implied => vec![Operand::Implied, Operand::Accumulator],
+                Syntax::Accumulator => vec![Operand::Accumulator],
+                Syntax::Immediate(_) => vec![Operand::Immediate(0)],
+                Syntax::Direct(_) if zero_page => vec![Operand::Relative(0), Operand::ZeroPage(0), Operand::Absolute(0)],
+                Syntax::Direct(_) => vec![Operand::Relative(0), Operand::Absolute(0)],
+                Syntax::IndexedX(_) if zero_page => vec![Operand::ZeroPageX(0), Operand::AbsoluteX(0)],
+                Syntax::IndexedX(_) => vec![Operand::AbsoluteX(0)],
+                Syntax::IndexedY(_) if zero_page => vec![Operand::ZeroPageY(0), Operand::AbsoluteY(0)],
+                Syntax::IndexedY(_) => vec![Operand::AbsoluteY(0)],
+                Syntax::Indirect(_) => vec![Operand::Indirect(0)],
+                Syntax::IndirectX(_) => vec![Operand::IndirectX(0)],
+                Syntax::IndirectY(_) => vec![Operand::IndirectY(0)],
+            };
+
+            // unofficial opcodes can be written with or without the "*" of the disassembler
+            let unofficial = format!("*{}", mnemonic.trim_start_matches('*'));
+            let opcode = [mnemonic, unofficial.as_str()]
+                .iter()
+                .find_map(|name| {
+                    candidates
+                        .iter()
+                        .find_map(|operand| OPCODES.get(&(name.to_string(), discriminant(operand))))
+                })
+                .ok_or(format!("invalid addressing mode for {}", mnemonic))?;
+            self.opcodes.push(*opcode);
+            *opcode
+        };
+        self.instruction += 1;
+
+        let decoded = disassemble_one(&[opcode, 0, 0], 0, 0);
+        self.emit(opcode);
+
+        match (INSTRUCTION_MAP[&opcode].bytes, decoded.operand) {
+            (2, Operand::Relative(_)) => {
+                let offset = val.map(|val| val - (self.pc as i64 + 1));
+                match offset {
+                    Some(offset) if self.final_pass && !(-128 ..= 127).contains(&offset) => {
+                        return Err(format!("branch target is {} bytes away", offset));
+                    },
+                    _ => self.emit(offset.unwrap_or(0) as u8),
+                }
+            },
+            (2, _) => self.emit(self.byte(val)?),
+            (3, _) => {
+                let val = self.word(val)?;
+                val.to_le_bytes().into_iter().for_each(|byte| self.emit(byte));
+            },
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    fn define(&mut self, scope: &str, name: &str, val: i64) -> Result<(), String> {
+        let name = qualify(scope, name);
+        if !self.final_pass && self.labels.contains_key(&name) {
+            return Err(format!("{} is already defined", name));
+        }
+        self.labels.insert(name, val);
+        Ok(())
+    }
+
+    fn emit(&mut self, byte: u8) {
+        if self.origin.is_none() {
+            self.origin = Some(self.pc);
+        }
+        self.bytes.push(byte);
+        self.pc = self.pc.wrapping_add(1);
+    }
+
+    /// Function that checks a value fits in a byte, unknown values only occur in the first pass
+    fn byte(&self, val: Option<i64>) -> Result<u8, String> {
+        match val {
+            Some(val) if !(-128 ..= 0xFF).contains(&val) => Err(format!("value {} doesn't fit in a byte", val)),
+            val => Ok(val.unwrap_or(0) as u8),
+        }
+    }
+
+    fn word(&self, val: Option<i64>) -> Result<u16, String> {
+        match val {
+            Some(val) if !(-0x8000 ..= 0xFFFF).contains(&val) => Err(format!("value {} doesn't fit in a word", val)),
+            val => Ok(val.unwrap_or(0) as u16),
+        }
+    }
+
+    /// Function that evaluates an expression, returns None for unknown symbols in the first pass
+    fn eval(&self, text: &str, scope: &str) -> Result<Option<i64>, String> {
+        let mut parser = Parser {
+            chars: text.chars().filter(|c| !c.is_whitespace()).collect(),
+            pos: 0,
+            pass: self,
+            scope,
+            unknown: None,
+        };
+
+        let val = parser.or()?;
+        if parser.pos < parser.chars.len() {
+            return Err(format!("unexpected {} in {}", parser.chars[parser.pos], text));
+        }
+
+        match parser.unknown {
+            Some(name) if self.final_pass => Err(format!("unknown symbol {}", name)),
+            Some(_) => Ok(None),
+            None => Ok(Some(val)),
+        }
+    }
+}
+
+/// Function that gives local labels the name of their scope
+fn qualify(scope: &str, name: &str) -> String {
+    if name.starts_with('@') { format!("{}{}", scope, name) } else { name.to_string() }
+}
+
+/// Recursive descent parser of expressions, from the lowest precedence:
+/// | ^ & << >> + - * / and the unary - ~ < (low byte) > (high byte)
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    pass: &'a Pass,
+    scope: &'a str,
+
+    /// First symbol that isn't defined yet
+    unknown: Option<String>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let matches = text.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c));
+        if matches {
+            self.pos += text.chars().count();
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Result<i64, String> {
+        let mut val = self.xor()?;
+        while self.eat("|") {
+            val |= self.xor()?;
+        }
+        Ok(val)
+    }
+
+    fn xor(&mut self) -> Result<i64, String> {
+        let mut val = self.and()?;
+        while self.eat("^") {
+            val ^= self.and()?;
+        }
+        Ok(val)
+    }
+
+    fn and(&mut self) -> Result<i64, String> {
+        let mut val = self.shift()?;
+        while self.eat("&") {
+            val &= self.shift()?;
+        }
+        Ok(val)
+    }
+
+    fn shift(&mut self) -> Result<i64, String> {
+        let mut val = self.sum()?;
+        loop {
+            if self.eat("<<") {
+                val <<= self.sum()? & 63;
+            } else if self.eat(">>") {
+                val >>= self.sum()? & 63;
+            } else {
+                return Ok(val);
+            }
+        }
+    }
+
+    fn sum(&mut self) -> Result<i64, String> {
+        let mut val = self.product()?;
+        loop {
+            if self.eat("+") {
+                val += self.product()?;
+            } else if self.eat("-") {
+                val -= self.product()?;
+            } else {
+                return Ok(val);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<i64, String> {
+        let mut val = self.unary()?;
+        loop {
+            if self.eat("*") {
+                val *= self.unary()?;
+            } else if self.eat("/") {
+                let divisor = self.unary()?;
+                if divisor == 0 && self.unknown.is_none() {
+                    return Err("division by zero".to_string());
+                }
+                val = val.checked_div(divisor).unwrap_or(0);
+            } else {
+                return Ok(val);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(-self.unary()?)
+            },
+            Some('~') => {
+                self.pos += 1;
+                Ok(!self.unary()?)
+            },
+            Some('<') => {
+                self.pos += 1;
+                Ok(self.unary()? & 0xFF)
+            },
+            Some('>') => {
+                self.pos += 1;
+                Ok((self.unary()? >> 8) & 0xFF)
+            },
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        let start = self.pos;
+
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let val = self.or()?;
+                if !self.eat(")") {
+                    return Err("missing )".to_string());
+                }
+                Ok(val)
+            },
+            Some('*') => {
+                self.pos += 1;
+                Ok(self.pass.pc as i64)
+            },
+            Some('\'') => match (self.chars.get(self.pos + 1), self.chars.get(self.pos + 2)) {
+                (Some(c), Some('\'')) => {
+                    self.pos += 3;
+                    Ok(*c as i64)
+                },
+                _ => Err("invalid character literal".to_string()),
+            },
+            Some('$') | Some('%') => {
+                let radix = if self.peek() == Some('$') { 16 } else { 2 };
+                self.pos += 1;
+                let digits = self.take_while(|c| c.is_ascii_alphanumeric());
+                i64::from_str_radix(&digits, radix).map_err(|_| format!("invalid number {}", &self.text_from(start)))
+            },
+            Some(c) if c.is_ascii_digit() => {
+                let digits = self.take_while(|c| c.is_ascii_alphanumeric());
+                digits.parse().map_err(|_| format!("invalid number {}", digits))
+            },
+            Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '@' => {
+                self.pos += 1;
+                let rest = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                let name = qualify(self.scope, &format!("{}{}", c, rest));
+
+                match self.pass.labels.get(&name) {
+                    Some(val) => Ok(*val),
+                    None => {
+                        self.unknown.get_or_insert(name);
+                        Ok(0)
+                    },
+                }
+            },
+            Some(c) => Err(format!("unexpected {}", c)),
+            None => Err("missing value".to_string()),
+        }
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&pred) {
+            self.pos += 1;
+        }
+        self.text_from(start)
+    }
+
+    fn text_from(&self, start: usize) -> String {
+        self.chars[start .. self.pos].iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disassembler::{disassemble, Symbols};
+
+    #[test]
+    fn test_assemble() {
+        let asm = assemble(
+            "
+            PPUCTRL = $2000
+            .org $C000
+            reset:  lda #<message   ; low byte
+                    ldx #0
+            @loop:  sta PPUCTRL,x
+                    inx
+                    bne @loop
+                    jmp (vector)
+            vector: .word reset, *
+            message: .byte \"Hi\", 1+2*3, >message
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(asm.origin, 0xC000);
+        assert_eq!(asm.label("reset@loop"), Some(0xC004));
+        assert_eq!(asm.label("message"), Some(0xC011));
+        assert_eq!(asm.bytes, vec![
+            0xA9, 0x11, 0xA2, 0x00, 0x9D, 0x00, 0x20, 0xE8, 0xD0, 0xFA, 0x6C, 0x0D, 0xC0,
+            0x00, 0xC0, 0x0F, 0xC0, b'H', b'i', 7, 0xC0,
+        ]);
+    }
+
+    #[test]
+    fn test_addressing_sizes() {
+        let asm = assemble(".org $10\nlda data\nlda a:$10\nldx $10,y\nasl\nrol a\ndata: .res 2, $FF").unwrap();
+
+        // forward references are assembled as absolute addresses
+        assert_eq!(asm.bytes, vec![0xAD, 0x1A, 0x00, 0xAD, 0x10, 0x00, 0xB6, 0x10, 0x0A, 0x2A, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_every_opcode_round_trips() {
+        for ins in INSTRUCTIONS.iter() {
+            let bytes = [ins.address, 0x34, 0x12];
+            let decoded = disassemble_one(&bytes, 0, 0x8000);
+            let source = format!(".org $8000\n{}", decoded.format(&Symbols::default()));
+
+            // unofficial duplicates assemble to the first opcode with the same mnemonic and addressing
+            let asm = assemble(&source).unwrap();
+            let redecoded = disassemble(&asm.bytes, 0x8000);
+            assert_eq!(redecoded[0].format(&Symbols::default()), decoded.format(&Symbols::default()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(assemble("nop\nstx $1234,y").unwrap_err().line, 2);
+        assert!(assemble("beq far\n.res 200\nfar: nop").unwrap_err().message.contains("bytes away"));
+        assert!(assemble("jmp nowhere").unwrap_err().message.contains("unknown symbol"));
+        assert!(assemble("a: nop\na: nop").unwrap_err().message.contains("already defined"));
+        assert!(assemble("lda #$100").is_err());
+    }
+}
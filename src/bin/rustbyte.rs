@@ -0,0 +1,414 @@
+// Command line tools that run without a window
+
+use rust_byte::assembler::assemble;
+use rust_byte::blargg::{format_table, run_test_rom, TestStatus, DEFAULT_FRAMES};
+use rust_byte::cpu::bus::Bus;
+use rust_byte::cpu::cpu::CPU;
+use rust_byte::debugger::engine::Debugger;
+use rust_byte::debugger::gdb::GdbStub;
+use rust_byte::debugger::repl::Repl;
+use rust_byte::disassembler::{disassemble, disassemble_reachable, Disassembled, Symbols};
+use rust_byte::gamedb::EMBEDDED;
+use rust_byte::movie::Movie;
+use rust_byte::patch::load_patched;
+use rust_byte::ppu::cartridge::{Cartridge, HeaderFormat};
+use rust_byte::ppu::ppu::PPU;
+use rust_byte::render::input::joypad::Joypad;
+use rust_byte::render::viewer::{debug_view, oam_table};
+use rust_byte::rom_info::RomInfo;
+use rust_byte::rom_source::RomSource;
+use rust_byte::verify::{compare, parse_log, run_movie, to_log};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage:
+  rustbyte debug <rom>
+      runs the game without a window, stopped in the debugger
+  rustbyte disasm <rom> [symbols] [--linear]
+      disassembles the PRG banks, following the code from the reset, NMI and IRQ vectors,
+      symbols are FCEUX .nl or ld65 -Ln label files, --linear disassembles every byte
+  rustbyte info <rom> [--json] [--gamedb <file>]
+      prints the header format, mapper, sizes, hashes, game database match and header problems,
+      --json prints them as a JSON object
+  rustbyte patch <rom> <source.s> <output>
+      assembles the source and writes it into the last PRG bank at the .org addresses
+  rustbyte gdb <rom> [port]
+      runs the game without a window and waits for GDB on 127.0.0.1 (default port 1234)
+  rustbyte ppu <rom> <output.ppm> [--frames <n>] [--palette <0-7>]
+      runs the game for n frames (default 60) and writes the pattern tables, nametables,
+      sprites and palettes as a PPM image, the sprite attributes are printed
+  rustbyte test <rom>... [--frames <n>]
+      runs test ROMs that report through $6000 (blargg's protocol) and prints a summary,
+      each ROM stops after n frames (default 3600)
+  rustbyte verify <rom> <movie.fm2> <hashes.log> [--update]
+      plays the movie and compares every frame with the hash log,
+      the log is created when it doesn't exist or --update is given";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let res = match args.first().map(|arg| arg.as_str()) {
+        Some("debug") => debug(&args[1..]),
+        Some("disasm") => disasm(&args[1..]),
+        Some("info") => info(&args[1..]),
+        Some("patch") => patch(&args[1..]),
+        Some("gdb") => gdb(&args[1..]),
+        Some("ppu") => ppu(&args[1..]),
+        Some("test") => test(&args[1..]),
+        Some("verify") => verify(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match res {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::from(2)
+        },
+    }
+}
+
+/// Function that loads a cartridge to run it, its mapper has to be implemented
+fn load_rom(path: &str) -> Result<Cartridge, String> {
+    let cartridge = read_rom(path)?;
+    cartridge.check_mapper().map_err(|err| format!("Failed to load {}: {}", path, err))?;
+    Ok(cartridge)
+}
+
+/// Function that loads a cartridge from a file, applying a patch found next to it
+/// and correcting the header by the game database
+fn read_rom(path: &str) -> Result<Cartridge, String> {
+    let source = RomSource::new(path, None);
+    let (bytes, patch) = load_patched(&source, None).or_else(|err| {
+        let bytes = source.read()?;
+        eprintln!("Warning: {}, loading the ROM without the patch", err);
+        Ok::<_, String>((bytes, None))
+    })?;
+    if let Some(patch) = patch {
+        eprintln!("Applied patch {}", patch.display());
+    }
+    let cartridge = Cartridge::parse(bytes, &EMBEDDED).map_err(|err| format!("Failed to load {}: {}", path, err))?;
+    for correction in &cartridge.corrections {
+        eprintln!("Corrected header {}", correction);
+    }
+    Ok(cartridge)
+}
+
+/// Function that runs a game headlessly under the debugger
+fn debug(args: &[String]) -> Result<ExitCode, String> {
+    let [rom] = args else {
+        return Err(USAGE.to_string());
+    };
+
+    let bus = Bus::new(load_rom(rom)?, |_ppu: &PPU, _joy: &mut Joypad| {});
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+
+    let mut debugger = Debugger::new();
+    debugger.pause();
+    let mut repl = Repl::stdio(debugger);
+
+    cpu.interpret_callback(|cpu| {
+        if !repl.hook(cpu) {
+            std::process::exit(0);
+        }
+    });
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Function that prints the disassembly of every PRG bank
+fn disasm(args: &[String]) -> Result<ExitCode, String> {
+    let linear = args.iter().any(|arg| arg == "--linear");
+    let paths: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+
+    let (rom, symbols) = match paths[..] {
+        [rom] => (rom, Symbols::default()),
+        [rom, symbols] => {
+            let text = std::fs::read_to_string(symbols).map_err(|err| format!("Failed to read {}: {}", symbols, err))?;
+            (rom, Symbols::parse(&text))
+        },
+        _ => return Err(USAGE.to_string()),
+    };
+
+    // only the ROM is read, so the mapper doesn't have to be implemented
+    let cartridge = read_rom(rom)?;
+    if cartridge.prg_rom.is_empty() {
+        return Err(format!("{} has no PRG-ROM to disassemble", rom));
+    }
+
+    // NROM maps the whole PRG below $FFFF, larger ROMs are split into 16KB banks with the last one fixed at $C000
+    let banks: Vec<&[u8]> = if cartridge.prg_rom.len() <= 0x8000 {
+        vec![&cartridge.prg_rom[..]]
+    } else {
+        cartridge.prg_rom.chunks(0x4000).collect()
+    };
+
+    for (i, bank) in banks.iter().enumerate() {
+        let last = i + 1 == banks.len();
+        let base = if last { (0x10000 - bank.len()) as u16 } else { 0x8000 };
+        let mut symbols = symbols.clone();
+
+        let code = if linear || !last {
+            disassemble(bank, base)
+        } else {
+            // a bank smaller than the vectors only has the ones it holds
+            let vector = |name: &'static str, addr: u16| {
+                let offset = addr.checked_sub(base)? as usize;
+                Some((name, u16::from_le_bytes([*bank.get(offset)?, *bank.get(offset + 1)?])))
+            };
+            let entries: Vec<(&str, u16)> =
+                [vector("NMI", 0xFFFA), vector("Reset", 0xFFFC), vector("IRQ", 0xFFFE)].into_iter().flatten().collect();
+
+            for &(name, addr) in &entries {
+                if symbols.get(addr).is_none() {
+                    symbols.insert(addr, name);
+                }
+            }
+
+            let entries: Vec<u16> = entries.iter().map(|(_, addr)| *addr).collect();
+            disassemble_reachable(bank, base, &entries)
+        };
+
+        println!("; bank {} at ${:04X}", i, base);
+        print_listing(&code, &mut symbols);
+        println!();
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Function that prints instructions with labels on the jump targets and notes on skipped bytes
+fn print_listing(code: &[Disassembled], symbols: &mut Symbols) {
+    for ins in code {
+        if let Some(target) = ins.target() {
+            if symbols.get(target).is_none() && code.iter().any(|other| other.addr == target) {
+                symbols.insert(target, &format!("L{:04X}", target));
+            }
+        }
+    }
+
+    let mut next = code.first().map(|ins| ins.addr);
+    for ins in code {
+        if let Some(expected) = next {
+            if ins.addr > expected {
+                println!("; ${:04X}-${:04X} not reached ({} bytes)", expected, ins.addr - 1, ins.addr - expected);
+            }
+        }
+        next = ins.addr.checked_add(ins.len() as u16);
+
+        if let Some(label) = symbols.get(ins.addr) {
+            println!("{}:", label);
+        }
+
+        let bytes: Vec<String> = ins.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        println!("  {:04X}  {:8}  {}", ins.addr, bytes.join(" "), ins.format(symbols));
+    }
+}
+
+/// Function that prints everything known about a ROM file
+fn info(args: &[String]) -> Result<ExitCode, String> {
+    let mut json = false;
+    let mut db = EMBEDDED.clone();
+    let mut paths = vec![];
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--gamedb" => {
+                let file = iter.next().ok_or(USAGE.to_string())?;
+                let text = std::fs::read_to_string(file).map_err(|err| format!("Failed to read {}: {}", file, err))?;
+                db.extend(&text).map_err(|err| format!("{}: {}", file, err))?;
+            },
+            _ => paths.push(arg),
+        }
+    }
+
+    let [rom] = paths[..] else {
+        return Err(USAGE.to_string());
+    };
+
+    // the file as it is, without a patch, so that its own header is inspected
+    let data = RomSource::new(rom, None).read()?;
+    let info = RomInfo::new(&data, &db).map_err(|err| format!("Failed to load {}: {}", rom, err))?;
+
+    if json {
+        print!("{}", info.to_json());
+    } else {
+        println!("File:      {}", rom);
+        print!("{}", info.to_text());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Function that assembles a patch into a copy of the ROM
+fn patch(args: &[String]) -> Result<ExitCode, String> {
+    let [rom, source, output] = args else {
+        return Err(USAGE.to_string());
+    };
+
+    // writing over the input would lose the original ROM the patch applies to
+    let same = |a: &str, b: &str| match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    };
+    if same(rom, output) {
+        return Err(format!("Refusing to overwrite {}, write the patched ROM to another file", rom));
+    }
+
+    let mut bytes = std::fs::read(rom).map_err(|err| format!("Failed to read {}: {}", rom, err))?;
+    let cartridge = Cartridge::parse(bytes.clone(), &EMBEDDED).map_err(|err| format!("Failed to load {}: {}", rom, err))?;
+    if cartridge.format == HeaderFormat::Unif {
+        return Err(format!("{} is a UNIF file, only iNES files can be patched", rom));
+    }
+
+    let text = std::fs::read_to_string(source).map_err(|err| format!("Failed to read {}: {}", source, err))?;
+    let asm = assemble(&text).map_err(|err| format!("{}:{}", source, err))?;
+
+    // the PRG follows the header and the optional trainer, the last bank (or all of NROM) ends at $FFFF
+    let start = 16 + cartridge.trainer.as_ref().map_or(0, |trainer| trainer.len());
+    let end = start + cartridge.prg_rom.len();
+    let len = cartridge.prg_rom.len().min(0x8000);
+    let prg = &mut bytes[end - len .. end];
+
+    asm.patch(prg, (0x10000 - len) as u16).map_err(|err| format!("{}: {}", source, err))?;
+    std::fs::write(output, &bytes).map_err(|err| format!("Failed to write {}: {}", output, err))?;
+    println!("Patched {} bytes at ${:04X}", asm.bytes.len(), asm.origin);
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Function that runs a game headlessly under a GDB remote stub
+fn gdb(args: &[String]) -> Result<ExitCode, String> {
+    let (rom, port) = match args {
+        [rom] => (rom, "1234"),
+        [rom, port] => (rom, port.as_str()),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let bus = Bus::new(load_rom(rom)?, |_ppu: &PPU, _joy: &mut Joypad| {});
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+
+    println!("Waiting for GDB on 127.0.0.1:{}", port);
+    let mut stub = GdbStub::listen(format!("127.0.0.1:{}", port)).map_err(|err| format!("GDB connection failed: {}", err))?;
+
+    cpu.interpret_callback(|cpu| {
+        if !stub.hook(cpu) {
+            std::process::exit(0);
+        }
+    });
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Function that runs a game headlessly and exports the PPU debug view
+fn ppu(args: &[String]) -> Result<ExitCode, String> {
+    let mut frames = 60;
+    let mut palette = 0;
+    let mut paths = vec![];
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--frames" => frames = iter.next().and_then(|val| val.parse().ok()).ok_or(USAGE.to_string())?,
+            "--palette" => palette = iter.next().and_then(|val| val.parse().ok()).filter(|val| *val < 8).ok_or(USAGE.to_string())?,
+            _ => paths.push(arg),
+        }
+    }
+
+    let [rom, output] = paths[..] else {
+        return Err(USAGE.to_string());
+    };
+
+    let bus = Bus::new(load_rom(rom)?, |_ppu: &PPU, _joy: &mut Joypad| {});
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+
+    while cpu.bus.ppu().frame() < frames {
+        cpu.poll_interrupts();
+        if !cpu.step() {
+            eprintln!("CPU stopped on unknown opcode at ${:04X}", cpu.prog_counter.wrapping_sub(1));
+            break;
+        }
+    }
+
+    let view = debug_view(cpu.bus.ppu(), palette);
+    std::fs::write(output, view.to_ppm()).map_err(|err| format!("Failed to write {}: {}", output, err))?;
+
+    print!("{}", oam_table(cpu.bus.ppu()));
+    println!("Wrote {}x{} image after {} frames to {}", view.width, view.height, cpu.bus.ppu().frame(), output);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Function that runs test ROMs headlessly and prints their results
+fn test(args: &[String]) -> Result<ExitCode, String> {
+    let mut frames = DEFAULT_FRAMES;
+    let mut roms = vec![];
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--frames" {
+            frames = iter.next().and_then(|val| val.parse().ok()).ok_or(USAGE.to_string())?;
+        } else {
+            roms.push(arg);
+        }
+    }
+
+    if roms.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let mut results = vec![];
+    for rom in roms {
+        let cartridge = load_rom(rom)?;
+        let name = std::path::Path::new(rom).file_name().map_or(rom.to_string(), |name| name.to_string_lossy().to_string());
+        results.push((name, run_test_rom(cartridge, frames)));
+    }
+
+    println!("{}", format_table(&results));
+
+    if results.iter().all(|(_, result)| result.status == TestStatus::Passed) {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// Function that plays a movie headlessly and checks it against a hash log
+fn verify(args: &[String]) -> Result<ExitCode, String> {
+    let update = args.iter().any(|arg| arg == "--update");
+    let paths: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let [rom, movie, log] = paths[..] else {
+        return Err(USAGE.to_string());
+    };
+
+    let cartridge = load_rom(rom)?;
+    let movie = Movie::load(movie)?;
+
+    if movie.rom_checksum != Movie::rom_checksum(&cartridge) {
+        eprintln!("Warning: the movie was recorded with a different ROM");
+    }
+
+    let actual = run_movie(cartridge, movie).map_err(|err| format!("Failed to load the movie save state: {}", err))?;
+
+    if update || !std::path::Path::new(log).exists() {
+        std::fs::write(log, to_log(&actual)).map_err(|err| format!("Failed to write {}: {}", log, err))?;
+        println!("Wrote {} frame hashes to {}", actual.len(), log);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let text = std::fs::read_to_string(log).map_err(|err| format!("Failed to read {}: {}", log, err))?;
+    let expected = parse_log(&text).map_err(|line| format!("Invalid hash log {} on line {}", log, line))?;
+
+    match compare(&expected, &actual) {
+        Some(divergence) => {
+            println!("{}", divergence);
+            Ok(ExitCode::FAILURE)
+        },
+        None => {
+            println!("{} frames match", actual.len());
+            Ok(ExitCode::SUCCESS)
+        },
+    }
+}
@@ -0,0 +1,136 @@
+// https://github.com/christopherpow/nes-test-roms/blob/master/instr_test-v5/readme.txt
+//
+// Runner for test ROMs that report through cartridge RAM (blargg's protocol)
+// [0x6000] => status, 0x80 while running, 0x81 when a reset is requested, the result code otherwise
+// [0x6001 - 0x6003] => DE B0 61 once the status is valid
+// [0x6004 - ...] => zero terminated text output
+
+use crate::cpu::bus::Bus;
+use crate::cpu::cpu::CPU;
+use crate::ppu::cartridge::Cartridge;
+use crate::ppu::ppu::PPU;
+use crate::render::input::joypad::Joypad;
+use std::fmt::{Display, Formatter};
+
+/// Bytes at $6001 marking the status as valid
+const MAGIC: [u8; 3] = [0xDE, 0xB0, 0x61];
+
+const RUNNING: u8 = 0x80;
+const RESET_REQUESTED: u8 = 0x81;
+
+/// Frames to wait before pressing reset, the ROMs ask for at least 100 ms
+const RESET_DELAY: u64 = 6;
+
+/// Default time limit, the slowest ROMs take about 20 seconds
+pub const DEFAULT_FRAMES: u64 = 60 * 60;
+
+/// Outcome of a test ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestStatus {
+    Passed,
+
+    /// Result code written by the ROM
+    Failed(u8),
+
+    /// The ROM didn't finish in time
+    Timeout,
+
+    /// The CPU hit an opcode it doesn't know
+    Crashed(u16),
+}
+
+impl Display for TestStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestStatus::Passed => write!(f, "passed"),
+            TestStatus::Failed(code) => write!(f, "failed ({})", code),
+            TestStatus::Timeout => write!(f, "timeout"),
+            TestStatus::Crashed(pc) => write!(f, "crashed at ${:04X}", pc),
+        }
+    }
+}
+
+/// Class representing the result of a test ROM with its text output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    pub status: TestStatus,
+    pub message: String,
+    pub frames: u64,
+}
+
+/// Function that runs a test ROM headlessly until it reports a result or the frame limit is reached
+pub fn run_test_rom(cartridge: Cartridge, max_frames: u64) -> TestResult {
+    let bus = Bus::new(cartridge, |_ppu: &PPU, _joy: &mut Joypad| {});
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+
+    let mut reset_at = None;
+    let mut status = TestStatus::Timeout;
+
+    while cpu.bus.ppu().frame() < max_frames {
+        cpu.poll_interrupts();
+        if !cpu.step() {
+            status = TestStatus::Crashed(cpu.prog_counter.wrapping_sub(1));
+            break;
+        }
+
+        let ram = cpu.bus.prg_ram();
+        if ram[1 .. 4] != MAGIC {
+            continue;
+        }
+
+        let frame = cpu.bus.ppu().frame();
+        match ram[0] {
+            RUNNING => reset_at = None,
+            RESET_REQUESTED => match reset_at {
+                None => reset_at = Some(frame + RESET_DELAY),
+                Some(at) if frame >= at => {
+                    cpu.reset();
+                    reset_at = Some(u64::MAX);
+                },
+                Some(_) => {},
+            },
+            0 => {
+                status = TestStatus::Passed;
+                break;
+            },
+            code => {
+                status = TestStatus::Failed(code);
+                break;
+            },
+        }
+    }
+
+    TestResult {
+        status,
+        message: message(cpu.bus.prg_ram()),
+        frames: cpu.bus.ppu().frame(),
+    }
+}
+
+/// Function that reads the zero terminated text at $6004
+fn message(ram: &[u8]) -> String {
+    if ram[1 .. 4] != MAGIC {
+        return String::new();
+    }
+
+    let text: Vec<u8> = ram[4 ..].iter().take_while(|byte| **byte != 0).copied().collect();
+    String::from_utf8_lossy(&text).trim().to_string()
+}
+
+/// Function that formats the results as a table, the message is reduced to its last line
+pub fn format_table(results: &[(String, TestResult)]) -> String {
+    let width = results.iter().map(|(name, _)| name.len()).chain(["ROM".len()]).max().unwrap_or(0);
+    let mut res = format!("{:width$}  {:18}  {}\n", "ROM", "Result", "Message", width = width);
+
+    for (name, result) in results {
+        let message = result.message.lines().last().unwrap_or("");
+        let row = format!("{:width$}  {:18}  {}", name, result.status.to_string(), message, width = width);
+        res.push_str(row.trim_end());
+        res.push('\n');
+    }
+
+    let passed = results.iter().filter(|(_, result)| result.status == TestStatus::Passed).count();
+    res.push_str(&format!("{} of {} passed", passed, results.len()));
+    res
+}
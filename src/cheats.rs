@@ -0,0 +1,242 @@
+// https://www.nesdev.org/wiki/Game_Genie
+//
+// Game Genie codes patch what the CPU reads from the cartridge, the ROM itself is left untouched
+// [6 letters] => address + value
+// [8 letters] => address + value + compare, the value is only replaced when the ROM holds the compare byte
+//
+// RAM cheats (Pro Action Replay style, "AAAA:VV" or "AAAAVV") write the value into RAM every frame
+//
+// Neither kind is part of a save state, Game Genie patches never reach memory and RAM cheats are
+// written again on the next frame after a state is loaded
+
+use std::fmt::{Display, Formatter};
+
+/// Game Genie letters, the position of a letter is its 4-bit value
+const LETTERS: &str = "APZLGITYEOXUKSVN";
+
+/// Directory the cheats of every game are stored in, one file per ROM hash
+pub const CHEATS_DIR: &str = "cheats";
+
+/// What a cheat does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatKind {
+    /// Replaces a byte read from the cartridge ($8000 - $FFFF)
+    GameGenie { addr: u16, value: u8, compare: Option<u8> },
+
+    /// Writes a byte into CPU RAM or cartridge RAM once per frame
+    Ram { addr: u16, value: u8 },
+}
+
+impl CheatKind {
+    /// Function that decodes a Game Genie or RAM cheat code
+    pub fn parse(code: &str) -> Result<CheatKind, String> {
+        let code = code.trim().to_uppercase();
+
+        if let Some((addr, value)) = code.split_once(':') {
+            let addr = u16::from_str_radix(addr, 16).map_err(|_| format!("invalid address in {}", code))?;
+            let value = u8::from_str_radix(value, 16).map_err(|_| format!("invalid value in {}", code))?;
+            return CheatKind::ram(addr, value);
+        }
+
+        // Game Genie codes are only letters, RAM codes have at least one digit
+        if code.len() == 6 && code.chars().all(|c| c.is_ascii_hexdigit()) && code.chars().any(|c| c.is_ascii_digit()) {
+            let addr = u16::from_str_radix(&code[.. 4], 16).unwrap();
+            let value = u8::from_str_radix(&code[4 ..], 16).unwrap();
+            return CheatKind::ram(addr, value);
+        }
+
+        let n: Vec<u16> = code
+            .chars()
+            .map(|c| LETTERS.find(c).map(|n| n as u16))
+            .collect::<Option<Vec<u16>>>()
+            .ok_or(format!("invalid cheat code {}", code))?;
+
+        if n.len() != 6 && n.len() != 8 {
+            return Err(format!("invalid cheat code {}, Game Genie codes have 6 or 8 letters", code));
+        }
+
+        let addr = 0x8000
+            | (n[3] & 7) << 12
+            | (n[5] & 7) << 8 | (n[4] & 8) << 8
+            | (n[2] & 7) << 4 | (n[1] & 8) << 4
+            | (n[4] & 7) | (n[3] & 8);
+
+        // the last letter holds the high bit of the value, or of the compare byte in 8 letter codes
+        let last = if n.len() == 6 { n[5] } else { n[7] };
+        let value = ((n[1] & 7) << 4 | (n[0] & 8) << 4 | (n[0] & 7) | (last & 8)) as u8;
+        let compare = (n.len() == 8).then(|| ((n[7] & 7) << 4 | (n[6] & 8) << 4 | (n[6] & 7) | (n[5] & 8)) as u8);
+
+        Ok(CheatKind::GameGenie { addr, value, compare })
+    }
+
+    fn ram(addr: u16, value: u8) -> Result<CheatKind, String> {
+        match addr {
+            0x0000 ..= 0x07FF | 0x6000 ..= 0x7FFF => Ok(CheatKind::Ram { addr, value }),
+            _ => Err(format!("${:04X} is not in CPU RAM or cartridge RAM", addr)),
+        }
+    }
+}
+
+impl Display for CheatKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheatKind::GameGenie { addr, value, compare: Some(compare) } => {
+                write!(f, "${:04X} = ${:02X} if ${:02X}", addr, value, compare)
+            },
+            CheatKind::GameGenie { addr, value, compare: None } => write!(f, "${:04X} = ${:02X}", addr, value),
+            CheatKind::Ram { addr, value } => write!(f, "${:04X} := ${:02X} every frame", addr, value),
+        }
+    }
+}
+
+/// Class representing a cheat as entered by the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub code: String,
+    pub name: String,
+    pub kind: CheatKind,
+    pub enabled: bool,
+}
+
+/// Class representing the cheats of a game
+#[derive(Debug, Clone, Default)]
+pub struct Cheats {
+    pub cheats: Vec<Cheat>,
+
+    /// Turns all of the cheats off without forgetting which ones are enabled
+    pub active: bool,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Cheats { cheats: vec![], active: true }
+    }
+
+    /// Path of the cheat file of a game
+    pub fn path(rom_hash: u32) -> String {
+        format!("{}/{:08X}.cht", CHEATS_DIR, rom_hash)
+    }
+
+    /// Function that parses a cheat file, one "on|off <code> [name]" per line
+    pub fn parse(text: &str) -> Result<Cheats, String> {
+        let mut cheats = Cheats::new();
+
+        for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let mut fields = line.trim().splitn(3, ' ');
+            let enabled = match fields.next() {
+                Some("on") => true,
+                Some("off") => false,
+                _ => return Err(format!("line {}: expected on or off", i + 1)),
+            };
+            let code = fields.next().ok_or(format!("line {}: missing code", i + 1))?;
+
+            let index = cheats.add(code, fields.next().unwrap_or("").trim()).map_err(|err| format!("line {}: {}", i + 1, err))?;
+            cheats.cheats[index].enabled = enabled;
+        }
+
+        Ok(cheats)
+    }
+
+    /// Function that writes the cheats into the cheat file of a game, returns its path
+    pub fn save(&self, rom_hash: u32) -> Result<String, String> {
+        let path = Cheats::path(rom_hash);
+        std::fs::create_dir_all(CHEATS_DIR)
+            .and_then(|_| std::fs::write(&path, self.to_text()))
+            .map_err(|err| format!("failed to write {}: {}", path, err))?;
+        Ok(path)
+    }
+
+    pub fn to_text(&self) -> String {
+        self.cheats
+            .iter()
+            .map(|cheat| format!("{} {} {}", if cheat.enabled { "on" } else { "off" }, cheat.code, cheat.name).trim_end().to_string() + "\n")
+            .collect()
+    }
+
+    /// Function that adds an enabled cheat and returns its index
+    pub fn add(&mut self, code: &str, name: &str) -> Result<usize, String> {
+        let kind = CheatKind::parse(code)?;
+        self.cheats.push(Cheat {
+            code: code.trim().to_uppercase(),
+            name: name.to_string(),
+            kind,
+            enabled: true,
+        });
+        Ok(self.cheats.len() - 1)
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &CheatKind> {
+        self.cheats.iter().filter(|cheat| self.active && cheat.enabled).map(|cheat| &cheat.kind)
+    }
+
+    /// Function that returns the byte the CPU sees when reading the cartridge
+    pub fn read(&self, addr: u16, value: u8) -> u8 {
+        for kind in self.enabled() {
+            if let CheatKind::GameGenie { addr: at, value: patched, compare } = *kind {
+                if at == addr && compare.is_none_or(|compare| compare == value) {
+                    return patched;
+                }
+            }
+        }
+        value
+    }
+
+    /// Function that returns the RAM writes to make at the start of a frame
+    pub fn ram_writes(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.enabled().filter_map(|kind| match *kind {
+            CheatKind::Ram { addr, value } => Some((addr, value)),
+            _ => None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_game_genie() {
+        // Super Mario Bros. infinite lives
+        assert_eq!(CheatKind::parse("SXIOPO"), Ok(CheatKind::GameGenie { addr: 0x91D9, value: 0xAD, compare: None }));
+        assert_eq!(CheatKind::parse("aaaaaaaa"), Ok(CheatKind::GameGenie { addr: 0x8000, value: 0, compare: Some(0) }));
+
+        match CheatKind::parse("ZEXPYGLA") {
+            Ok(CheatKind::GameGenie { compare: Some(_), .. }) => {},
+            other => panic!("expected an 8 letter code, got {:?}", other),
+        }
+
+        assert!(CheatKind::parse("SXIOP").is_err());
+        assert!(CheatKind::parse("SXIOPB").is_err());
+    }
+
+    #[test]
+    fn test_ram_codes() {
+        assert_eq!(CheatKind::parse("075A:09"), Ok(CheatKind::Ram { addr: 0x075A, value: 0x09 }));
+        assert_eq!(CheatKind::parse("600103"), Ok(CheatKind::Ram { addr: 0x6001, value: 0x03 }));
+        assert!(CheatKind::parse("2000:80").is_err());
+    }
+
+    #[test]
+    fn test_read_and_file() {
+        let mut cheats = Cheats::parse("on SXIOPO Infinite lives\n\noff 075A:09\n").unwrap();
+        cheats.add("AAAAAAAA", "").unwrap();
+
+        assert_eq!(cheats.read(0x91D9, 0xCE), 0xAD);
+        assert_eq!(cheats.read(0x8000, 0x01), 0x01);
+        assert_eq!(cheats.read(0x8000, 0x00), 0x00);
+        assert_eq!(cheats.ram_writes().count(), 0);
+
+        cheats.cheats[1].enabled = true;
+        assert_eq!(cheats.ram_writes().collect::<Vec<_>>(), vec![(0x075A, 0x09)]);
+
+        cheats.active = false;
+        assert_eq!(cheats.read(0x91D9, 0xCE), 0xCE);
+
+        assert_eq!(cheats.to_text(), "on SXIOPO Infinite lives\non 075A:09\non AAAAAAAA\n");
+        assert!(Cheats::parse("maybe SXIOPO").is_err());
+    }
+}
@@ -8,14 +8,37 @@
 // Special addresses
 // [0xFFFC - 0xFFFD] => Reset vector
 
+use crate::cheats::Cheats;
+use crate::hash::Crc32;
+use crate::movie::MovieSession;
 use crate::ppu::cartridge::Cartridge;
 use crate::ppu::ppu::PPU;
+use crate::region::Region;
 use crate::render::input::joypad::Joypad;
+use crate::state::{Savestate, StateError, StateReader, StateWriter};
+
+/// Address space of a memory access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    CPU,
+    PPU,
+}
+
+/// Memory access recorded for the debugger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub space: AddressSpace,
+    pub addr: u16,
+    pub write: bool,
+}
 
 pub struct Bus<'callback> {
     /// 2kB of RAM
     ram: [u8; 2048],
 
+    /// Cartridge RAM, at least 8kB, the first 8kB are at $6000 - $7FFF
+    prg_ram: Vec<u8>,
+
     /// Program ROM
     prg: Vec<u8>,
 
@@ -25,11 +48,33 @@ pub struct Bus<'callback> {
     /// Number of cycles
     pub cycles: usize,
 
+    /// Leftover fraction of a PPU dot (PAL runs 3.2 dots per CPU cycle)
+    ppu_remainder: usize,
+
     /// Game callback
     game: Box<dyn FnMut(&PPU, &mut Joypad) + 'callback>,
     
     /// Joypad 1
     joypad1: Joypad,
+
+    /// CRC-32 of the PRG and CHR ROM, identifies the game in save states
+    rom_hash: u32,
+
+    /// Movie being recorded or played back
+    movie: Option<MovieSession>,
+
+    /// Memory accesses since the last call to take_accesses, recorded only when tracking
+    accesses: Vec<MemoryAccess>,
+    track_accesses: bool,
+
+    /// Number of NMIs serviced since power on
+    nmi_count: u64,
+
+    /// Game Genie and RAM cheats, not part of the save state
+    cheats: Cheats,
+
+    /// Trainer of the cartridge, put into PRG-RAM at $7000 on power on
+    trainer: Option<Vec<u8>>,
 }
 
 /// Implementation of the Bus.
@@ -42,17 +87,109 @@ impl<'a> Bus<'a> {
     where
         F: FnMut(&PPU, &mut Joypad) + 'callback,
     {
-        let ppu = PPU::new(cartridge.chr_rom, cartridge.mirroring);
+        let mut rom_hash = Crc32::new();
+        rom_hash.update(&cartridge.prg_rom);
+        if !cartridge.chr_ram {
+            rom_hash.update(&cartridge.chr_rom);
+        }
+
+        let mut ppu = PPU::new(cartridge.chr_rom, cartridge.mirroring);
+        ppu.set_region(cartridge.region);
+        ppu.set_chr_ram(cartridge.chr_ram);
 
-        Bus {
+        let mut bus = Bus {
             ram: [0; 2048],
+            prg_ram: vec![0; cartridge.prg_ram_size.max(0x2000)],
             prg: cartridge.prg_rom,
             ppu,
             cycles: 0,
+            ppu_remainder: 0,
             game: Box::from(callback),
             joypad1: Joypad::default(),
+            rom_hash: rom_hash.finish(),
+            movie: None,
+            accesses: vec![],
+            track_accesses: false,
+            nmi_count: 0,
+            cheats: Cheats::new(),
+            trainer: cartridge.trainer,
+        };
+        bus.power_on_prg_ram();
+        bus
+    }
+
+    /// Function that fills PRG-RAM with its power on content
+    /// The trainer is in PRG-RAM at $7000 - $71FF on power on, as a copier would have left it
+    fn power_on_prg_ram(&mut self) {
+        self.prg_ram.fill(0);
+        if let Some(trainer) = &self.trainer {
+            self.prg_ram[0x1000 .. 0x1000 + trainer.len()].copy_from_slice(trainer);
         }
     }
+
+    /// Function that turns the console off and on again, clearing the memory of the console and the cartridge
+    pub fn power_cycle(&mut self) {
+        self.ram = [0; 2048];
+        self.power_on_prg_ram();
+        self.ppu.power_cycle();
+        self.ppu_remainder = 0;
+    }
+
+    /// Function that returns the PPU
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    /// Function that returns the PPU for editing its memory from the debugger
+    pub fn ppu_mut(&mut self) -> &mut PPU {
+        &mut self.ppu
+    }
+
+    /// Function that returns the 2kB of CPU RAM
+    pub fn ram(&self) -> &[u8; 2048] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8; 2048] {
+        &mut self.ram
+    }
+
+    /// Function that returns the 8kB of cartridge RAM mapped at $6000
+    pub fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    pub fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    /// CRC-32 of the PRG and CHR ROM of the loaded cartridge
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    /// Function that starts recording or playing back a movie
+    pub fn set_movie(&mut self, movie: MovieSession) {
+        self.movie = Some(movie);
+    }
+
+    /// Function that returns the current movie session
+    pub fn movie(&mut self) -> Option<&mut MovieSession> {
+        self.movie.as_mut()
+    }
+
+    /// Function that stops the current movie session and returns it
+    pub fn take_movie(&mut self) -> Option<MovieSession> {
+        self.movie.take()
+    }
     //
     // pub fn new<'callback>(cartridge: Cartridge) -> Bus<'callback> {
     //     let ppu = PPU::new(cartridge.chr_rom, cartridge.mirroring);
@@ -66,23 +203,103 @@ impl<'a> Bus<'a> {
     //     }
     // }
 
+    /// Function that overrides the region detected from the cartridge
+    pub fn set_region(&mut self, region: Region) {
+        self.ppu.set_region(region);
+        self.ppu_remainder = 0;
+    }
+
+    /// Function that returns the region the console is running in
+    pub fn region(&self) -> Region {
+        self.ppu.region()
+    }
+
     /// Function that ticks the bus, updating the number of cycles and the PPU
     pub fn tick(&mut self, cycles: u8) {
         // update cycles
         self.cycles += cycles as usize;
 
-        let nmi_before = self.ppu.nmi;
-        self.ppu.tick(cycles * 3);
-        let nmi_after = self.ppu.nmi;
+        // convert CPU cycles to PPU dots, keeping the fraction for the next tick
+        let (dots, per_cycles) = self.ppu.region().clock_ratio();
+        let total = cycles as usize * dots + self.ppu_remainder;
+        self.ppu_remainder = total % per_cycles;
+
+        self.ppu.tick((total / per_cycles) as u8);
+
+        // once per frame, also while the game has NMI turned off
+        if self.ppu.take_frame_ready() {
+            // RAM cheats are written again every frame, after the game had a chance to change the values
+            for (addr, value) in self.cheats.ram_writes() {
+                match addr {
+                    0x0000 ..= 0x07FF => self.ram[addr as usize] = value,
+                    _ => self.prg_ram[(addr - 0x6000) as usize] = value,
+                }
+            }
 
-        if !nmi_before && nmi_after {
             (self.game)(&self.ppu, &mut self.joypad1);
+
+            // the movie sees (or overrides) the input the game will read during the next frame
+            if let Some(movie) = self.movie.as_mut() {
+                movie.frame(&mut self.joypad1);
+            }
         }
     }
 
     /// Function that gets the NMI status from the PPU
     pub fn nmi_status(&mut self) -> bool {
-        self.ppu.nmi()
+        let nmi = self.ppu.nmi();
+        if nmi {
+            self.nmi_count += 1;
+        }
+        nmi
+    }
+
+    /// Number of NMIs serviced since power on
+    pub fn nmi_count(&self) -> u64 {
+        self.nmi_count
+    }
+
+    /// Function that enables recording of memory accesses (used by watchpoints)
+    pub fn track_accesses(&mut self, enabled: bool) {
+        self.track_accesses = enabled;
+        self.accesses.clear();
+    }
+
+    /// Function that returns and clears the recorded memory accesses
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        std::mem::take(&mut self.accesses)
+    }
+
+    /// Function that records a memory access if tracking is enabled
+    fn record(&mut self, space: AddressSpace, addr: u16, write: bool) {
+        if self.track_accesses {
+            self.accesses.push(MemoryAccess { space, addr, write });
+        }
+    }
+
+    /// Function that reads a value without side effects, used by the debugger
+    /// I/O registers other than PPUSTATUS read as 0
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000 ..= 0x1FFF => self.ram[(addr & 0x07FF) as usize],
+            0x2000 ..= 0x3FFF if addr & 0x0007 == 2 => self.ppu.status_register.value,
+            0x6000 ..= 0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000 ..= 0xFFFF => self.cheats.read(addr, self.prg[(addr - 0x8000) as usize % self.prg.len()]),
+            _ => 0,
+        }
+    }
+
+    /// Function that reads a u16 value without side effects
+    pub fn peek_u16(&self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.peek(addr), self.peek(addr.wrapping_add(1))])
+    }
+
+    /// Function that returns the 16KB PRG bank mapped at an address, None outside of the ROM
+    pub fn prg_bank(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000 ..= 0xFFFF => Some(((addr - 0x8000) as usize % self.prg.len()) / 0x4000),
+            _ => None,
+        }
     }
 
     /// Function that returns a value read from the memory at a given address
@@ -90,6 +307,7 @@ impl<'a> Bus<'a> {
     pub fn read(&mut self, addr: u16) -> u8 {
 
         // println!("Read from address: {:#06X}", addr);
+        self.record(AddressSpace::CPU, addr, false);
 
         match addr {
             0x0000 ..= 0x1FFF => {
@@ -97,9 +315,13 @@ impl<'a> Bus<'a> {
                 // this makes sure that the ram is mirrored every 0x0800 bytes
                 self.ram[(addr & 0x07FF) as usize]
             },
-            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 | 0x4014 => {
+            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => {
                 // write only registers
-                // panic!("Write only register at address: {:#06X}", addr);
+                // reading them returns the decaying value of the PPU I/O latch
+                self.ppu.read_open_bus()
+            },
+            0x4014 => {
+                // OAMDMA (write only)
                 0
             },
             0x2002 => {
@@ -112,6 +334,7 @@ impl<'a> Bus<'a> {
             }
             0x2007 => {
                 // PPUDATA
+                self.record(AddressSpace::PPU, self.ppu.address_register.get() & 0x3FFF, false);
                 self.ppu.read()
             },
             0x4000 ..= 0x4015 => {
@@ -130,9 +353,14 @@ impl<'a> Bus<'a> {
                 let mirror_addr = addr & 0x2007;
                 self.read(mirror_addr)
             },
+            0x6000 ..= 0x7FFF => {
+                // cartridge RAM
+                self.prg_ram[(addr - 0x6000) as usize]
+            },
             0x8000 ..= 0xFFFF => {
-                // cartridge
-                self.read_from_rom(addr)
+                // cartridge, seen through the Game Genie
+                let value = self.read_from_rom(addr);
+                self.cheats.read(addr, value)
             },
             _ => {
                 // invalid read
@@ -158,6 +386,7 @@ impl<'a> Bus<'a> {
     pub fn write(&mut self, addr: u16, val: u8) {
 
         // println!("Write to address: {:#06X}, value: {:#04X}", addr, val);
+        self.record(AddressSpace::CPU, addr, true);
 
         match addr {
             0x0000 ..= 0x1FFF => {
@@ -191,6 +420,7 @@ impl<'a> Bus<'a> {
             },
             0x2007 => {
                 // PPUDATA
+                self.record(AddressSpace::PPU, self.ppu.address_register.get() & 0x3FFF, true);
                 self.ppu.write(val);
             },
             0x4000..=0x4013 | 0x4015 => {
@@ -218,9 +448,12 @@ impl<'a> Bus<'a> {
                 let mirror_addr = addr & 0x2007;
                 self.write(mirror_addr, val);
             },
+            0x6000 ..= 0x7FFF => {
+                // cartridge RAM
+                self.prg_ram[(addr - 0x6000) as usize] = val;
+            },
             0x8000 ..= 0xFFFF => {
-                // cartridge
-                panic!("Write to ROM is not supported");
+                // cartridge ROM, writes are ignored without a mapper that listens to them
             },
             _ => {
                 // invalid write
@@ -251,4 +484,25 @@ impl<'a> Bus<'a> {
         // return the value at the adjusted address
         self.prg[adjusted_addr as usize]
     }
-}
\ No newline at end of file
+}
+
+impl Savestate for Bus<'_> {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+        writer.bytes(&self.prg_ram);
+        writer.u64(self.cycles as u64);
+        writer.u64(self.ppu_remainder as u64);
+        self.ppu.save(writer);
+        self.joypad1.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.ram)?;
+        reader.bytes_into(&mut self.prg_ram)?;
+        self.cycles = reader.u64()? as usize;
+        self.ppu_remainder = reader.u64()? as usize;
+        self.ppu.load(reader)?;
+        self.joypad1.load(reader)?;
+        Ok(())
+    }
+}
@@ -8,6 +8,7 @@ use crate::cpu::cpu_status::CPUStatus;
 use crate::cpu::instructions::{Instruction, OpName::*, INSTRUCTION_MAP};
 use crate::cpu::interrupt::{Interrupt, NMI};
 use crate::flags::Status;
+use crate::state::{Savestate, StateError, StateReader, StateWriter};
 
 /// This class represents the CPU
 pub struct CPU<'a> {
@@ -82,6 +83,39 @@ impl<'a> CPU<'a> {
         self.prog_counter = self.read_u16(0xFFFC);
     }
 
+    /// Function that turns the console off and on again, unlike reset the memory is cleared
+    pub fn power_cycle(&mut self) {
+        self.bus.power_cycle();
+        self.reset();
+    }
+
+    /// Function that serializes the entire machine into a save state
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.header(self.bus.rom_hash());
+        self.save(&mut writer);
+        writer.finish()
+    }
+
+    /// Function that restores the entire machine from a save state
+    /// States made with a different ROM or format version are rejected
+    /// and leave the machine untouched
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::new(data);
+        reader.header(self.bus.rom_hash())?;
+
+        // restore the current state if the new one turns out to be invalid halfway through
+        let backup = self.save_state();
+        if let Err(err) = self.load(&mut reader) {
+            let mut reader = StateReader::new(&backup);
+            reader.header(self.bus.rom_hash())?;
+            self.load(&mut reader)?;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
     /// Function that handles an interrupt
     pub fn interrupt(&mut self, interrupt: Interrupt) {
         // push the program counter to the stack
@@ -297,7 +331,7 @@ impl<'a> CPU<'a> {
         let res = param << 1;
         self.write(address, res);
         self.zero_negative(res);
-        param
+        res
     }
 
     fn branch(&mut self, condition: bool) {
@@ -503,7 +537,7 @@ impl<'a> CPU<'a> {
         self.zero_negative(res);
     }
 
-    fn lsr(&mut self, mode: &Addressing) {
+    fn lsr(&mut self, mode: &Addressing) -> u8 {
         let (address, _) = self.get_param_address_internal(mode);
         let param = self.read(address);
 
@@ -517,6 +551,7 @@ impl<'a> CPU<'a> {
         let res = param >> 1;
         self.write(address, res);
         self.zero_negative(res);
+        res
     }
 
     fn ora(&mut self, mode: &Addressing) {
@@ -708,6 +743,66 @@ impl<'a> CPU<'a> {
         self.zero_negative(self.a.value());
     }
 
+    // https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+    // The read-modify-write combinations take the same cycles regardless of page crossing
+
+    // unofficial NOPs still take an extra cycle when the address crosses a page
+    fn nop(&mut self, mode: &Addressing) {
+        if matches!(mode, Addressing::None) {
+            return;
+        }
+
+        let (_, cross) = self.get_param_address_internal(mode);
+        if cross {
+            self.bus.tick(1);
+        }
+    }
+
+    fn lax(&mut self, mode: &Addressing) {
+        self.lda(mode);
+        self.x.set(self.a.value());
+    }
+
+    fn sax(&mut self, mode: &Addressing) {
+        let (address, _) = self.get_param_address_internal(mode);
+        self.write(address, self.a.value() & self.x.value());
+    }
+
+    fn dcp(&mut self, mode: &Addressing) {
+        let param = self.dec(mode);
+
+        self.status.set(Status::Carry.as_u8(), param <= self.a.value());
+        self.zero_negative(self.a.value().wrapping_sub(param));
+    }
+
+    fn isb(&mut self, mode: &Addressing) {
+        let param = self.inc(mode);
+        self.add_to_a(!param);
+    }
+
+    fn slo(&mut self, mode: &Addressing) {
+        let param = self.asl(mode);
+        self.a.set(self.a.value() | param);
+        self.zero_negative(self.a.value());
+    }
+
+    fn rla(&mut self, mode: &Addressing) {
+        let param = self.rol(mode);
+        self.a.set(self.a.value() & param);
+        self.zero_negative(self.a.value());
+    }
+
+    fn sre(&mut self, mode: &Addressing) {
+        let param = self.lsr(mode);
+        self.a.set(self.a.value() ^ param);
+        self.zero_negative(self.a.value());
+    }
+
+    fn rra(&mut self, mode: &Addressing) {
+        let param = self.ror(mode);
+        self.add_to_a(param);
+    }
+
     /// Function that interprets the given program
     pub fn interpret(&mut self) {
         self.interpret_callback(|_| {});
@@ -719,103 +814,130 @@ impl<'a> CPU<'a> {
         F: FnMut(&mut CPU)
     {
         loop {
-            if self.bus.nmi_status() {
-                self.interrupt(NMI);
-            }
+            self.poll_interrupts();
 
             callback(self);
 
-            let ins_code = self.read(self.prog_counter);
-            self.prog_counter += 1;
-            let prog_counter_state = self.prog_counter;
-
-            let ins: &Instruction = match INSTRUCTION_MAP.get(&ins_code) {
-                Some(instruction) => instruction,
-                None => {
-                    eprintln!("Unrecognized opcode: 0x{:X}", ins_code);
-                    return;
-                }
-            };
-
-            // println!("Before PC: {:X} | {} | A: {} X: {} Y: {}", self.prog_counter, self.status, self.a.value(), self.x.value(), self.y.value());
-
-            // println!("Executing: {:?} - {:?} (0x{:X}, {} bytes)", ins.name, ins.mode, ins.address, ins.bytes);
-
-            match ins.name {
-                ADC => self.adc(&ins.mode),
-                AND => self.and(&ins.mode),
-                ASL_A => self.asl_a(),
-                ASL => { self.asl(&ins.mode); },
-                BIT => self.bit(&ins.mode),
-                BCS => self.branch(self.status.is_set(Status::Carry.as_u8())),
-                BCC => self.branch(!self.status.is_set(Status::Carry.as_u8())),
-                BEQ => self.branch(self.status.is_set(Status::Zero.as_u8())),
-                BNE => self.branch(!self.status.is_set(Status::Zero.as_u8())),
-                BMI => self.branch(self.status.is_set(Status::Negative.as_u8())),
-                BPL => self.branch(!self.status.is_set(Status::Negative.as_u8())),
-                BVS => self.branch(self.status.is_set(Status::Overflow.as_u8())),
-                BVC => self.branch(!self.status.is_set(Status::Overflow.as_u8())),
-                BRK => { },
-                CLC => self.clear_status(Status::Carry),
-                CLD => self.clear_status(Status::Decimal),
-                CLI => self.clear_status(Status::InterruptDisable),
-                CLV => self.clear_status(Status::Overflow),
-                CMP => self.compare(self.a.value(), &ins.mode),
-                CPX => self.compare(self.x.value(), &ins.mode),
-                CPY => self.compare(self.y.value(), &ins.mode),
-                DEC => { self.dec(&ins.mode); },
-                DEX => self.dex(),
-                DEY => self.dey(),
-                EOR => self.eor(&ins.mode),
-                INC => { self.inc(&ins.mode); },
-                INX => self.inx(),
-                INY => self.iny(),
-                JMP_ABS => self.jmp_abs(),
-                JMP_IND => self.jmp_ind(),
-                JSR => self.jsr(),
-                LDA => self.lda(&ins.mode),
-                LDX => self.ldx(&ins.mode),
-                LDY => self.ldy(&ins.mode),
-                LSR_A => self.lsr_a(),
-                LSR => self.lsr(&ins.mode),
-                NOP => /* no change */ (),
-                ORA => self.ora(&ins.mode),
-                PHA => self.pha(),
-                PHP => self.php(),
-                PLA => self.pla(),
-                PLP => self.plp(),
-                ROL_A => self.rol_a(),
-                ROL => { self.rol(&ins.mode); },
-                ROR_A => self.ror_a(),
-                ROR => { self.ror(&ins.mode); },
-                RTI => self.rti(),
-                RTS => self.rts(),
-                SBC => self.sbc(&ins.mode),
-                SEC => self.set_status(Status::Carry),
-                SED => self.set_status(Status::Decimal),
-                SEI => self.set_status(Status::InterruptDisable),
-                STA => self.sta(&ins.mode),
-                STX => self.stx(&ins.mode),
-                STY => self.sty(&ins.mode),
-                TAX => self.tax(),
-                TAY => self.tay(),
-                TSX => self.tsx(),
-                TXA => self.txa(),
-                TXS => self.txs(),
-                TYA => self.tya(),
+            if !self.step() {
+                return;
             }
+        }
+    }
 
-            self.bus.tick(ins.cycles);
+    /// Function that services a pending NMI
+    pub fn poll_interrupts(&mut self) {
+        if self.bus.nmi_status() {
+            self.interrupt(NMI);
+        }
+    }
 
-            if self.prog_counter == prog_counter_state {
-                // increase prog_counter
-                // (ins.bytes - 1) because we already increased it by 1 at the beginning
-                self.prog_counter += (ins.bytes - 1) as u16;
+    /// Function that executes a single instruction and ticks the bus
+    /// Returns false if the opcode is not recognized
+    pub fn step(&mut self) -> bool {
+        let ins_code = self.read(self.prog_counter);
+        self.prog_counter += 1;
+        let prog_counter_state = self.prog_counter;
+
+        let ins: &Instruction = match INSTRUCTION_MAP.get(&ins_code) {
+            Some(instruction) => instruction,
+            None => {
+                eprintln!("Unrecognized opcode: 0x{:X}", ins_code);
+                return false;
             }
+        };
 
-            // println!("After PC: {:X} | {} | A: {} X: {} Y: {}", self.prog_counter, self.status, self.a.value(), self.x.value(), self.y.value());
-            // println!("Status: {} SP: {:X} CYC: {}", self.status, self.stack.pointer, self.bus.cycles);
-        }
+        // println!("Before PC: {:X} | {} | A: {} X: {} Y: {}", self.prog_counter, self.status, self.a.value(), self.x.value(), self.y.value());
+
+        // println!("Executing: {:?} - {:?} (0x{:X}, {} bytes)", ins.name, ins.mode, ins.address, ins.bytes);
+
+        match ins.name {
+            ADC => self.adc(&ins.mode),
+            AND => self.and(&ins.mode),
+            ASL_A => self.asl_a(),
+            ASL => { self.asl(&ins.mode); },
+            BIT => self.bit(&ins.mode),
+            BCS => self.branch(self.status.is_set(Status::Carry.as_u8())),
+            BCC => self.branch(!self.status.is_set(Status::Carry.as_u8())),
+            BEQ => self.branch(self.status.is_set(Status::Zero.as_u8())),
+            BNE => self.branch(!self.status.is_set(Status::Zero.as_u8())),
+            BMI => self.branch(self.status.is_set(Status::Negative.as_u8())),
+            BPL => self.branch(!self.status.is_set(Status::Negative.as_u8())),
+            BVS => self.branch(self.status.is_set(Status::Overflow.as_u8())),
+            BVC => self.branch(!self.status.is_set(Status::Overflow.as_u8())),
+            BRK => { },
+            CLC => self.clear_status(Status::Carry),
+            CLD => self.clear_status(Status::Decimal),
+            CLI => self.clear_status(Status::InterruptDisable),
+            CLV => self.clear_status(Status::Overflow),
+            CMP => self.compare(self.a.value(), &ins.mode),
+            CPX => self.compare(self.x.value(), &ins.mode),
+            CPY => self.compare(self.y.value(), &ins.mode),
+            DEC => { self.dec(&ins.mode); },
+            DEX => self.dex(),
+            DEY => self.dey(),
+            EOR => self.eor(&ins.mode),
+            INC => { self.inc(&ins.mode); },
+            INX => self.inx(),
+            INY => self.iny(),
+            JMP_ABS => self.jmp_abs(),
+            JMP_IND => self.jmp_ind(),
+            JSR => self.jsr(),
+            LDA => self.lda(&ins.mode),
+            LDX => self.ldx(&ins.mode),
+            LDY => self.ldy(&ins.mode),
+            LSR_A => self.lsr_a(),
+            LSR => { self.lsr(&ins.mode); },
+            NOP => /* no change */ (),
+            ORA => self.ora(&ins.mode),
+            PHA => self.pha(),
+            PHP => self.php(),
+            PLA => self.pla(),
+            PLP => self.plp(),
+            ROL_A => self.rol_a(),
+            ROL => { self.rol(&ins.mode); },
+            ROR_A => self.ror_a(),
+            ROR => { self.ror(&ins.mode); },
+            RTI => self.rti(),
+            RTS => self.rts(),
+            SBC => self.sbc(&ins.mode),
+            SEC => self.set_status(Status::Carry),
+            SED => self.set_status(Status::Decimal),
+            SEI => self.set_status(Status::InterruptDisable),
+            STA => self.sta(&ins.mode),
+            STX => self.stx(&ins.mode),
+            STY => self.sty(&ins.mode),
+            TAX => self.tax(),
+            TAY => self.tay(),
+            TSX => self.tsx(),
+            TXA => self.txa(),
+            TXS => self.txs(),
+            TYA => self.tya(),
+
+            // unofficial
+            NOP_U => self.nop(&ins.mode),
+            SBC_U => self.sbc(&ins.mode),
+            LAX => self.lax(&ins.mode),
+            SAX => self.sax(&ins.mode),
+            DCP => self.dcp(&ins.mode),
+            ISB => self.isb(&ins.mode),
+            SLO => self.slo(&ins.mode),
+            RLA => self.rla(&ins.mode),
+            SRE => self.sre(&ins.mode),
+            RRA => self.rra(&ins.mode),
+        }
+
+        self.bus.tick(ins.cycles);
+
+        if self.prog_counter == prog_counter_state {
+            // increase prog_counter
+            // (ins.bytes - 1) because we already increased it by 1 at the beginning
+            self.prog_counter += (ins.bytes - 1) as u16;
+        }
+
+        // println!("After PC: {:X} | {} | A: {} X: {} Y: {}", self.prog_counter, self.status, self.a.value(), self.x.value(), self.y.value());
+        // println!("Status: {} SP: {:X} CYC: {}", self.status, self.stack.pointer, self.bus.cycles);
+
+        true
     }
 
     pub fn stack_push(&mut self, val: u8) {
@@ -839,3 +961,25 @@ impl<'a> CPU<'a> {
         (high << 8) | low
     }
 }
+
+impl Savestate for CPU<'_> {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.a.value());
+        writer.u8(self.x.value());
+        writer.u8(self.y.value());
+        writer.u8(self.status.value);
+        writer.u16(self.prog_counter);
+        writer.u8(self.stack_pointer);
+        self.bus.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.a.set(reader.u8()?);
+        self.x.set(reader.u8()?);
+        self.y.set(reader.u8()?);
+        self.status.set_bits(reader.u8()?);
+        self.prog_counter = reader.u16()?;
+        self.stack_pointer = reader.u8()?;
+        self.bus.load(reader)
+    }
+}
@@ -210,6 +210,106 @@ lazy_static! {
         Instruction::new(0x68, OpName::PLA, 1, 4, Addressing::None),
         Instruction::new(0x08, OpName::PHP, 1, 3, Addressing::None),
         Instruction::new(0x28, OpName::PLP, 1, 4, Addressing::None),
+
+        /* Unofficial */
+        // https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+        Instruction::new(0x1a, OpName::NOP_U, 1, 2, Addressing::None),
+        Instruction::new(0x3a, OpName::NOP_U, 1, 2, Addressing::None),
+        Instruction::new(0x5a, OpName::NOP_U, 1, 2, Addressing::None),
+        Instruction::new(0x7a, OpName::NOP_U, 1, 2, Addressing::None),
+        Instruction::new(0xda, OpName::NOP_U, 1, 2, Addressing::None),
+        Instruction::new(0xfa, OpName::NOP_U, 1, 2, Addressing::None),
+        Instruction::new(0x80, OpName::NOP_U, 2, 2, Addressing::Immediate),
+        Instruction::new(0x82, OpName::NOP_U, 2, 2, Addressing::Immediate),
+        Instruction::new(0x89, OpName::NOP_U, 2, 2, Addressing::Immediate),
+        Instruction::new(0xc2, OpName::NOP_U, 2, 2, Addressing::Immediate),
+        Instruction::new(0xe2, OpName::NOP_U, 2, 2, Addressing::Immediate),
+        Instruction::new(0x04, OpName::NOP_U, 2, 3, Addressing::ZeroPage),
+        Instruction::new(0x44, OpName::NOP_U, 2, 3, Addressing::ZeroPage),
+        Instruction::new(0x64, OpName::NOP_U, 2, 3, Addressing::ZeroPage),
+        Instruction::new(0x14, OpName::NOP_U, 2, 4, Addressing::ZeroPageX),
+        Instruction::new(0x34, OpName::NOP_U, 2, 4, Addressing::ZeroPageX),
+        Instruction::new(0x54, OpName::NOP_U, 2, 4, Addressing::ZeroPageX),
+        Instruction::new(0x74, OpName::NOP_U, 2, 4, Addressing::ZeroPageX),
+        Instruction::new(0xd4, OpName::NOP_U, 2, 4, Addressing::ZeroPageX),
+        Instruction::new(0xf4, OpName::NOP_U, 2, 4, Addressing::ZeroPageX),
+        Instruction::new(0x0c, OpName::NOP_U, 3, 4, Addressing::Absolute),
+        Instruction::new(0x1c, OpName::NOP_U, 3, 4/*+1 if page crossed*/, Addressing::AbsoluteX),
+        Instruction::new(0x3c, OpName::NOP_U, 3, 4/*+1 if page crossed*/, Addressing::AbsoluteX),
+        Instruction::new(0x5c, OpName::NOP_U, 3, 4/*+1 if page crossed*/, Addressing::AbsoluteX),
+        Instruction::new(0x7c, OpName::NOP_U, 3, 4/*+1 if page crossed*/, Addressing::AbsoluteX),
+        Instruction::new(0xdc, OpName::NOP_U, 3, 4/*+1 if page crossed*/, Addressing::AbsoluteX),
+        Instruction::new(0xfc, OpName::NOP_U, 3, 4/*+1 if page crossed*/, Addressing::AbsoluteX),
+
+        Instruction::new(0xeb, OpName::SBC_U, 2, 2, Addressing::Immediate),
+
+        // LDA + TAX
+        Instruction::new(0xa7, OpName::LAX, 2, 3, Addressing::ZeroPage),
+        Instruction::new(0xb7, OpName::LAX, 2, 4, Addressing::ZeroPageY),
+        Instruction::new(0xaf, OpName::LAX, 3, 4, Addressing::Absolute),
+        Instruction::new(0xbf, OpName::LAX, 3, 4/*+1 if page crossed*/, Addressing::AbsoluteY),
+        Instruction::new(0xa3, OpName::LAX, 2, 6, Addressing::IndirectX),
+        Instruction::new(0xb3, OpName::LAX, 2, 5/*+1 if page crossed*/, Addressing::IndirectY),
+
+        // stores A & X
+        Instruction::new(0x87, OpName::SAX, 2, 3, Addressing::ZeroPage),
+        Instruction::new(0x97, OpName::SAX, 2, 4, Addressing::ZeroPageY),
+        Instruction::new(0x8f, OpName::SAX, 3, 4, Addressing::Absolute),
+        Instruction::new(0x83, OpName::SAX, 2, 6, Addressing::IndirectX),
+
+        // DEC + CMP
+        Instruction::new(0xc7, OpName::DCP, 2, 5, Addressing::ZeroPage),
+        Instruction::new(0xd7, OpName::DCP, 2, 6, Addressing::ZeroPageX),
+        Instruction::new(0xcf, OpName::DCP, 3, 6, Addressing::Absolute),
+        Instruction::new(0xdf, OpName::DCP, 3, 7, Addressing::AbsoluteX),
+        Instruction::new(0xdb, OpName::DCP, 3, 7, Addressing::AbsoluteY),
+        Instruction::new(0xc3, OpName::DCP, 2, 8, Addressing::IndirectX),
+        Instruction::new(0xd3, OpName::DCP, 2, 8, Addressing::IndirectY),
+
+        // INC + SBC
+        Instruction::new(0xe7, OpName::ISB, 2, 5, Addressing::ZeroPage),
+        Instruction::new(0xf7, OpName::ISB, 2, 6, Addressing::ZeroPageX),
+        Instruction::new(0xef, OpName::ISB, 3, 6, Addressing::Absolute),
+        Instruction::new(0xff, OpName::ISB, 3, 7, Addressing::AbsoluteX),
+        Instruction::new(0xfb, OpName::ISB, 3, 7, Addressing::AbsoluteY),
+        Instruction::new(0xe3, OpName::ISB, 2, 8, Addressing::IndirectX),
+        Instruction::new(0xf3, OpName::ISB, 2, 8, Addressing::IndirectY),
+
+        // ASL + ORA
+        Instruction::new(0x07, OpName::SLO, 2, 5, Addressing::ZeroPage),
+        Instruction::new(0x17, OpName::SLO, 2, 6, Addressing::ZeroPageX),
+        Instruction::new(0x0f, OpName::SLO, 3, 6, Addressing::Absolute),
+        Instruction::new(0x1f, OpName::SLO, 3, 7, Addressing::AbsoluteX),
+        Instruction::new(0x1b, OpName::SLO, 3, 7, Addressing::AbsoluteY),
+        Instruction::new(0x03, OpName::SLO, 2, 8, Addressing::IndirectX),
+        Instruction::new(0x13, OpName::SLO, 2, 8, Addressing::IndirectY),
+
+        // ROL + AND
+        Instruction::new(0x27, OpName::RLA, 2, 5, Addressing::ZeroPage),
+        Instruction::new(0x37, OpName::RLA, 2, 6, Addressing::ZeroPageX),
+        Instruction::new(0x2f, OpName::RLA, 3, 6, Addressing::Absolute),
+        Instruction::new(0x3f, OpName::RLA, 3, 7, Addressing::AbsoluteX),
+        Instruction::new(0x3b, OpName::RLA, 3, 7, Addressing::AbsoluteY),
+        Instruction::new(0x23, OpName::RLA, 2, 8, Addressing::IndirectX),
+        Instruction::new(0x33, OpName::RLA, 2, 8, Addressing::IndirectY),
+
+        // LSR + EOR
+        Instruction::new(0x47, OpName::SRE, 2, 5, Addressing::ZeroPage),
+        Instruction::new(0x57, OpName::SRE, 2, 6, Addressing::ZeroPageX),
+        Instruction::new(0x4f, OpName::SRE, 3, 6, Addressing::Absolute),
+        Instruction::new(0x5f, OpName::SRE, 3, 7, Addressing::AbsoluteX),
+        Instruction::new(0x5b, OpName::SRE, 3, 7, Addressing::AbsoluteY),
+        Instruction::new(0x43, OpName::SRE, 2, 8, Addressing::IndirectX),
+        Instruction::new(0x53, OpName::SRE, 2, 8, Addressing::IndirectY),
+
+        // ROR + ADC
+        Instruction::new(0x67, OpName::RRA, 2, 5, Addressing::ZeroPage),
+        Instruction::new(0x77, OpName::RRA, 2, 6, Addressing::ZeroPageX),
+        Instruction::new(0x6f, OpName::RRA, 3, 6, Addressing::Absolute),
+        Instruction::new(0x7f, OpName::RRA, 3, 7, Addressing::AbsoluteX),
+        Instruction::new(0x7b, OpName::RRA, 3, 7, Addressing::AbsoluteY),
+        Instruction::new(0x63, OpName::RRA, 2, 8, Addressing::IndirectX),
+        Instruction::new(0x73, OpName::RRA, 2, 8, Addressing::IndirectY),
     ];
 }
 
@@ -299,12 +399,38 @@ pub enum OpName {
     TXA,
     TXS,
     TYA,
+
+    // unofficial
+    NOP_U,
+    SBC_U,
+    LAX,
+    SAX,
+    DCP,
+    ISB,
+    SLO,
+    RLA,
+    SRE,
+    RRA,
+}
+
+impl OpName {
+    /// Checks if the instruction is one of the unofficial opcodes
+    pub fn is_unofficial(&self) -> bool {
+        matches!(
+            self,
+            OpName::NOP_U | OpName::SBC_U | OpName::LAX | OpName::SAX | OpName::DCP
+                | OpName::ISB | OpName::SLO | OpName::RLA | OpName::SRE | OpName::RRA
+        )
+    }
 }
 
 impl Display for OpName {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let name = format!("{:?}", self);
         let first = name.split('_').next().unwrap_or(&name);
-        write!(f, "{}", first)
+
+        // unofficial opcodes are marked like in nestest.log
+        let prefix = if self.is_unofficial() { "*" } else { "" };
+        f.pad(&format!("{}{}", prefix, first))
     }
 }
\ No newline at end of file
@@ -0,0 +1,254 @@
+use crate::cpu::bus::{AddressSpace, MemoryAccess};
+use crate::cpu::cpu::CPU;
+use crate::cpu::instructions::INSTRUCTION_MAP;
+use crate::debugger::expression::Expression;
+use std::fmt::{Display, Formatter};
+
+/// Opcodes that end step out
+const RTS: u8 = 0x60;
+const RTI: u8 = 0x40;
+
+/// Opcode that step over runs through
+const JSR: u8 = 0x20;
+
+/// Execution breakpoint with an optional condition
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<Expression>,
+}
+
+/// Watchpoint on a range of addresses in the CPU or PPU address space
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub space: AddressSpace,
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &MemoryAccess) -> bool {
+        access.space == self.space
+            && (self.start ..= self.end).contains(&access.addr)
+            && if access.write { self.write } else { self.read }
+    }
+}
+
+/// Reason why the debugger stopped the execution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    Pause,
+    Step,
+    Breakpoint(usize),
+    Watchpoint(usize, MemoryAccess),
+    Nmi,
+    Irq,
+    Scanline(u16),
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Pause => write!(f, "paused"),
+            StopReason::Step => write!(f, "step"),
+            StopReason::Breakpoint(index) => write!(f, "breakpoint {}", index),
+            StopReason::Watchpoint(index, access) => write!(
+                f,
+                "watchpoint {}: {} {} ${:04X}",
+                index,
+                if access.write { "write" } else { "read" },
+                match access.space {
+                    AddressSpace::CPU => "CPU",
+                    AddressSpace::PPU => "PPU",
+                },
+                access.addr
+            ),
+            StopReason::Nmi => write!(f, "NMI"),
+            StopReason::Irq => write!(f, "IRQ"),
+            StopReason::Scanline(scanline) => write!(f, "scanline {}", scanline),
+        }
+    }
+}
+
+/// How the execution continues after the debugger resumes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    Continue,
+    StepInto,
+    StepOver { return_pc: u16, sp: u8 },
+    StepOut { sp: u8 },
+    RunToScanline(u16),
+}
+
+/// Class representing the debugger
+/// `check` is called before every instruction and decides whether to stop there
+#[derive(Debug)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub break_on_nmi: bool,
+
+    /// There is no IRQ source yet, so this stops when the IRQ/BRK handler is entered
+    pub break_on_irq: bool,
+
+    mode: RunMode,
+    paused: bool,
+
+    /// State of the previous instruction, used to filter its own fetch and to detect interrupts
+    last_pc: u16,
+    last_opcode: u8,
+    last_nmi: u64,
+    last_scanline: u16,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: vec![],
+            watchpoints: vec![],
+            break_on_nmi: false,
+            break_on_irq: false,
+            mode: RunMode::Continue,
+            paused: false,
+            last_pc: 0,
+            last_opcode: 0,
+            last_nmi: 0,
+            last_scanline: 0,
+        }
+    }
+
+    /// Function that stops the execution before the next instruction
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Function that resumes the execution until the next breakpoint
+    pub fn resume(&mut self) {
+        self.mode = RunMode::Continue;
+    }
+
+    /// Function that executes a single instruction
+    pub fn step_into(&mut self) {
+        self.mode = RunMode::StepInto;
+    }
+
+    /// Function that executes a single instruction, running subroutines called by JSR to completion
+    pub fn step_over(&mut self, cpu: &CPU) {
+        let pc = cpu.prog_counter;
+
+        self.mode = if cpu.bus.peek(pc) == JSR {
+            RunMode::StepOver { return_pc: pc.wrapping_add(3), sp: cpu.stack_pointer }
+        } else {
+            RunMode::StepInto
+        };
+    }
+
+    /// Function that runs until the current subroutine or interrupt handler returns
+    pub fn step_out(&mut self, cpu: &CPU) {
+        self.mode = RunMode::StepOut { sp: cpu.stack_pointer };
+    }
+
+    /// Function that runs until the PPU reaches the scanline
+    pub fn run_to_scanline(&mut self, scanline: u16) {
+        self.mode = RunMode::RunToScanline(scanline);
+    }
+
+    /// Function that checks if the execution should stop before the instruction at PC
+    pub fn check(&mut self, cpu: &mut CPU) -> Option<StopReason> {
+        let pc = cpu.prog_counter;
+        let opcode = cpu.bus.peek(pc);
+        let scanline = cpu.bus.ppu().scanline();
+
+        // memory accesses of the previous instruction, without its own fetch
+        let fetch = match INSTRUCTION_MAP.get(&self.last_opcode) {
+            Some(ins) => self.last_pc ..= self.last_pc.wrapping_add(ins.bytes as u16 - 1),
+            None => self.last_pc ..= self.last_pc,
+        };
+        let accesses: Vec<MemoryAccess> = cpu
+            .bus
+            .take_accesses()
+            .into_iter()
+            .filter(|access| access.space != AddressSpace::CPU || access.write || !fetch.contains(&access.addr))
+            .collect();
+        cpu.bus.track_accesses(!self.watchpoints.is_empty());
+
+        let nmi = cpu.bus.nmi_count() != self.last_nmi;
+        let returned = (self.last_opcode == RTS || self.last_opcode == RTI) && pc != self.last_pc;
+        let entered_scanline = scanline != self.last_scanline;
+
+        self.last_pc = pc;
+        self.last_opcode = opcode;
+        self.last_nmi = cpu.bus.nmi_count();
+        self.last_scanline = scanline;
+
+        let reason = self.stop_reason(cpu, &accesses, nmi, returned, entered_scanline);
+        if reason.is_some() {
+            self.paused = false;
+            self.mode = RunMode::Continue;
+        }
+        reason
+    }
+
+    fn stop_reason(
+        &self,
+        cpu: &CPU,
+        accesses: &[MemoryAccess],
+        nmi: bool,
+        returned: bool,
+        entered_scanline: bool,
+    ) -> Option<StopReason> {
+        let pc = cpu.prog_counter;
+
+        if self.paused {
+            return Some(StopReason::Pause);
+        }
+
+        for access in accesses {
+            if let Some(index) = self.watchpoints.iter().position(|watch| watch.matches(access)) {
+                return Some(StopReason::Watchpoint(index, *access));
+            }
+        }
+
+        if let Some(index) = self.watchpoints.iter().position(|watch| {
+            watch.execute && watch.space == AddressSpace::CPU && (watch.start ..= watch.end).contains(&pc)
+        }) {
+            let access = MemoryAccess { space: AddressSpace::CPU, addr: pc, write: false };
+            return Some(StopReason::Watchpoint(index, access));
+        }
+
+        if nmi && self.break_on_nmi {
+            return Some(StopReason::Nmi);
+        }
+
+        if self.break_on_irq && pc == cpu.bus.peek_u16(0xFFFE) {
+            return Some(StopReason::Irq);
+        }
+
+        if let Some(index) = self.breakpoints.iter().position(|breakpoint| {
+            breakpoint.addr == pc && breakpoint.condition.as_ref().is_none_or(|cond| cond.is_true(cpu))
+        }) {
+            return Some(StopReason::Breakpoint(index));
+        }
+
+        match self.mode {
+            RunMode::Continue => None,
+            RunMode::StepInto => Some(StopReason::Step),
+            RunMode::StepOver { return_pc, sp } => {
+                (pc == return_pc && cpu.stack_pointer >= sp).then_some(StopReason::Step)
+            },
+            RunMode::StepOut { sp } => (returned && cpu.stack_pointer > sp).then_some(StopReason::Step),
+            RunMode::RunToScanline(target) => {
+                (entered_scanline && cpu.bus.ppu().scanline() == target).then_some(StopReason::Scanline(target))
+            },
+        }
+    }
+}
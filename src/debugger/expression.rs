@@ -0,0 +1,358 @@
+// Conditional expressions for breakpoints and the print command
+//
+// A == #$10 && [$0300] > 3
+//
+// Numbers:    $10 / #$10 (hex), %1010 (binary), 16 (decimal)
+// Registers:  A X Y P SP PC, SL (scanline), DOT, FRAME
+// Memory:     [addr] reads a CPU byte without side effects
+// Operators:  ! - (unary), + - & | ^, == != < <= > >=, &&, ||
+
+use crate::cpu::cpu::CPU;
+
+/// Registers and counters that can be used in expressions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    P,
+    SP,
+    PC,
+    Scanline,
+    Dot,
+    Frame,
+}
+
+impl Register {
+    fn from_name(name: &str) -> Option<Register> {
+        match name.to_ascii_uppercase().as_str() {
+            "A" => Some(Register::A),
+            "X" => Some(Register::X),
+            "Y" => Some(Register::Y),
+            "P" => Some(Register::P),
+            "SP" => Some(Register::SP),
+            "PC" => Some(Register::PC),
+            "SL" | "SCANLINE" => Some(Register::Scanline),
+            "DOT" => Some(Register::Dot),
+            "FRAME" => Some(Register::Frame),
+            _ => None,
+        }
+    }
+
+    fn value(&self, cpu: &CPU) -> i64 {
+        match self {
+            Register::A => cpu.a.value() as i64,
+            Register::X => cpu.x.value() as i64,
+            Register::Y => cpu.y.value() as i64,
+            Register::P => cpu.status.value as i64,
+            Register::SP => cpu.stack_pointer as i64,
+            Register::PC => cpu.prog_counter as i64,
+            Register::Scanline => cpu.bus.ppu().scanline() as i64,
+            Register::Dot => cpu.bus.ppu().dot() as i64,
+            Register::Frame => cpu.bus.ppu().frame() as i64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Negate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    BitAnd,
+    BitOr,
+    BitXor,
+}
+
+/// Parsed expression tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    Number(i64),
+    Register(Register),
+    Memory(Box<Expression>),
+    Unary(UnaryOp, Box<Expression>),
+    Binary(BinaryOp, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+/// Operators ordered so that longer ones are matched first
+const OPERATORS: [&str; 19] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "+", "-", "&", "|", "^", "!", "(", ")", "[", "]", "=",
+];
+
+impl Expression {
+    /// Function that parses an expression
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let tokens = Expression::tokenize(text)?;
+        let mut parser = Parser { tokens, pos: 0 };
+
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    /// Function that evaluates the expression, comparisons and logic operators result in 0 or 1
+    pub fn eval(&self, cpu: &CPU) -> i64 {
+        match self {
+            Expression::Number(val) => *val,
+            Expression::Register(register) => register.value(cpu),
+            Expression::Memory(addr) => cpu.bus.peek(addr.eval(cpu) as u16) as i64,
+            Expression::Unary(op, expr) => {
+                let val = expr.eval(cpu);
+                match op {
+                    UnaryOp::Not => (val == 0) as i64,
+                    UnaryOp::Negate => -val,
+                }
+            },
+            Expression::Binary(BinaryOp::And, lhs, rhs) => (lhs.eval(cpu) != 0 && rhs.eval(cpu) != 0) as i64,
+            Expression::Binary(BinaryOp::Or, lhs, rhs) => (lhs.eval(cpu) != 0 || rhs.eval(cpu) != 0) as i64,
+            Expression::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(cpu), rhs.eval(cpu));
+                match op {
+                    BinaryOp::Equal => (lhs == rhs) as i64,
+                    BinaryOp::NotEqual => (lhs != rhs) as i64,
+                    BinaryOp::Less => (lhs < rhs) as i64,
+                    BinaryOp::LessEqual => (lhs <= rhs) as i64,
+                    BinaryOp::Greater => (lhs > rhs) as i64,
+                    BinaryOp::GreaterEqual => (lhs >= rhs) as i64,
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Subtract => lhs.wrapping_sub(rhs),
+                    BinaryOp::BitAnd => lhs & rhs,
+                    BinaryOp::BitOr => lhs | rhs,
+                    BinaryOp::BitXor => lhs ^ rhs,
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
+                }
+            },
+        }
+    }
+
+    /// Function that checks if the expression is true
+    pub fn is_true(&self, cpu: &CPU) -> bool {
+        self.eval(cpu) != 0
+    }
+
+    /// Function that parses a number in one of the supported notations
+    pub fn parse_number(text: &str) -> Option<i64> {
+        let text = text.strip_prefix('#').unwrap_or(text);
+
+        if let Some(hex) = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(bin) = text.strip_prefix('%') {
+            i64::from_str_radix(bin, 2).ok()
+        } else {
+            text.parse().ok()
+        }
+    }
+
+    fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+        let mut tokens = vec![];
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+
+            if c.is_whitespace() {
+                i += 1;
+            } else if c.is_ascii_alphanumeric() || c == '$' || c == '#' || c == '%' {
+                let start = i;
+                i += 1;
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || (chars[i] == '$' && chars[i - 1] == '#'))
+                {
+                    i += 1;
+                }
+
+                let word: String = chars[start .. i].iter().collect();
+                if c.is_ascii_alphabetic() {
+                    tokens.push(Token::Name(word));
+                } else {
+                    let val = Expression::parse_number(&word).ok_or(format!("invalid number {}", word))?;
+                    tokens.push(Token::Number(val));
+                }
+            } else {
+                let rest: String = chars[i ..].iter().take(2).collect();
+                let op = OPERATORS
+                    .iter()
+                    .find(|op| rest.starts_with(**op))
+                    .ok_or(format!("unexpected character {}", c))?;
+
+                // a single = is accepted as ==
+                tokens.push(Token::Op(if *op == "=" { "==" } else { op }));
+                i += op.len();
+            }
+        }
+
+        Ok(tokens)
+    }
+}
+
+/// Recursive descent parser, one function per precedence level
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn accept(&mut self, ops: &[(&str, BinaryOp)]) -> Option<BinaryOp> {
+        if let Some(Token::Op(token)) = self.tokens.get(self.pos) {
+            if let Some((_, op)) = ops.iter().find(|(name, _)| name == token) {
+                self.pos += 1;
+                return Some(*op);
+            }
+        }
+
+        None
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(token)) if *token == op => {
+                self.pos += 1;
+                Ok(())
+            },
+            _ => Err(format!("expected {}", op)),
+        }
+    }
+
+    fn binary<F>(&mut self, ops: &[(&str, BinaryOp)], mut next: F) -> Result<Expression, String>
+    where
+        F: FnMut(&mut Parser) -> Result<Expression, String>
+    {
+        let mut lhs = next(self)?;
+
+        while let Some(op) = self.accept(ops) {
+            let rhs = next(self)?;
+            lhs = Expression::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expression, String> {
+        self.binary(&[("||", BinaryOp::Or)], Parser::and)
+    }
+
+    fn and(&mut self) -> Result<Expression, String> {
+        self.binary(&[("&&", BinaryOp::And)], Parser::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expression, String> {
+        self.binary(&[
+            ("==", BinaryOp::Equal),
+            ("!=", BinaryOp::NotEqual),
+            ("<=", BinaryOp::LessEqual),
+            (">=", BinaryOp::GreaterEqual),
+            ("<", BinaryOp::Less),
+            (">", BinaryOp::Greater),
+        ], Parser::term)
+    }
+
+    fn term(&mut self) -> Result<Expression, String> {
+        self.binary(&[
+            ("+", BinaryOp::Add),
+            ("-", BinaryOp::Subtract),
+            ("&", BinaryOp::BitAnd),
+            ("|", BinaryOp::BitOr),
+            ("^", BinaryOp::BitXor),
+        ], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+
+        match token {
+            Some(Token::Op("!")) => Ok(Expression::Unary(UnaryOp::Not, Box::new(self.unary()?))),
+            Some(Token::Op("-")) => Ok(Expression::Unary(UnaryOp::Negate, Box::new(self.unary()?))),
+            Some(Token::Op("(")) => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            },
+            Some(Token::Op("[")) => {
+                let expr = self.or()?;
+                self.expect("]")?;
+                Ok(Expression::Memory(Box::new(expr)))
+            },
+            Some(Token::Number(val)) => Ok(Expression::Number(val)),
+            Some(Token::Name(name)) => Register::from_name(&name)
+                .map(Expression::Register)
+                .ok_or(format!("unknown register {}", name)),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::bus::Bus;
+    use crate::ppu::ppu::PPU;
+    use crate::render::input::joypad::Joypad;
+    use crate::trace::test_rom;
+
+    #[test]
+    fn test_parse_precedence() {
+        let expr = Expression::parse("A == #$10 && [$0300] > 3").unwrap();
+        assert_eq!(expr, Expression::Binary(
+            BinaryOp::And,
+            Box::new(Expression::Binary(
+                BinaryOp::Equal,
+                Box::new(Expression::Register(Register::A)),
+                Box::new(Expression::Number(0x10)),
+            )),
+            Box::new(Expression::Binary(
+                BinaryOp::Greater,
+                Box::new(Expression::Memory(Box::new(Expression::Number(0x300)))),
+                Box::new(Expression::Number(3)),
+            )),
+        ));
+
+        assert!(Expression::parse("A ==").is_err());
+        assert!(Expression::parse("Q == 1").is_err());
+        assert!(Expression::parse("([$10]").is_err());
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut bus = Bus::new(test_rom(), |_ppu: &PPU, _joy: &mut Joypad| {});
+        bus.write(0x0300, 5);
+        bus.write(0x0010, 0x03);
+
+        let mut cpu = CPU::new(bus);
+        cpu.a.set(0x10);
+        cpu.x.set(2);
+
+        let eval = |text: &str| Expression::parse(text).unwrap().eval(&cpu);
+        assert_eq!(eval("A == #$10 && [$0300] > 3"), 1);
+        assert_eq!(eval("A == #$10 && [$0300] > 5"), 0);
+        assert_eq!(eval("[$0300] - X"), 3);
+        assert_eq!(eval("[[$10] & 0 | $300]"), 5);
+        assert_eq!(eval("%1010 | 1"), 11);
+        assert_eq!(eval("!(X != 2) || 0"), 1);
+        assert_eq!(eval("-1 + A"), 15);
+    }
+}
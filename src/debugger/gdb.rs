@@ -0,0 +1,354 @@
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+//
+// GDB remote serial protocol stub
+// Registers (in this order): A, X, Y, P, SP (8 bits each), PC (16 bits, little endian)
+// Memory is the CPU address space, writes to ROM are rejected.
+
+use crate::cpu::cpu::CPU;
+use std::collections::HashSet;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// Target description, GDB has no built-in 6502 architecture
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rustbyte.6502">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="x" bitsize="8" regnum="1"/>
+    <reg name="y" bitsize="8" regnum="2"/>
+    <reg name="p" bitsize="8" regnum="3"/>
+    <reg name="sp" bitsize="8" regnum="4"/>
+    <reg name="pc" bitsize="16" regnum="5" type="code_ptr"/>
+  </feature>
+</target>"#;
+
+/// Byte GDB sends to interrupt the target (Ctrl-C)
+const INTERRUPT: u8 = 0x03;
+
+/// Number of instructions between two checks for an interrupt while running
+const INTERRUPT_POLL: usize = 1000;
+
+/// Signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// State of the target as seen by GDB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Stopped,
+    Running,
+    Stepping,
+    Detached,
+    Killed,
+}
+
+/// Class representing a GDB remote stub
+/// `hook` is meant to be called from `CPU::interpret_callback`
+pub struct GdbStub {
+    stream: TcpStream,
+    breakpoints: HashSet<u16>,
+    state: State,
+
+    /// Instructions executed since the last interrupt check
+    executed: usize,
+}
+
+impl GdbStub {
+    /// Function that waits for GDB to connect, the target starts stopped
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> std::io::Result<GdbStub> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        GdbStub::new(stream)
+    }
+
+    /// Create a stub for an already connected client
+    pub fn new(stream: TcpStream) -> std::io::Result<GdbStub> {
+        stream.set_nodelay(true)?;
+
+        Ok(GdbStub {
+            stream,
+            breakpoints: HashSet::new(),
+            state: State::Stopped,
+            executed: 0,
+        })
+    }
+
+    /// Function that is called before every instruction
+    /// Returns false when GDB kills the target or the connection is lost
+    pub fn hook(&mut self, cpu: &mut CPU) -> bool {
+        let signal = match self.state {
+            State::Detached => return true,
+            State::Killed => return false,
+            State::Stopped => None,
+            State::Stepping => Some(SIGTRAP),
+            State::Running => {
+                if self.breakpoints.contains(&cpu.prog_counter) {
+                    Some(SIGTRAP)
+                } else if self.interrupted() {
+                    Some(SIGINT)
+                } else {
+                    return true;
+                }
+            },
+        };
+
+        if let Some(signal) = signal {
+            self.state = State::Stopped;
+            if self.send(&format!("S{:02x}", signal)).is_err() {
+                return false;
+            }
+        }
+
+        self.serve(cpu).is_ok() && self.state != State::Killed
+    }
+
+    /// Function that handles packets until GDB resumes the target
+    fn serve(&mut self, cpu: &mut CPU) -> std::io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+
+        while self.state == State::Stopped {
+            let packet = self.receive()?;
+            let reply = self.handle(&packet, cpu);
+
+            match self.state {
+                // resuming is answered by the stop reply, killing is not answered
+                State::Running | State::Stepping | State::Killed => {},
+                _ => self.send(&reply)?,
+            }
+        }
+
+        self.executed = 0;
+        self.stream.set_nonblocking(self.state == State::Running)
+    }
+
+    /// Function that checks for a Ctrl-C from GDB every few instructions
+    fn interrupted(&mut self) -> bool {
+        self.executed += 1;
+        if self.executed < INTERRUPT_POLL {
+            return false;
+        }
+        self.executed = 0;
+
+        let mut byte = [0u8; 1];
+        match self.stream.read(&mut byte) {
+            Ok(1) => byte[0] == INTERRUPT,
+            Ok(_) => true,
+            Err(err) => err.kind() != ErrorKind::WouldBlock,
+        }
+    }
+
+    /// Function that handles a single packet and returns the reply
+    fn handle(&mut self, packet: &str, cpu: &mut CPU) -> String {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+
+        match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => {
+                let pc = cpu.prog_counter.to_le_bytes();
+                format!(
+                    "{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+                    cpu.a.value(), cpu.x.value(), cpu.y.value(), cpu.status.value, cpu.stack_pointer, pc[0], pc[1]
+                )
+            },
+            "G" => match decode_hex(args) {
+                Some(bytes) if bytes.len() == 7 => {
+                    for (i, val) in bytes[.. 5].iter().enumerate() {
+                        GdbStub::set_register(cpu, i, *val as u16);
+                    }
+                    GdbStub::set_register(cpu, 5, u16::from_le_bytes([bytes[5], bytes[6]]));
+                    "OK".to_string()
+                },
+                _ => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(5) => format!("{:04x}", cpu.prog_counter.swap_bytes()),
+                Ok(register) if register < 5 => format!("{:02x}", GdbStub::register(cpu, register)),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(register, val)| {
+                    Some((usize::from_str_radix(register, 16).ok()?, decode_hex(val)?))
+                });
+
+                match parsed {
+                    Some((5, bytes)) if bytes.len() == 2 => {
+                        GdbStub::set_register(cpu, 5, u16::from_le_bytes([bytes[0], bytes[1]]));
+                        "OK".to_string()
+                    },
+                    Some((register, bytes)) if register < 5 && bytes.len() == 1 => {
+                        GdbStub::set_register(cpu, register, bytes[0] as u16);
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string(),
+                }
+            },
+            "m" => match parse_range(args) {
+                Some((addr, len)) => (0 .. len)
+                    .map(|i| format!("{:02x}", cpu.bus.peek(addr.wrapping_add(i))))
+                    .collect(),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, decode_hex(data)?)));
+
+                match parsed {
+                    Some(((addr, len), data)) if data.len() == len as usize && addr as usize + data.len() <= 0x8000 => {
+                        for (i, val) in data.iter().enumerate() {
+                            cpu.bus.write(addr + i as u16, *val);
+                        }
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string(),
+                }
+            },
+            "Z" | "z" => {
+                // Z0 (software) and Z1 (hardware) breakpoints are both checked before every instruction
+                let mut fields = args.split(',');
+                let kind = fields.next();
+                let addr = fields.next().and_then(|addr| u16::from_str_radix(addr, 16).ok());
+
+                match (kind, addr) {
+                    (Some("0") | Some("1"), Some(addr)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(addr);
+                        } else {
+                            self.breakpoints.remove(&addr);
+                        }
+                        "OK".to_string()
+                    },
+                    _ => String::new(),
+                }
+            },
+            "c" | "s" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    cpu.prog_counter = addr;
+                }
+                self.state = if command == "c" { State::Running } else { State::Stepping };
+                String::new()
+            },
+            "D" => {
+                self.state = State::Detached;
+                "OK".to_string()
+            },
+            "k" => {
+                self.state = State::Killed;
+                String::new()
+            },
+            "H" => "OK".to_string(),
+            "q" => {
+                if args.starts_with("Supported") {
+                    "PacketSize=1000;qXfer:features:read+".to_string()
+                } else if args == "Attached" {
+                    "1".to_string()
+                } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+                    match parse_range(range) {
+                        Some((offset, len)) => {
+                            let offset = (offset as usize).min(TARGET_XML.len());
+                            let end = (offset + len as usize).min(TARGET_XML.len());
+                            let prefix = if end == TARGET_XML.len() { "l" } else { "m" };
+                            format!("{}{}", prefix, &TARGET_XML[offset .. end])
+                        },
+                        None => "E01".to_string(),
+                    }
+                } else {
+                    String::new()
+                }
+            },
+            // unsupported packets get an empty reply
+            _ => String::new(),
+        }
+    }
+
+    fn register(cpu: &CPU, register: usize) -> u8 {
+        match register {
+            0 => cpu.a.value(),
+            1 => cpu.x.value(),
+            2 => cpu.y.value(),
+            3 => cpu.status.value,
+            _ => cpu.stack_pointer,
+        }
+    }
+
+    fn set_register(cpu: &mut CPU, register: usize, val: u16) {
+        match register {
+            0 => cpu.a.set(val as u8),
+            1 => cpu.x.set(val as u8),
+            2 => cpu.y.set(val as u8),
+            3 => cpu.status.value = val as u8,
+            4 => cpu.stack_pointer = val as u8,
+            _ => cpu.prog_counter = val,
+        }
+    }
+
+    /// Function that reads the next packet "$data#checksum" and acknowledges it
+    fn receive(&mut self) -> std::io::Result<String> {
+        loop {
+            // skip acks and interrupts until the start of a packet
+            while self.read_byte()? != b'$' {}
+
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+            if expected == Some(checksum_of(&data)) {
+                self.stream.write_all(b"+")?;
+                return Ok(String::from_utf8_lossy(&data).to_string());
+            }
+
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    /// Function that sends a packet and waits for the acknowledgement
+    fn send(&mut self, data: &str) -> std::io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.set_nonblocking(false)?;
+
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+
+            match self.read_byte()? {
+                b'-' => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> std::io::Result<u8> {
+        let mut byte = [0u8; 1];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+}
+
+/// Function that computes the checksum of a packet, the sum of its bytes modulo 256
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Function that parses "addr,length"
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((u16::from_str_radix(addr, 16).ok()?, u16::from_str_radix(len, 16).ok()?))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0 .. text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i .. i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,313 @@
+// https://www.nesdev.org/wiki/CPU_memory_map
+// https://www.nesdev.org/wiki/PPU_memory_map
+//
+// Direct access to the emulator memory for the debugger, without the side effects of the bus,
+// and a cheat finder style search over it
+
+use crate::cpu::cpu::CPU;
+use crate::ppu::ppu::PPU;
+
+/// Memory that can be viewed and edited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryRegion {
+    /// 2kB of CPU RAM at $0000
+    Ram,
+
+    /// 8kB of cartridge RAM at $6000
+    PrgRam,
+
+    /// 2kB of nametable RAM inside the console, shown at $2000
+    Vram,
+
+    /// 256 bytes of sprite attributes
+    Oam,
+
+    /// 32 bytes of palette RAM at $3F00
+    Palette,
+}
+
+impl MemoryRegion {
+    pub fn from_name(name: &str) -> Option<MemoryRegion> {
+        match name {
+            "ram" => Some(MemoryRegion::Ram),
+            "prg" => Some(MemoryRegion::PrgRam),
+            "vram" => Some(MemoryRegion::Vram),
+            "oam" => Some(MemoryRegion::Oam),
+            "pal" => Some(MemoryRegion::Palette),
+            _ => None,
+        }
+    }
+
+    /// Address of the first byte, addresses given to the debugger are in this range
+    pub fn base(&self) -> usize {
+        match self {
+            MemoryRegion::Ram | MemoryRegion::Oam => 0,
+            MemoryRegion::PrgRam => 0x6000,
+            MemoryRegion::Vram => 0x2000,
+            MemoryRegion::Palette => 0x3F00,
+        }
+    }
+
+    pub fn len(&self, cpu: &CPU) -> usize {
+        match self {
+            MemoryRegion::Ram => cpu.bus.ram().len(),
+            MemoryRegion::PrgRam => cpu.bus.prg_ram().len(),
+            MemoryRegion::Vram => cpu.bus.ppu().ram.len(),
+            MemoryRegion::Oam => cpu.bus.ppu().oam.len(),
+            MemoryRegion::Palette => cpu.bus.ppu().palette.len(),
+        }
+    }
+
+    /// Function that returns the whole region
+    pub fn bytes<'a>(&self, cpu: &'a CPU) -> &'a [u8] {
+        match self {
+            MemoryRegion::Ram => cpu.bus.ram(),
+            MemoryRegion::PrgRam => cpu.bus.prg_ram(),
+            MemoryRegion::Vram => &cpu.bus.ppu().ram,
+            MemoryRegion::Oam => &cpu.bus.ppu().oam,
+            MemoryRegion::Palette => &cpu.bus.ppu().palette,
+        }
+    }
+
+    fn bytes_mut<'a>(&self, cpu: &'a mut CPU) -> &'a mut [u8] {
+        match self {
+            MemoryRegion::Ram => cpu.bus.ram_mut(),
+            MemoryRegion::PrgRam => cpu.bus.prg_ram_mut(),
+            MemoryRegion::Vram => &mut cpu.bus.ppu_mut().ram,
+            MemoryRegion::Oam => &mut cpu.bus.ppu_mut().oam,
+            MemoryRegion::Palette => &mut cpu.bus.ppu_mut().palette,
+        }
+    }
+
+    /// Function that converts an address to an offset into the region
+    pub fn offset(&self, cpu: &CPU, addr: usize) -> Result<usize, String> {
+        let offset = addr.wrapping_sub(self.base());
+        if offset >= self.len(cpu) {
+            return Err(format!("${:04X} is outside of ${:04X}-${:04X}", addr, self.base(), self.base() + self.len(cpu) - 1));
+        }
+        Ok(offset)
+    }
+
+    /// Function that maps an offset to the byte backing it, $3F10/$3F14/$3F18/$3F1C share the
+    /// entries of $3F00/$3F04/$3F08/$3F0C like they do for the PPU
+    fn index(&self, offset: usize) -> usize {
+        match self {
+            MemoryRegion::Palette => PPU::palette_index(offset as u16),
+            _ => offset,
+        }
+    }
+
+    pub fn read(&self, cpu: &CPU, addr: usize) -> Result<u8, String> {
+        let offset = self.offset(cpu, addr)?;
+        Ok(self.bytes(cpu)[self.index(offset)])
+    }
+
+    /// Function that writes bytes starting at the address, palette entries keep only their 6 bits
+    pub fn write(&self, cpu: &mut CPU, addr: usize, data: &[u8]) -> Result<(), String> {
+        let offset = self.offset(cpu, addr)?;
+        if offset + data.len() > self.len(cpu) {
+            return Err(format!("{} bytes at ${:04X} don't fit into the region", data.len(), addr));
+        }
+
+        let mask = if *self == MemoryRegion::Palette { 0x3F } else { 0xFF };
+        for (i, src) in data.iter().enumerate() {
+            let index = self.index(offset + i);
+            self.bytes_mut(cpu)[index] = src & mask;
+        }
+        Ok(())
+    }
+}
+
+/// Size and interpretation of the searched values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchFormat {
+    /// 16-bit little endian values instead of bytes
+    pub word: bool,
+    pub signed: bool,
+}
+
+impl SearchFormat {
+    fn size(&self) -> usize {
+        if self.word { 2 } else { 1 }
+    }
+
+    fn value(&self, data: &[u8], offset: usize) -> i64 {
+        match (self.word, self.signed) {
+            (false, false) => data[offset] as i64,
+            (false, true) => data[offset] as i8 as i64,
+            (true, false) => u16::from_le_bytes([data[offset], data[offset + 1]]) as i64,
+            (true, true) => i16::from_le_bytes([data[offset], data[offset + 1]]) as i64,
+        }
+    }
+}
+
+/// Comparison that keeps a candidate, against the previous snapshot or a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+
+    /// Equal to the value, negative values match their two's complement when unsigned
+    Value(i64),
+}
+
+impl SearchFilter {
+    fn matches(&self, format: SearchFormat, previous: i64, current: i64) -> bool {
+        match self {
+            SearchFilter::Unchanged => current == previous,
+            SearchFilter::Changed => current != previous,
+            SearchFilter::Increased => current > previous,
+            SearchFilter::Decreased => current < previous,
+            SearchFilter::Value(value) => {
+                let mask = if format.word { 0xFFFF } else { 0xFF };
+                current & mask == value & mask
+            },
+        }
+    }
+}
+
+/// Candidate of a search with its value in the last snapshot and now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+    pub addr: usize,
+    pub previous: i64,
+    pub current: i64,
+}
+
+/// Class representing a RAM search, every filter narrows the candidates and takes a new snapshot
+#[derive(Debug, Clone)]
+pub struct RamSearch {
+    pub region: MemoryRegion,
+    pub format: SearchFormat,
+    candidates: Vec<usize>,
+    snapshot: Vec<u8>,
+}
+
+impl RamSearch {
+    /// Function that starts a search with every address of the region as a candidate
+    pub fn new(cpu: &CPU, region: MemoryRegion, format: SearchFormat) -> Self {
+        let snapshot = region.bytes(cpu).to_vec();
+        let candidates = (0 ..= snapshot.len() - format.size()).collect();
+
+        RamSearch { region, format, candidates, snapshot }
+    }
+
+    pub fn filter(&mut self, cpu: &CPU, filter: SearchFilter) {
+        let current = self.region.bytes(cpu);
+
+        self.candidates.retain(|offset| {
+            let previous = self.format.value(&self.snapshot, *offset);
+            filter.matches(self.format, previous, self.format.value(current, *offset))
+        });
+        self.snapshot = current.to_vec();
+    }
+
+    pub fn results(&self, cpu: &CPU) -> Vec<SearchResult> {
+        let current = self.region.bytes(cpu);
+
+        self.candidates
+            .iter()
+            .map(|offset| SearchResult {
+                addr: self.region.base() + offset,
+                previous: self.format.value(&self.snapshot, *offset),
+                current: self.format.value(current, *offset),
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::bus::Bus;
+    use crate::render::input::joypad::Joypad;
+    use crate::assembler::assemble;
+    use crate::ppu::cartridge::Cartridge;
+
+    /// Function that assembles a program into an NROM cartridge
+    fn nrom(source: &str) -> Cartridge {
+        let mut prg = vec![0; 0x8000];
+        assemble(source).unwrap().patch(&mut prg, 0x8000).unwrap();
+
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.extend(prg);
+        rom.extend(vec![0; 0x2000]);
+        Cartridge::new(rom).unwrap()
+    }
+
+    fn cpu<'a>() -> CPU<'a> {
+        let rom = nrom(".org $8000\nreset: jmp reset\n.org $FFFA\n.word reset, reset, reset");
+        CPU::new(Bus::new(rom, |_ppu: &PPU, _joy: &mut Joypad| {}))
+    }
+
+    #[test]
+    fn test_regions() {
+        let mut cpu = cpu();
+
+        MemoryRegion::PrgRam.write(&mut cpu, 0x6010, &[1, 2]).unwrap();
+        MemoryRegion::Palette.write(&mut cpu, 0x3F01, &[0xFF]).unwrap();
+        assert_eq!(cpu.bus.peek(0x6011), 2);
+        assert_eq!(MemoryRegion::Palette.read(&cpu, 0x3F01), Ok(0x3F));
+
+        // the backdrop mirrors are shared with the PPU
+        MemoryRegion::Palette.write(&mut cpu, 0x3F10, &[0x21, 0x22]).unwrap();
+        assert_eq!(MemoryRegion::Palette.read(&cpu, 0x3F00), Ok(0x21));
+        assert_eq!(MemoryRegion::Palette.read(&cpu, 0x3F11), Ok(0x22));
+        cpu.bus.write(0x2006, 0x3F);
+        cpu.bus.write(0x2006, 0x1C);
+        cpu.bus.write(0x2007, 0x0F);
+        assert_eq!(MemoryRegion::Palette.read(&cpu, 0x3F0C), Ok(0x0F));
+        assert_eq!(MemoryRegion::Palette.read(&cpu, 0x3F1C), Ok(0x0F));
+
+        assert!(MemoryRegion::Oam.read(&cpu, 0x100).is_err());
+        assert!(MemoryRegion::Ram.write(&mut cpu, 0x7FF, &[1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_search() {
+        let mut cpu = cpu();
+        cpu.bus.write(0x0040, 3);
+        cpu.bus.write(0x0041, 3);
+
+        let format = SearchFormat { word: false, signed: false };
+        let mut search = RamSearch::new(&cpu, MemoryRegion::Ram, format);
+        assert_eq!(search.len(), 2048);
+
+        search.filter(&cpu, SearchFilter::Value(3));
+        assert_eq!(search.len(), 2);
+
+        // lives go down, the other counter stays
+        cpu.bus.write(0x0040, 2);
+        search.filter(&cpu, SearchFilter::Decreased);
+        assert_eq!(search.results(&cpu), vec![SearchResult { addr: 0x40, previous: 2, current: 2 }]);
+    }
+
+    #[test]
+    fn test_search_signed_words() {
+        let mut cpu = cpu();
+        cpu.bus.write_u16(0x6100, 0xFFFE);
+
+        let format = SearchFormat { word: true, signed: true };
+        let mut search = RamSearch::new(&cpu, MemoryRegion::PrgRam, format);
+        search.filter(&cpu, SearchFilter::Value(-2));
+        assert_eq!(search.results(&cpu)[0].addr, 0x6100);
+        assert_eq!(search.results(&cpu)[0].current, -2);
+
+        cpu.bus.write_u16(0x6100, 1);
+        search.filter(&cpu, SearchFilter::Increased);
+        assert_eq!(search.len(), 1);
+
+        search.filter(&cpu, SearchFilter::Changed);
+        assert!(search.is_empty());
+    }
+}
@@ -0,0 +1,5 @@
+pub mod engine;
+pub mod expression;
+pub mod repl;
+pub mod gdb;
+pub mod memory;
@@ -0,0 +1,403 @@
+
+use crate::cpu::bus::AddressSpace;
+use crate::cpu::cpu::CPU;
+use crate::debugger::engine::{Breakpoint, Debugger, Watchpoint};
+use crate::debugger::expression::Expression;
+use crate::debugger::memory::{MemoryRegion, RamSearch, SearchFilter, SearchFormat};
+use crate::disassembler::{disassemble_one, Symbols};
+use std::io::{BufRead, BufReader, Stdin, Stdout, Write};
+
+const HELP: &str = "Commands:
+  c, continue                  run until a breakpoint
+  s, step                      execute one instruction
+  n, next                      step over subroutine calls
+  finish                       run until the current subroutine returns
+  sl, scanline <n>             run until the PPU reaches the scanline
+  b, break <addr> [if <expr>]  add a breakpoint, e.g. b $C000 if A == #$10 && [$0300] > 3
+  w, watch <r|w|rw|x> [cpu|ppu] <addr>[-<end>]
+                               add a watchpoint on a range of addresses
+  d, delete <b|w> <index>      remove a breakpoint or watchpoint
+  nmi <on|off>                 stop when an NMI is serviced
+  irq <on|off>                 stop when the IRQ/BRK handler is entered
+  l, list                      list breakpoints and watchpoints
+  r, regs                      show the registers
+  x, mem [cpu|ppu|<region>] <addr> [len]
+                               dump memory, regions are ram, prg, vram, oam and pal
+  poke [cpu|<region>] <addr> <byte>...
+                               write bytes to memory
+  search <region> [8|16] [u|s] start a RAM search with every address as a candidate
+  search <same|changed|inc|dec>
+                               keep the candidates compared with the last search
+  search = <value>             keep the candidates equal to the value
+  search                       list the candidates
+  cheat [list]                 list the cheats
+  cheat add <code> [name]      add a Game Genie (SXIOPO) or RAM (075A:09) cheat
+  cheat <on|off|del> <index>   enable, disable or remove a cheat
+  cheat save                   store the cheats of this game in cheats/<rom hash>.cht
+  p, print <expr>              evaluate an expression
+  q, quit                      exit the emulator";
+
+/// What the REPL does after a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Wait for the next command
+    Stay,
+
+    /// Resume the execution
+    Resume,
+
+    /// Exit the emulator
+    Quit,
+}
+
+/// Class representing the terminal interface of the debugger
+/// `hook` is meant to be called from `CPU::interpret_callback`
+pub struct Repl<R: BufRead, W: Write> {
+    pub debugger: Debugger,
+    search: Option<RamSearch>,
+    input: R,
+    output: W,
+}
+
+impl Repl<BufReader<Stdin>, Stdout> {
+    /// Create a REPL reading commands from stdin
+    pub fn stdio(debugger: Debugger) -> Self {
+        Repl::new(debugger, BufReader::new(std::io::stdin()), std::io::stdout())
+    }
+}
+
+impl<R: BufRead, W: Write> Repl<R, W> {
+    pub fn new(debugger: Debugger, input: R, output: W) -> Self {
+        Repl { debugger, search: None, input, output }
+    }
+
+    /// Function that checks the debugger and reads commands while stopped
+    /// Returns false when the user wants to quit
+    pub fn hook(&mut self, cpu: &mut CPU) -> bool {
+        let reason = match self.debugger.check(cpu) {
+            Some(reason) => reason,
+            None => return true,
+        };
+
+        let _ = writeln!(self.output, "Stopped: {}", reason);
+        let _ = writeln!(self.output, "{}", location(cpu));
+
+        loop {
+            let _ = write!(self.output, "(dbg) ");
+            let _ = self.output.flush();
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line).unwrap_or(0) == 0 {
+                return false;
+            }
+
+            match self.execute(line.trim(), cpu) {
+                Ok(Action::Stay) => {},
+                Ok(Action::Resume) => {
+                    cpu.bus.track_accesses(!self.debugger.watchpoints.is_empty());
+                    return true;
+                },
+                Ok(Action::Quit) => return false,
+                Err(err) => {
+                    let _ = writeln!(self.output, "Error: {}", err);
+                },
+            }
+        }
+    }
+
+    /// Function that executes a single command
+    pub fn execute(&mut self, line: &str, cpu: &mut CPU) -> Result<Action, String> {
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+        let words: Vec<&str> = args.split_whitespace().collect();
+
+        match command {
+            "" => Ok(Action::Stay),
+            "h" | "help" => self.print(HELP),
+            "c" | "continue" => {
+                self.debugger.resume();
+                Ok(Action::Resume)
+            },
+            "s" | "step" => {
+                self.debugger.step_into();
+                Ok(Action::Resume)
+            },
+            "n" | "next" => {
+                self.debugger.step_over(cpu);
+                Ok(Action::Resume)
+            },
+            "finish" => {
+                self.debugger.step_out(cpu);
+                Ok(Action::Resume)
+            },
+            "sl" | "scanline" => {
+                let scanline = number(words.first())?;
+                self.debugger.run_to_scanline(scanline as u16);
+                Ok(Action::Resume)
+            },
+            "b" | "break" => {
+                let (addr, condition) = match args.split_once(" if ") {
+                    Some((addr, condition)) => (addr.trim(), Some(Expression::parse(condition)?)),
+                    None => (args, None),
+                };
+                let addr = number(Some(&addr))? as u16;
+
+                self.debugger.breakpoints.push(Breakpoint { addr, condition });
+                self.print(&format!("Breakpoint {} at ${:04X}", self.debugger.breakpoints.len() - 1, addr))
+            },
+            "w" | "watch" => {
+                let watchpoint = parse_watchpoint(&words)?;
+                self.debugger.watchpoints.push(watchpoint);
+                self.print(&format!("Watchpoint {}", self.debugger.watchpoints.len() - 1))
+            },
+            "d" | "delete" => {
+                let index = number(words.get(1))? as usize;
+                let removed = match words.first() {
+                    Some(&"b") if index < self.debugger.breakpoints.len() => {
+                        self.debugger.breakpoints.remove(index);
+                        true
+                    },
+                    Some(&"w") if index < self.debugger.watchpoints.len() => {
+                        self.debugger.watchpoints.remove(index);
+                        true
+                    },
+                    _ => false,
+                };
+
+                if removed { Ok(Action::Stay) } else { Err("no such breakpoint or watchpoint".to_string()) }
+            },
+            "nmi" => {
+                self.debugger.break_on_nmi = toggle(words.first())?;
+                Ok(Action::Stay)
+            },
+            "irq" => {
+                self.debugger.break_on_irq = toggle(words.first())?;
+                Ok(Action::Stay)
+            },
+            "l" | "list" => {
+                let mut res = String::new();
+                for (i, breakpoint) in self.debugger.breakpoints.iter().enumerate() {
+                    res.push_str(&format!("b {}: ${:04X}", i, breakpoint.addr));
+                    if breakpoint.condition.is_some() {
+                        res.push_str(" (conditional)");
+                    }
+                    res.push('\n');
+                }
+                for (i, watch) in self.debugger.watchpoints.iter().enumerate() {
+                    res.push_str(&format!(
+                        "w {}: {}{}{} {:?} ${:04X}-${:04X}\n",
+                        i,
+                        if watch.read { "r" } else { "" },
+                        if watch.write { "w" } else { "" },
+                        if watch.execute { "x" } else { "" },
+                        watch.space,
+                        watch.start,
+                        watch.end,
+                    ));
+                }
+                self.print(res.trim_end())
+            },
+            "r" | "regs" => self.print(&location(cpu)),
+            "x" | "mem" => {
+                let (space, region, words) = match words.first() {
+                    Some(&"ppu") => (AddressSpace::PPU, None, &words[1 ..]),
+                    Some(&"cpu") => (AddressSpace::CPU, None, &words[1 ..]),
+                    Some(word) if MemoryRegion::from_name(word).is_some() => {
+                        (AddressSpace::CPU, MemoryRegion::from_name(word), &words[1 ..])
+                    },
+                    _ => (AddressSpace::CPU, None, &words[..]),
+                };
+                let start = number(words.first())? as u16;
+                let len = words.get(1).map_or(Ok(16), |len| number(Some(len)))? as u16;
+
+                let mut res = String::new();
+                for row in (0 .. len).step_by(16) {
+                    let addr = start.wrapping_add(row);
+                    res.push_str(&format!("{:04X}:", addr));
+                    for i in 0 .. (len - row).min(16) {
+                        let addr = addr.wrapping_add(i);
+                        let val = match (region, space) {
+                            (Some(region), _) => region.read(cpu, addr as usize)?,
+                            (None, AddressSpace::CPU) => cpu.bus.peek(addr),
+                            (None, AddressSpace::PPU) => cpu.bus.ppu().peek(addr),
+                        };
+                        res.push_str(&format!(" {:02X}", val));
+                    }
+                    res.push('\n');
+                }
+                self.print(res.trim_end())
+            },
+            "poke" => {
+                let (region, words) = match words.first() {
+                    Some(&"cpu") => (None, &words[1 ..]),
+                    Some(word) if MemoryRegion::from_name(word).is_some() => (MemoryRegion::from_name(word), &words[1 ..]),
+                    _ => (None, &words[..]),
+                };
+                let addr = number(words.first())? as u16;
+                let data = words[1 ..].iter().map(|word| number(Some(word)).map(|val| val as u8)).collect::<Result<Vec<u8>, String>>()?;
+                if data.is_empty() {
+                    return Err("missing bytes to write".to_string());
+                }
+
+                // the CPU address space goes through the bus, writing to a register has its usual effect
+                match region {
+                    Some(region) => region.write(cpu, addr as usize, &data)?,
+                    None => data.iter().enumerate().for_each(|(i, val)| cpu.bus.write(addr.wrapping_add(i as u16), *val)),
+                }
+                Ok(Action::Stay)
+            },
+            "search" => {
+                let filter = match words.first() {
+                    None => None,
+                    Some(&"same") => Some(SearchFilter::Unchanged),
+                    Some(&"changed") => Some(SearchFilter::Changed),
+                    Some(&"inc") => Some(SearchFilter::Increased),
+                    Some(&"dec") => Some(SearchFilter::Decreased),
+                    Some(&"=") => Some(SearchFilter::Value(number(words.get(1))?)),
+                    Some(name) => {
+                        let region = MemoryRegion::from_name(name).ok_or(format!("unknown region {}", name))?;
+                        let format = SearchFormat {
+                            word: words.contains(&"16"),
+                            signed: words.contains(&"s"),
+                        };
+                        self.search = Some(RamSearch::new(cpu, region, format));
+                        return self.print(&format!("{} candidates", self.search.as_ref().unwrap().len()));
+                    },
+                };
+
+                let search = self.search.as_mut().ok_or("no search, start one with search <region>")?;
+                if let Some(filter) = filter {
+                    search.filter(cpu, filter);
+                }
+
+                let results = search.results(cpu);
+                let mut res = format!("{} candidates\n", results.len());
+                for result in results.iter().take(20) {
+                    res.push_str(&format!("  ${:04X}: {} -> {}\n", result.addr, result.previous, result.current));
+                }
+                if results.len() > 20 {
+                    res.push_str("  ...\n");
+                }
+                self.print(res.trim_end())
+            },
+            "cheat" => {
+                let rom_hash = cpu.bus.rom_hash();
+                let cheats = cpu.bus.cheats_mut();
+                let count = cheats.cheats.len();
+                let index = || -> Result<usize, String> {
+                    let index = number(words.get(1))? as usize;
+                    match index < count {
+                        true => Ok(index),
+                        false => Err(format!("no cheat {}", index)),
+                    }
+                };
+
+                match words.first() {
+                    None | Some(&"list") => {},
+                    Some(&"add") => {
+                        let code = words.get(1).ok_or("missing code")?;
+                        cheats.add(code, &words[2 ..].join(" "))?;
+                    },
+                    Some(&"on") => cheats.cheats[index()?].enabled = true,
+                    Some(&"off") => cheats.cheats[index()?].enabled = false,
+                    Some(&"del") => {
+                        cheats.cheats.remove(index()?);
+                    },
+                    Some(&"save") => {
+                        let path = cheats.save(rom_hash)?;
+                        return self.print(&format!("Saved to {}", path));
+                    },
+                    Some(other) => return Err(format!("unknown cheat command {}", other)),
+                }
+
+                let mut res = String::new();
+                for (i, cheat) in cheats.cheats.iter().enumerate() {
+                    let state = if cheat.enabled { "on " } else { "off" };
+                    res.push_str(&format!("{}: {} {:9} {:28} {}\n", i, state, cheat.code, cheat.kind.to_string(), cheat.name));
+                }
+                if res.is_empty() {
+                    res.push_str("no cheats");
+                }
+                self.print(res.trim_end())
+            },
+            "p" | "print" => {
+                let val = Expression::parse(args)?.eval(cpu);
+                self.print(&format!("{} (${:X})", val, val))
+            },
+            "q" | "quit" => Ok(Action::Quit),
+            _ => Err(format!("unknown command {}, type help for a list", command)),
+        }
+    }
+
+    fn print(&mut self, text: &str) -> Result<Action, String> {
+        writeln!(self.output, "{}", text).map_err(|err| err.to_string())?;
+        Ok(Action::Stay)
+    }
+}
+
+/// Function that formats the registers and the instruction at PC
+fn location(cpu: &CPU) -> String {
+    let pc = cpu.prog_counter;
+    let data: Vec<u8> = (0 .. 3).map(|i| cpu.bus.peek(pc.wrapping_add(i))).collect();
+    let ins = disassemble_one(&data, 0, pc);
+    let bytes: Vec<String> = ins.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    let instruction = format!("{:8}  {:13}", bytes.join(" "), ins.format(&Symbols::default()));
+
+    format!(
+        "{:04X}  {} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} SL:{} DOT:{} CYC:{}",
+        pc,
+        instruction,
+        cpu.a.value(),
+        cpu.x.value(),
+        cpu.y.value(),
+        cpu.status.value,
+        cpu.stack_pointer,
+        cpu.bus.ppu().scanline(),
+        cpu.bus.ppu().dot(),
+        cpu.bus.cycles,
+    )
+}
+
+fn number(word: Option<&&str>) -> Result<i64, String> {
+    let word = word.ok_or("missing number")?;
+    Expression::parse_number(word).ok_or(format!("invalid number {}", word))
+}
+
+fn toggle(word: Option<&&str>) -> Result<bool, String> {
+    match word {
+        Some(&"on") => Ok(true),
+        Some(&"off") => Ok(false),
+        _ => Err("expected on or off".to_string()),
+    }
+}
+
+/// Function that parses "<r|w|rw|x> [cpu|ppu] <addr>[-<end>]"
+fn parse_watchpoint(words: &[&str]) -> Result<Watchpoint, String> {
+    let kind = words.first().ok_or("missing watchpoint kind")?;
+    if kind.is_empty() || !kind.chars().all(|c| "rwx".contains(c)) {
+        return Err(format!("invalid watchpoint kind {}", kind));
+    }
+
+    let (space, range) = match words.get(1) {
+        Some(&"ppu") => (AddressSpace::PPU, words.get(2)),
+        Some(&"cpu") => (AddressSpace::CPU, words.get(2)),
+        _ => (AddressSpace::CPU, words.get(1)),
+    };
+    let range = range.ok_or("missing address")?;
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+
+    let watchpoint = Watchpoint {
+        space,
+        start: number(Some(&start))? as u16,
+        end: number(Some(&end))? as u16,
+        read: kind.contains('r'),
+        write: kind.contains('w'),
+        execute: kind.contains('x'),
+    };
+
+    if watchpoint.execute && space == AddressSpace::PPU {
+        return Err("execute watchpoints are only supported in the CPU address space".to_string());
+    }
+
+    Ok(watchpoint)
+}
@@ -0,0 +1,285 @@
+// https://www.nesdev.org/obelisk-6502-guide/addressing.html
+//
+// 6502 disassembler working on plain bytes, without a running CPU
+
+use crate::cpu::addressing::Addressing;
+use crate::cpu::instructions::{OpName, INSTRUCTION_MAP};
+use std::collections::{BTreeMap, HashMap};
+
+/// Operand of a disassembled instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Implied,
+    Accumulator,
+    Immediate(u8),
+    ZeroPage(u8),
+    ZeroPageX(u8),
+    ZeroPageY(u8),
+    Absolute(u16),
+    AbsoluteX(u16),
+    AbsoluteY(u16),
+    Indirect(u16),
+    IndirectX(u8),
+    IndirectY(u8),
+
+    /// Branch, holding the resolved target address
+    Relative(u16),
+}
+
+/// Class representing a single disassembled instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disassembled {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+
+    /// Mnemonic, or None for a byte that is not a known opcode
+    pub mnemonic: Option<String>,
+    pub operand: Operand,
+}
+
+impl Disassembled {
+    /// Number of bytes of the instruction
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Address the instruction jumps, branches or calls to
+    pub fn target(&self) -> Option<u16> {
+        match (self.mnemonic.as_deref(), self.operand) {
+            (_, Operand::Relative(target)) => Some(target),
+            (Some("JMP") | Some("JSR"), Operand::Absolute(target)) => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Checks if the execution never continues with the next instruction
+    pub fn ends_flow(&self) -> bool {
+        matches!(self.mnemonic.as_deref(), None | Some("JMP") | Some("RTS") | Some("RTI") | Some("BRK"))
+    }
+
+    /// Function that formats the instruction, replacing addresses with labels when known
+    pub fn format(&self, symbols: &Symbols) -> String {
+        let mnemonic = match &self.mnemonic {
+            Some(mnemonic) => mnemonic,
+            None => return format!(".db ${:02X}", self.bytes[0]),
+        };
+
+        let addr = |addr: u16, width: usize| match symbols.get(addr) {
+            Some(label) => label.to_string(),
+            None => format!("${:0width$X}", addr, width = width),
+        };
+
+        let operand = match self.operand {
+            Operand::Implied => String::new(),
+            Operand::Accumulator => "A".to_string(),
+            Operand::Immediate(val) => format!("#${:02X}", val),
+            Operand::ZeroPage(val) => addr(val as u16, 2),
+            Operand::ZeroPageX(val) => format!("{},X", addr(val as u16, 2)),
+            Operand::ZeroPageY(val) => format!("{},Y", addr(val as u16, 2)),
+            Operand::Absolute(val) | Operand::Relative(val) => addr(val, 4),
+            Operand::AbsoluteX(val) => format!("{},X", addr(val, 4)),
+            Operand::AbsoluteY(val) => format!("{},Y", addr(val, 4)),
+            Operand::Indirect(val) => format!("({})", addr(val, 4)),
+            Operand::IndirectX(val) => format!("({},X)", addr(val as u16, 2)),
+            Operand::IndirectY(val) => format!("({}),Y", addr(val as u16, 2)),
+        };
+
+        format!("{} {}", mnemonic, operand).trim_end().to_string()
+    }
+}
+
+/// Class representing labels loaded from a symbol file
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    labels: HashMap<u16, String>,
+}
+
+impl Symbols {
+    /// Function that parses FCEUX (.nl, "$C000#label#comment") and ld65 -Ln ("al 00C000 .label") symbol files
+    pub fn parse(text: &str) -> Symbols {
+        let mut symbols = Symbols::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+
+            let parsed = if let Some(rest) = line.strip_prefix('$') {
+                let mut fields = rest.split('#');
+                let addr = fields.next().and_then(|addr| u16::from_str_radix(addr, 16).ok());
+                addr.zip(fields.next())
+            } else if let Some(rest) = line.strip_prefix("al ") {
+                let mut fields = rest.split_whitespace();
+                let addr = fields.next().and_then(|addr| u32::from_str_radix(addr, 16).ok());
+                addr.map(|addr| addr as u16).zip(fields.next().map(|label| label.trim_start_matches('.')))
+            } else {
+                None
+            };
+
+            if let Some((addr, label)) = parsed {
+                if !label.is_empty() {
+                    symbols.insert(addr, label);
+                }
+            }
+        }
+
+        symbols
+    }
+
+    pub fn insert(&mut self, addr: u16, label: &str) {
+        self.labels.insert(addr, label.to_string());
+    }
+
+    pub fn get(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(|label| label.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+/// Function that disassembles the instruction at offset, data starts at base in the address space
+pub fn disassemble_one(data: &[u8], offset: usize, base: u16) -> Disassembled {
+    let addr = base.wrapping_add(offset as u16);
+    let code = data[offset];
+
+    let ins = match INSTRUCTION_MAP.get(&code) {
+        Some(ins) if offset + ins.bytes as usize <= data.len() => ins,
+        _ => {
+            return Disassembled { addr, bytes: vec![code], mnemonic: None, operand: Operand::Implied };
+        },
+    };
+
+    let bytes = data[offset .. offset + ins.bytes as usize].to_vec();
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or(0)]);
+
+    let operand = match (&ins.mode, ins.bytes) {
+        (Addressing::Immediate, _) => Operand::Immediate(byte),
+        (Addressing::ZeroPage, _) => Operand::ZeroPage(byte),
+        (Addressing::ZeroPageX, _) => Operand::ZeroPageX(byte),
+        (Addressing::ZeroPageY, _) => Operand::ZeroPageY(byte),
+        (Addressing::Absolute, _) => Operand::Absolute(word),
+        (Addressing::AbsoluteX, _) => Operand::AbsoluteX(word),
+        (Addressing::AbsoluteY, _) => Operand::AbsoluteY(word),
+        (Addressing::IndirectX, _) => Operand::IndirectX(byte),
+        (Addressing::IndirectY, _) => Operand::IndirectY(byte),
+
+        // the remaining modes are implied by the instruction
+        (Addressing::None, 2) => Operand::Relative(addr.wrapping_add(2).wrapping_add(byte as i8 as u16)),
+        (Addressing::None, 3) if matches!(ins.name, OpName::JMP_IND) => Operand::Indirect(word),
+        (Addressing::None, 3) => Operand::Absolute(word),
+        (Addressing::None, _) if matches!(ins.name, OpName::ASL_A | OpName::LSR_A | OpName::ROL_A | OpName::ROR_A) => {
+            Operand::Accumulator
+        },
+        (Addressing::None, _) => Operand::Implied,
+    };
+
+    Disassembled {
+        addr,
+        bytes,
+        mnemonic: Some(ins.name.to_string()),
+        operand,
+    }
+}
+
+/// Function that disassembles all of the data from the start to the end
+pub fn disassemble(data: &[u8], base: u16) -> Vec<Disassembled> {
+    let mut res = vec![];
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let ins = disassemble_one(data, offset, base);
+        offset += ins.len();
+        res.push(ins);
+    }
+
+    res
+}
+
+/// Function that disassembles only the code reachable from the entry points,
+/// following branches, jumps and subroutine calls
+pub fn disassemble_reachable(data: &[u8], base: u16, entries: &[u16]) -> Vec<Disassembled> {
+    let mut res: BTreeMap<u16, Disassembled> = BTreeMap::new();
+    let mut pending: Vec<u16> = entries.to_vec();
+    let end = base as usize + data.len();
+
+    while let Some(mut addr) = pending.pop() {
+        while (base as usize .. end).contains(&(addr as usize)) && !res.contains_key(&addr) {
+            let ins = disassemble_one(data, (addr - base) as usize, base);
+
+            if let Some(target) = ins.target() {
+                pending.push(target);
+            }
+
+            let next = addr.wrapping_add(ins.len() as u16);
+            let ends_flow = ins.ends_flow();
+            res.insert(addr, ins);
+
+            if ends_flow || next < addr {
+                break;
+            }
+            addr = next;
+        }
+    }
+
+    res.into_values().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_disassemble_modes() {
+        let code = [
+            0xA9, 0x10,         // LDA #$10
+            0xB5, 0x20,         // LDA $20,X
+            0xBE, 0x00, 0x03,   // LDX $0300,Y
+            0x6C, 0xFC, 0xFF,   // JMP ($FFFC)
+            0xB1, 0x02,         // LDA ($02),Y
+            0x0A,               // ASL A
+            0xD0, 0xF1,         // BNE $C000
+            0x02,               // unknown
+        ];
+        let symbols = Symbols::default();
+        let lines: Vec<String> = disassemble(&code, 0xC000).iter().map(|ins| ins.format(&symbols)).collect();
+
+        assert_eq!(lines, vec![
+            "LDA #$10", "LDA $20,X", "LDX $0300,Y", "JMP ($FFFC)", "LDA ($02),Y", "ASL A", "BNE $C000", ".db $02",
+        ]);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let symbols = Symbols::parse("$C000#Reset#entry point\nal 000010 .counter\n# comment\n");
+        assert_eq!(symbols.get(0xC000), Some("Reset"));
+        assert_eq!(symbols.get(0x0010), Some("counter"));
+
+        let code = [0xE6, 0x10, 0x4C, 0x00, 0xC0];
+        let lines: Vec<String> = disassemble(&code, 0xC000).iter().map(|ins| ins.format(&symbols)).collect();
+        assert_eq!(lines, vec!["INC counter", "JMP Reset"]);
+    }
+
+    #[test]
+    fn test_reachable() {
+        let code = [
+            0x20, 0x08, 0x80,   // $8000 JSR $8008
+            0x4C, 0x00, 0x80,   // $8003 JMP $8000
+            0xFF, 0xFF,         // $8006 data
+            0xF0, 0x01,         // $8008 BEQ $800B
+            0x60,               // $800A RTS
+            0x40,               // $800B RTI
+        ];
+
+        let addrs: Vec<u16> = disassemble_reachable(&code, 0x8000, &[0x8000]).iter().map(|ins| ins.addr).collect();
+        assert_eq!(addrs, vec![0x8000, 0x8003, 0x8008, 0x800A, 0x800B]);
+    }
+}
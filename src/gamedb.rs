@@ -0,0 +1,218 @@
+// https://www.nesdev.org/wiki/NES_2.0_XML_Database
+// https://www.nesdev.org/wiki/INES#Bad_headers
+//
+// Database of known dumps, used to fix the headers of ROMs that have wrong or garbage values
+// [key] => CRC-32 or SHA-1 of the PRG-ROM followed by the CHR-ROM
+// [entry] => the fields the header should have, anything not listed is taken from the header
+
+use crate::hash::{crc32, sha1};
+use crate::ppu::mirroring::Mirroring;
+use crate::region::Region;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+
+lazy_static! {
+    /// Database built into the emulator
+    pub static ref EMBEDDED: GameDb = GameDb::parse(include_str!("../assets/gamedb.txt")).unwrap();
+}
+
+/// Class representing what the database knows about a game
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GameEntry {
+    pub title: Option<String>,
+    pub mapper: Option<u16>,
+    pub submapper: Option<u8>,
+    pub mirroring: Option<Mirroring>,
+    pub battery: Option<bool>,
+    pub prg_ram: Option<usize>,
+    pub chr_ram: Option<usize>,
+    pub region: Option<Region>,
+}
+
+/// Class representing the game database
+#[derive(Debug, Clone, Default)]
+pub struct GameDb {
+    crc: HashMap<u32, GameEntry>,
+    sha1: HashMap<[u8; 20], GameEntry>,
+}
+
+/// Function that parses a mirroring name used in the database
+fn mirroring(name: &str) -> Option<Mirroring> {
+    match name {
+        "horizontal" | "h" => Some(Mirroring::Horizontal),
+        "vertical" | "v" => Some(Mirroring::Vertical),
+        "four" | "4" => Some(Mirroring::FourScreen),
+        "single-a" => Some(Mirroring::SingleScreenA),
+        "single-b" => Some(Mirroring::SingleScreenB),
+        _ => None,
+    }
+}
+
+impl GameDb {
+    pub fn new() -> Self {
+        GameDb::default()
+    }
+
+    /// Function that parses a database, one "<hash> field=value... [title=name]" per line
+    pub fn parse(text: &str) -> Result<GameDb, String> {
+        let mut db = GameDb::new();
+        db.extend(text)?;
+        Ok(db)
+    }
+
+    /// Function that adds the entries of a database, replacing the ones with the same hash
+    pub fn extend(&mut self, text: &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: String| format!("line {}: {}", i + 1, message);
+
+            // the title is the rest of the line and may contain spaces
+            let (fields, title) = match line.split_once("title=") {
+                Some((fields, title)) => (fields, Some(title.trim().to_string())),
+                None => (line, None),
+            };
+
+            let mut fields = fields.split_whitespace();
+            let key = fields.next().unwrap_or_default();
+            let mut entry = GameEntry { title, ..GameEntry::default() };
+
+            for field in fields {
+                let (name, value) = field.split_once('=').ok_or(error(format!("expected field=value, got {}", field)))?;
+                let invalid = || error(format!("invalid {} {}", name, value));
+
+                match name {
+                    "mapper" => entry.mapper = Some(value.parse().map_err(|_| invalid())?),
+                    "submapper" => entry.submapper = Some(value.parse().map_err(|_| invalid())?),
+                    "mirroring" => entry.mirroring = Some(mirroring(value).ok_or_else(invalid)?),
+                    "battery" => entry.battery = Some(value.parse::<u8>().map_err(|_| invalid())? != 0),
+                    "prg_ram" => entry.prg_ram = Some(value.parse().map_err(|_| invalid())?),
+                    "chr_ram" => entry.chr_ram = Some(value.parse().map_err(|_| invalid())?),
+                    "region" => entry.region = Some(Region::from_name(value).ok_or_else(invalid)?),
+                    _ => return Err(error(format!("unknown field {}", name))),
+                }
+            }
+
+            let bytes: Vec<u8> = (0 .. key.len() / 2)
+                .map(|i| key.get(i * 2 .. i * 2 + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .ok_or(error(format!("invalid hash {}", key)))?;
+
+            match bytes.len() {
+                4 if key.len() == 8 => {
+                    self.crc.insert(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]), entry);
+                },
+                20 if key.len() == 40 => {
+                    self.sha1.insert(bytes.try_into().unwrap(), entry);
+                },
+                _ => return Err(error(format!("expected a CRC-32 or SHA-1, got {}", key))),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Function that finds the entry of a game by its PRG-ROM and CHR-ROM
+    pub fn lookup(&self, prg_rom: &[u8], chr_rom: &[u8]) -> Option<&GameEntry> {
+        let data = [prg_rom, chr_rom].concat();
+
+        if !self.sha1.is_empty() {
+            if let Some(entry) = self.by_sha1(&sha1(&data)) {
+                return Some(entry);
+            }
+        }
+        self.by_crc32(crc32(&data))
+    }
+
+    pub fn by_crc32(&self, crc: u32) -> Option<&GameEntry> {
+        self.crc.get(&crc)
+    }
+
+    pub fn by_sha1(&self, hash: &[u8; 20]) -> Option<&GameEntry> {
+        self.sha1.get(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.crc.len() + self.sha1.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hash::to_hex;
+
+    #[test]
+    fn test_embedded() {
+        assert_eq!(EMBEDDED.len(), GameDb::parse(include_str!("../assets/gamedb.txt")).unwrap().len());
+        assert!(!EMBEDDED.is_empty());
+
+        // Super Mario Bros. (World), No-Intro CRC-32 and SHA-1 of the headerless ROM
+        let sha1: Vec<u8> = (0 .. 20)
+            .map(|i| u8::from_str_radix(&"ea343f4e445a9050d4b4fbac2c77d0693b1d0922"[i * 2 .. i * 2 + 2], 16).unwrap())
+            .collect();
+        for entry in [EMBEDDED.by_crc32(0x3337EC46), EMBEDDED.by_sha1(&sha1.try_into().unwrap())] {
+            let entry = entry.unwrap();
+            assert_eq!(entry.mapper, Some(0));
+            assert_eq!(entry.mirroring, Some(Mirroring::Vertical));
+            assert_eq!(entry.title.as_deref(), Some("Super Mario Bros. (World)"));
+        }
+
+        let zelda = EMBEDDED.by_crc32(0x3FE272FB).unwrap();
+        assert_eq!(zelda.mapper, Some(1));
+        assert_eq!(zelda.battery, Some(true));
+        assert_eq!(zelda.chr_ram, Some(0x2000));
+
+        // every field the database corrects is set by a built-in entry
+        let entries: Vec<&GameEntry> = EMBEDDED.crc.values().collect();
+        assert!(entries.iter().any(|entry| entry.mapper.is_some_and(|mapper| mapper > 0)));
+        assert!(entries.iter().any(|entry| entry.submapper.is_some_and(|submapper| submapper > 0)));
+        assert!(entries.iter().any(|entry| entry.mirroring.is_some()));
+        assert!(entries.iter().any(|entry| entry.battery == Some(true)));
+        assert!(entries.iter().any(|entry| entry.prg_ram.is_some()));
+        assert!(entries.iter().any(|entry| entry.chr_ram.is_some()));
+        assert!(entries.iter().any(|entry| entry.region == Some(Region::PAL)));
+        assert!(entries.iter().all(|entry| entry.title.is_some()));
+    }
+
+    #[test]
+    fn test_parse_and_lookup() {
+        let prg = [1u8; 16];
+        let chr = [2u8; 8];
+        let data = [&prg[..], &chr[..]].concat();
+
+        let text = format!(
+            "# comment\n\n{:08x} mapper=4 mirroring=v battery=1 title=Some Game (U)\n{} region=pal\n",
+            crc32(&data),
+            to_hex(&sha1(&prg)),
+        );
+        let db = GameDb::parse(&text).unwrap();
+        assert_eq!(db.len(), 2);
+
+        let entry = db.lookup(&prg, &chr).unwrap();
+        assert_eq!(entry.mapper, Some(4));
+        assert_eq!(entry.mirroring, Some(Mirroring::Vertical));
+        assert_eq!(entry.battery, Some(true));
+        assert_eq!(entry.title.as_deref(), Some("Some Game (U)"));
+
+        // the SHA-1 is of the PRG alone here, a game without CHR-ROM
+        assert_eq!(db.lookup(&prg, &[]).unwrap().region, Some(Region::PAL));
+        assert!(db.lookup(&chr, &prg).is_none());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(GameDb::parse("1234567 mapper=1").is_err());
+        assert!(GameDb::parse("12345678 mapper=x").is_err());
+        assert!(GameDb::parse("12345678 colour=red").is_err());
+        assert!(GameDb::parse("12345678 mirroring=diagonal").is_err());
+        assert!(GameDb::parse("12345678 mapper").is_err());
+    }
+}
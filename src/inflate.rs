@@ -0,0 +1,277 @@
+// https://www.rfc-editor.org/rfc/rfc1951
+// https://github.com/madler/zlib/blob/master/contrib/puff/puff.c
+//
+// Decoder for raw DEFLATE streams, used to load zipped and gzipped ROMs
+// [block header] => 1 bit last block flag, 2 bits type (stored, fixed Huffman, dynamic Huffman)
+
+/// Base lengths and extra bits of the length codes 257 - 285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+
+/// Base distances and extra bits of the distance codes 0 - 29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Order in which the code length code lengths are stored in a dynamic block
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const MAX_BITS: usize = 15;
+
+/// Class reading the stream bit by bit, least significant bit first
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn bit(&mut self) -> Result<u16, &'static str> {
+        let byte = *self.data.get(self.pos).ok_or("compressed data is truncated")?;
+        let res = (byte >> self.bit) & 1;
+
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Ok(res as u16)
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u16, &'static str> {
+        let mut res = 0;
+        for i in 0 .. count {
+            res |= self.bit()? << i;
+        }
+        Ok(res)
+    }
+
+    /// Function that skips to the next byte boundary, stored blocks start there
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        let res = self.data.get(self.pos .. self.pos + len).ok_or("compressed data is truncated")?;
+        self.pos += len;
+        Ok(res)
+    }
+}
+
+/// Class representing a canonical Huffman code, the symbols sorted by code length
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, &'static str> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for len in lengths {
+            counts[*len as usize] += 1;
+        }
+
+        // a code with more codes of a length than there are bit patterns is invalid
+        let mut left: i32 = 1;
+        for count in &counts[1 ..] {
+            left = left * 2 - *count as i32;
+            if left < 0 {
+                return Err("invalid Huffman code");
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1 ..= MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, len) in lengths.iter().enumerate().filter(|(_, len)| **len != 0) {
+            symbols[offsets[*len as usize] as usize] = symbol as u16;
+            offsets[*len as usize] += 1;
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, &'static str> {
+        let (mut code, mut first, mut index): (i32, i32, i32) = (0, 0, 0);
+
+        for len in 1 ..= MAX_BITS {
+            code |= reader.bit()? as i32;
+            let count = self.counts[len] as i32;
+
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err("invalid Huffman code in the data")
+    }
+}
+
+/// Function that decompresses a raw DEFLATE stream
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut reader = BitReader { data, pos: 0, bit: 0 };
+    let mut res = vec![];
+
+    loop {
+        let last = reader.bit()? == 1;
+
+        match reader.bits(2)? {
+            0 => stored(&mut reader, &mut res)?,
+            1 => {
+                let (literals, distances) = fixed()?;
+                codes(&mut reader, &mut res, &literals, &distances)?;
+            },
+            2 => {
+                let (literals, distances) = dynamic(&mut reader)?;
+                codes(&mut reader, &mut res, &literals, &distances)?;
+            },
+            _ => return Err("invalid block type"),
+        }
+
+        if last {
+            return Ok(res);
+        }
+    }
+}
+
+fn stored(reader: &mut BitReader, res: &mut Vec<u8>) -> Result<(), &'static str> {
+    reader.align();
+
+    let header = reader.bytes(4)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+    if len != !nlen {
+        return Err("stored block length is corrupted");
+    }
+
+    res.extend_from_slice(reader.bytes(len as usize)?);
+    Ok(())
+}
+
+/// Function that builds the fixed Huffman codes defined by the RFC
+fn fixed() -> Result<(Huffman, Huffman), &'static str> {
+    let mut lengths = [0u8; 288];
+    lengths[0 .. 144].fill(8);
+    lengths[144 .. 256].fill(9);
+    lengths[256 .. 280].fill(7);
+    lengths[280 .. 288].fill(8);
+
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+/// Function that reads the Huffman codes stored at the start of a dynamic block
+fn dynamic(reader: &mut BitReader) -> Result<(Huffman, Huffman), &'static str> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for index in &CODE_LENGTH_ORDER[.. code_count] {
+        code_lengths[*index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    // the lengths of both codes are one sequence, runs may cross from one into the other
+    let mut lengths = vec![];
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(reader)? {
+            len @ 0 ..= 15 => (len as u8, 1),
+            16 => (*lengths.last().ok_or("repeat without a previous length")?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+
+    if lengths.len() > literal_count + distance_count {
+        return Err("too many code lengths");
+    }
+    if lengths[256] == 0 {
+        return Err("missing end of block code");
+    }
+
+    Ok((Huffman::new(&lengths[.. literal_count])?, Huffman::new(&lengths[literal_count ..])?))
+}
+
+/// Function that decodes literals and back references until the end of the block
+fn codes(reader: &mut BitReader, res: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), &'static str> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+
+        match symbol {
+            0 ..= 255 => res.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err("invalid length code");
+                }
+                let len = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index])? as usize;
+
+                let index = distances.decode(reader)? as usize;
+                if index >= DIST_BASE.len() {
+                    return Err("invalid distance code");
+                }
+                let dist = DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index])? as usize;
+                if dist > res.len() {
+                    return Err("distance points before the start of the data");
+                }
+
+                // byte by byte, the copy can overlap the bytes it produces
+                let start = res.len() - dist;
+                for i in 0 .. len {
+                    res.push(res[start + i]);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hash::crc32;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0 .. hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i .. i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_stored_and_fixed() {
+        assert_eq!(inflate(&from_hex("010600f9ff73746f726564")), Ok(b"stored".to_vec()));
+        assert_eq!(inflate(&from_hex("4b4c4a4e444500")), Ok(b"abcabcabcabcabcabc".to_vec()));
+
+        assert!(inflate(&from_hex("010600f9fe73746f726564")).is_err());
+        assert!(inflate(&from_hex("4b4c4a4e44")).is_err());
+    }
+
+    #[test]
+    fn test_dynamic() {
+        // "0 squared is 0\n" up to "39 squared is 1521\n", compressed by zlib
+        let data = from_hex(concat!(
+            "5dd13b0e02310c04d07e4eb147883ff1c6c74182821210f767b6cb503ab2ec97f1383eafefedfdb81fcfcf31607b69f0bd4cc45e",
+            "36529a0b73af7da2f63a0aa74c6bacbdae44eff532d89005833cf5397b846899b05056c314da05532aad568a678f707d718e80c3b9",
+            "abf58b4c4ccc49b38b39933d1aeb4ab898a7375cccf32cb8988b6617735d3d623eaf39623eaf5d1a333d21e6a639c4dcfc5768ce83",
+            "9f0f0d7a30a1d0a48d31c6d493d11da54f3c4808dc82578bf577596e14ba4ddeff07",
+        ));
+
+        let text = inflate(&data).unwrap();
+        assert_eq!(text.len(), 704);
+        assert_eq!(crc32(&text), 1248335237);
+        assert!(text.starts_with(b"0 squared is 0\n1 squared is 1\n"));
+    }
+}
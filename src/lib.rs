@@ -3,4 +3,20 @@ pub mod ppu;
 pub mod render;
 pub mod byte_status;
 pub mod flags;
-pub mod trace;
\ No newline at end of file
+pub mod trace;
+pub mod disassembler;
+pub mod assembler;
+pub mod region;
+pub mod gamedb;
+pub mod hash;
+pub mod inflate;
+pub mod state;
+pub mod rewind;
+pub mod movie;
+pub mod verify;
+pub mod blargg;
+pub mod cheats;
+pub mod patch;
+pub mod rom_source;
+pub mod rom_info;
+pub mod debugger;
@@ -1,29 +1,130 @@
 extern crate sdl2;
 
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Instant;
+use rust_byte::cheats::Cheats;
 use rust_byte::cpu::bus::Bus;
 use rust_byte::cpu::cpu::CPU;
+use rust_byte::debugger::engine::Debugger;
+use rust_byte::debugger::gdb::GdbStub;
+use rust_byte::debugger::repl::Repl;
 use rust_byte::ppu::cartridge::Cartridge;
 use rust_byte::ppu::ppu::PPU;
 use rust_byte::render::frame::Frame;
 use rust_byte::render::renderer::Renderer;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use rust_byte::render::viewer::{debug_view, VIEW_HEIGHT, VIEW_WIDTH};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::PixelFormatEnum;
 use rust_byte::flags::Button;
+use rust_byte::gamedb::EMBEDDED;
+use rust_byte::movie::{Movie, MovieMode, MovieSession, Reset};
+use rust_byte::patch::load_patched;
+use rust_byte::region::Region;
+use rust_byte::rom_source::RomSource;
+use rust_byte::rewind::Rewind;
 use rust_byte::render::input::joypad::Joypad;
+use rust_byte::trace::{TraceFilter, TraceLogger};
+
+/// Command line options that are followed by a value
+const VALUE_OPTIONS: [&str; 14] = [
+    "--region", "--record", "--play", "--from-slot", "--gdb", "--cheat", "--patch", "--entry", "--gamedb",
+    "--trace", "--trace-ring", "--trace-pc", "--trace-bank", "--trace-frames",
+];
+
+/// ROM loaded when no path is given on the command line
+const DEFAULT_ROM: &str = "assets/balloon_fight.nes";
+
+/// Function that returns the value following a command line option
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+}
+
+/// Function that returns the ROM path, the first argument that isn't an option
+fn rom_path(args: &[String]) -> String {
+    let mut i = 1;
+    while i < args.len() {
+        if VALUE_OPTIONS.contains(&args[i].as_str()) {
+            i += 2;
+        } else if args[i].starts_with("--") {
+            i += 1;
+        } else {
+            return args[i].clone();
+        }
+    }
+
+    DEFAULT_ROM.to_string()
+}
+
+/// Save state requests from the keyboard, handled between two instructions
+#[derive(Debug, Clone, Copy)]
+enum SlotAction {
+    Save(u8),
+    Load(u8),
+}
+
+/// Function that maps F1 - F10 to save state slots 1 - 10
+fn slot(key: Keycode) -> Option<u8> {
+    let keys = [
+        Keycode::F1, Keycode::F2, Keycode::F3, Keycode::F4, Keycode::F5,
+        Keycode::F6, Keycode::F7, Keycode::F8, Keycode::F9, Keycode::F10,
+    ];
+
+    keys.iter().position(|k| *k == key).map(|i| i as u8 + 1)
+}
+
+/// Function that creates the trace logger from the command line options
+fn trace_logger(args: &[String], file: &str) -> TraceLogger {
+    let mut logger = match option(args, "--trace-ring") {
+        Some(len) => TraceLogger::ring_to_file(len.parse().expect("Invalid --trace-ring length"), file),
+        None => TraceLogger::to_file(file).expect("Failed to create the trace log"),
+    };
+
+    logger.ppu_columns = args.iter().any(|arg| arg == "--trace-ppu");
+    if let Some(ranges) = option(args, "--trace-pc") {
+        for range in ranges.split(',') {
+            let (start, end) = TraceFilter::parse_range(range).expect("Invalid --trace-pc range");
+            logger.filter.ranges.push((start as u16, end as u16));
+        }
+    }
+    logger.filter.bank = option(args, "--trace-bank").map(|bank| bank.parse().expect("Invalid --trace-bank"));
+    logger.filter.frames = option(args, "--trace-frames")
+        .map(|frames| TraceFilter::parse_range(frames).expect("Invalid --trace-frames range"));
+
+    logger
+}
+
+/// Function that flushes the trace log, a ring buffer is written to the file only now
+/// A panic drops the logger, which writes the ring buffer as well
+fn finish_trace(logger: &mut TraceLogger, file: &str) {
+    if let Err(err) = logger.finish() {
+        eprintln!("Failed to write the trace log {}: {}", file, err);
+    }
+}
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let path = rom_path(&args);
+
+    // region given by the user overrides the one from the ROM header
+    let user_region = option(&args, "--region")
+        .map(|name| Region::from_name(name).expect("Unknown region, expected ntsc, pal or dendy"));
+
     // init sdl2
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("Tile viewer", (256.0 * 3.0) as u32, (240.0 * 3.0) as u32)
+        .window("RustByte", (256.0 * 3.0) as u32, (240.0 * 3.0) as u32)
         .position_centered()
         .build()
         .unwrap();
 
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    // frames are paced by the region's frame rate instead of vsync
+    let mut canvas = window.into_canvas().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
     canvas.set_scale(3.0, 3.0).unwrap();
 
@@ -31,11 +132,145 @@ fn main() {
     let mut texture = creator
         .create_texture_target(PixelFormatEnum::RGB24, 256, 240)
         .unwrap();
+    let main_window = canvas.window().id();
+
+    // --ppu-viewer opens a second window with the pattern tables, nametables, OAM and palettes
+    let mut viewer_canvas = args.iter().any(|arg| arg == "--ppu-viewer").then(|| {
+        let window = video_subsystem
+            .window("PPU viewer", VIEW_WIDTH as u32, VIEW_HEIGHT as u32)
+            .build()
+            .unwrap();
+        window.into_canvas().build().unwrap()
+    });
+    let viewer_creator = viewer_canvas.as_ref().map(|canvas| canvas.texture_creator());
+    let mut viewer_texture = viewer_creator.as_ref().map(|creator| {
+        creator
+            .create_texture_target(PixelFormatEnum::RGB24, VIEW_WIDTH as u32, VIEW_HEIGHT as u32)
+            .unwrap()
+    });
+
+    // palette used for the pattern tables, tab cycles through the 4 background and 4 sprite palettes
+    let mut viewer_palette = 0;
+
+    // load the game, from a zip (--entry <name> picks the file) or gzip archive,
+    // with game.ips/ups/bps or --patch <file> applied in memory
+    let source = RomSource::new(&path, option(&args, "--entry").map(|entry| entry.as_str()));
+    let patch_arg = option(&args, "--patch").map(|patch| patch.as_str());
+    let loaded = match load_patched(&source, patch_arg) {
+        // a patch that was only found next to the game doesn't stop it from running
+        Err(err) if patch_arg.is_none() => source.read().map(|bytes| {
+            eprintln!("Warning: {}, loading the ROM without the patch", err);
+            (bytes, None)
+        }),
+        res => res,
+    };
+    let (bytes, patch) = loaded.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    if let Some(patch) = patch {
+        println!("Applied patch {}", patch.display());
+    }
+
+    // header fixes come from the built in game database and --gamedb <file>
+    let mut db = EMBEDDED.clone();
+    if let Some(file) = option(&args, "--gamedb") {
+        let text = std::fs::read_to_string(file).expect("Failed to read the game database");
+        db.extend(&text).unwrap_or_else(|err| panic!("{}: {}", file, err));
+    }
+    let rom = Cartridge::with_db(bytes, &db).unwrap_or_else(|err| {
+        eprintln!("Failed to load {}: {}", path, err);
+        std::process::exit(1);
+    });
+    for correction in &rom.corrections {
+        println!("Corrected header {}", correction);
+    }
+    if let Some(title) = &rom.title {
+        canvas.window_mut().set_title(&format!("RustByte - {}", title)).unwrap();
+    }
+
+    // movies: --record <file> records the input, --play <file> plays it back
+    let record_path = option(&args, "--record").cloned();
+    let movie = match (&record_path, option(&args, "--play")) {
+        (_, Some(file)) => {
+            let movie = Movie::load(file).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            });
+            if movie.rom_checksum != Movie::rom_checksum(&rom) {
+                eprintln!("Warning: the movie was recorded with a different ROM");
+            }
+            Some(MovieSession::play(movie))
+        },
+        (Some(_), None) => {
+            let name = std::path::Path::new(&path)
+                .file_stem()
+                .map_or(path.clone(), |stem| stem.to_string_lossy().to_string());
+            let mut movie = Movie::new(&name, &rom);
+            movie.pal = user_region.unwrap_or(rom.region) == Region::PAL;
+
+            // --from-slot <n> starts the recording from a save state instead of power on
+            if let Some(slot) = option(&args, "--from-slot") {
+                let file = format!("{}.ss{}", path, slot);
+                movie.savestate = Some(std::fs::read(&file).expect("Failed to read the save state"));
+            }
+            Some(MovieSession::record(movie))
+        },
+        (None, None) => None,
+    };
+    let movie_active = movie.is_some();
+
+    // a played movie recorded on PAL runs as PAL unless the user says otherwise
+    let region = match &movie {
+        Some(session) if session.mode() == MovieMode::Playing && session.movie().pal => {
+            user_region.unwrap_or(Region::PAL)
+        },
+        _ => user_region.unwrap_or(rom.region),
+    };
 
-    // load the game
-    let bytes: Vec<u8> = std::fs::read("assets/balloon_fight.nes").unwrap();
-    let rom = Cartridge::new(bytes).unwrap();
     let mut frame = Frame::new();
+    let mut next_frame = Instant::now();
+
+    // save states: shift + F1 - F10 saves, F1 - F10 loads
+    let slot_action = Rc::new(Cell::new(None));
+    let slot_request = slot_action.clone();
+
+    // rewind: holding backspace runs the game backwards for up to 60 seconds
+    let rewinding = Rc::new(Cell::new(false));
+    let rewind_request = rewinding.clone();
+    let mut rewind = Rewind::with_duration(60.0, region, 1);
+    let mut last_frame = 0;
+
+    // quitting is deferred until the next instruction so that a recorded movie can be written
+    let quit = Rc::new(Cell::new(false));
+    let quit_request = quit.clone();
+
+    // debugger: --debug starts paused, F12 breaks into the debugger on the terminal
+    let mut repl = args.iter().any(|arg| arg == "--debug").then(|| {
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        Repl::stdio(debugger)
+    });
+    let break_requested = Rc::new(Cell::new(false));
+    let break_request = break_requested.clone();
+
+    // cheats: C turns all of them on and off, Shift + C saves them with the ones given by --cheat
+    let cheats_toggled = Rc::new(Cell::new(false));
+    let cheats_toggle = cheats_toggled.clone();
+    let cheats_save_requested = Rc::new(Cell::new(false));
+    let cheats_save_request = cheats_save_requested.clone();
+
+    // trace logger: --trace <file> logs from the start, F11 turns it on and off
+    let trace_file = option(&args, "--trace").cloned().unwrap_or(format!("{}.trace.log", path));
+    let trace = Rc::new(RefCell::new(option(&args, "--trace").map(|_| trace_logger(&args, &trace_file))));
+    let trace_toggled = Rc::new(Cell::new(false));
+    let trace_toggle = trace_toggled.clone();
+
+    // --gdb <port> waits for GDB to connect before the game starts
+    let mut gdb = option(&args, "--gdb").map(|port| {
+        println!("Waiting for GDB on 127.0.0.1:{}", port);
+        GdbStub::listen(format!("127.0.0.1:{}", port)).expect("Failed to accept the GDB connection")
+    });
     
     // map keyboard to joypad
     let mut keys = HashMap::new();
@@ -48,19 +283,70 @@ fn main() {
     keys.insert(Keycode::Q, Button::A);
     keys.insert(Keycode::E, Button::B);
 
-    let bus = Bus::new(rom, move |ppu: &PPU, joy: &mut Joypad| {
+    let mut bus = Bus::new(rom, move |ppu: &PPU, joy: &mut Joypad| {
         Renderer::render(ppu, &mut frame);
         texture.update(None, &frame.data, 256 * 3).unwrap();
 
         canvas.copy(&texture, None, None).unwrap();
         canvas.present();
 
+        if let (Some(viewer), Some(texture)) = (viewer_canvas.as_mut(), viewer_texture.as_mut()) {
+            let view = debug_view(ppu, viewer_palette);
+            texture.update(None, &view.data, VIEW_WIDTH * 3).unwrap();
+
+            viewer.copy(texture, None, None).unwrap();
+            viewer.present();
+        }
+
+        // wait for the next frame (60 Hz for NTSC, 50 Hz for PAL and Dendy)
+        next_frame += region.frame_duration();
+        let now = Instant::now();
+        if next_frame > now {
+            std::thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => std::process::exit(0),
+                } => quit_request.set(true),
+
+                // with two windows open closing one doesn't quit, only the game window does
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } => {
+                    if window_id == main_window {
+                        quit_request.set(true);
+                    } else {
+                        viewer_texture = None;
+                        viewer_canvas = None;
+                    }
+                },
+
+                Event::KeyDown { keycode: Some(Keycode::Tab), repeat: false, .. } => viewer_palette = (viewer_palette + 1) % 8,
+
+                Event::KeyDown { keycode: Some(Keycode::F12), .. } => break_request.set(true),
+                Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => trace_toggle.set(true),
+                Event::KeyDown { keycode: Some(Keycode::C), keymod, repeat: false, .. } => {
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        cheats_save_request.set(true);
+                    } else {
+                        cheats_toggle.set(true);
+                    }
+                },
+
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => rewind_request.set(true),
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => rewind_request.set(false),
+
+                Event::KeyDown { keycode: Some(key), keymod, repeat: false, .. } if slot(key).is_some() => {
+                    let slot = slot(key).unwrap();
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        slot_request.set(Some(SlotAction::Save(slot)));
+                    } else {
+                        slot_request.set(Some(SlotAction::Load(slot)));
+                    }
+                },
                 
                 Event::KeyDown { keycode, .. } => {
                     if let Some(key) = keys.get(&keycode.unwrap_or(Keycode::Ampersand)) {
@@ -79,7 +365,154 @@ fn main() {
         }
     });
 
+    bus.set_region(region);
+
+    // cheats saved for this game, then the ones given with --cheat <code>
+    let cheats_file = Cheats::path(bus.rom_hash());
+    if let Ok(text) = std::fs::read_to_string(&cheats_file) {
+        match Cheats::parse(&text) {
+            Ok(cheats) => {
+                println!("Loaded {} cheats from {}", cheats.cheats.len(), cheats_file);
+                *bus.cheats_mut() = cheats;
+            },
+            Err(err) => eprintln!("Failed to load the cheats from {}: {}", cheats_file, err),
+        }
+    }
+    for (i, _) in args.iter().enumerate().filter(|(_, arg)| *arg == "--cheat") {
+        let code = args.get(i + 1).expect("Missing --cheat code");
+        bus.cheats_mut().add(code, "").expect("Invalid --cheat code");
+    }
+
     let mut cpu = CPU::new(bus);
     cpu.reset();
-    cpu.interpret_callback(|cpu| {});
-}
\ No newline at end of file
+
+    if let Some(movie) = movie {
+        if let Some(state) = &movie.movie().savestate {
+            cpu.load_state(state).expect("Failed to load the movie save state");
+        }
+        cpu.bus.set_movie(movie);
+    }
+
+    let trace_log = trace.clone();
+    let trace_path = trace_file.clone();
+    cpu.interpret_callback(move |cpu| {
+        if trace_toggled.take() {
+            let mut trace = trace_log.borrow_mut();
+            match trace.as_mut() {
+                Some(logger) => {
+                    let enabled = logger.toggle();
+                    println!("Trace logging {}", if enabled { "resumed" } else { "paused" });
+                },
+                None => {
+                    *trace = Some(trace_logger(&args, &trace_path));
+                    println!("Trace logging to {}", trace_path);
+                },
+            }
+        }
+
+        if cheats_toggled.take() {
+            let cheats = cpu.bus.cheats_mut();
+            cheats.active = !cheats.active;
+            println!("Cheats {}", if cheats.active { "on" } else { "off" });
+        }
+
+        if cheats_save_requested.take() {
+            match cpu.bus.cheats().save(cpu.bus.rom_hash()) {
+                Ok(file) => println!("Saved {} cheats to {}", cpu.bus.cheats().cheats.len(), file),
+                Err(err) => eprintln!("Failed to save the cheats: {}", err),
+            }
+        }
+
+        if let Some(logger) = trace_log.borrow_mut().as_mut() {
+            if let Err(err) = logger.log(cpu) {
+                eprintln!("Failed to write the trace log: {}", err);
+                logger.enabled = false;
+            }
+        }
+
+        if break_requested.take() {
+            repl.get_or_insert_with(|| Repl::stdio(Debugger::new())).debugger.pause();
+        }
+
+        if let Some(repl) = repl.as_mut() {
+            if !repl.hook(cpu) {
+                quit.set(true);
+            }
+        }
+
+        if let Some(gdb) = gdb.as_mut() {
+            if !gdb.hook(cpu) {
+                quit.set(true);
+            }
+        }
+
+        if quit.get() {
+            if let (Some(file), Some(session)) = (&record_path, cpu.bus.take_movie()) {
+                match session.movie().save(file) {
+                    Ok(_) => println!("Recorded {} frames to {}", session.frame_count(), file),
+                    Err(err) => eprintln!("Failed to write the movie to {}: {}", file, err),
+                }
+            }
+            if let Some(logger) = trace_log.borrow_mut().as_mut() {
+                finish_trace(logger, &trace_path);
+            }
+            std::process::exit(0);
+        }
+
+        if let Some(session) = cpu.bus.movie() {
+            if let Some(reset) = session.take_reset() {
+                match reset {
+                    Reset::Soft => cpu.reset(),
+                    Reset::Power => cpu.power_cycle(),
+                }
+            } else if session.mode() == MovieMode::Finished && record_path.is_none() {
+                println!("Movie playback finished");
+                cpu.bus.take_movie();
+            }
+        }
+
+        // snapshot or rewind once per frame, rewinding would desync a movie
+        if cpu.bus.ppu().frame() != last_frame {
+            if rewinding.get() && !movie_active {
+                if let Some(state) = rewind.pop() {
+                    cpu.load_state(&state).unwrap();
+                }
+            } else {
+                rewind.record(cpu);
+            }
+
+            last_frame = cpu.bus.ppu().frame();
+        }
+
+        if let Some(action) = slot_action.take() {
+            match action {
+                SlotAction::Save(slot) => {
+                    let file = format!("{}.ss{}", path, slot);
+                    match std::fs::write(&file, cpu.save_state()) {
+                        Ok(_) => println!("Saved state to slot {}", slot),
+                        Err(err) => eprintln!("Failed to save state to {}: {}", file, err),
+                    }
+                },
+                SlotAction::Load(_) if movie_active => {
+                    eprintln!("Loading states is disabled while a movie is active");
+                },
+                SlotAction::Load(slot) => {
+                    let file = format!("{}.ss{}", path, slot);
+                    match std::fs::read(&file).map(|data| cpu.load_state(&data)) {
+                        Ok(Ok(_)) => {
+                            rewind.clear();
+                            println!("Loaded state from slot {}", slot)
+                        },
+                        Ok(Err(err)) => eprintln!("Failed to load state from slot {}: {}", slot, err),
+                        Err(err) => eprintln!("Failed to read {}: {}", file, err),
+                    }
+                },
+            }
+        }
+    });
+    // the loop only ends on an opcode the CPU doesn't know, keep the trace leading up to it
+    eprintln!("CPU stopped on unknown opcode ${:02X} at ${:04X}", cpu.bus.peek(cpu.prog_counter), cpu.prog_counter);
+    if let Some(logger) = trace.borrow_mut().as_mut() {
+        finish_trace(logger, &trace_file);
+    };
+}
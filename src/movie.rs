@@ -0,0 +1,453 @@
+// https://fceux.com/web/help/fm2.html
+
+use crate::hash::{md5, to_hex};
+use crate::ppu::cartridge::Cartridge;
+use crate::region::Region;
+use crate::render::input::joypad::Joypad;
+use std::fmt::{Display, Formatter};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Button order of an input line, from the most significant bit of `Joypad::buttons`
+const BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+/// Soft reset command bit of an input line
+pub const COMMAND_RESET: u8 = 0b01;
+
+/// Power (hard reset) command bit of an input line
+pub const COMMAND_POWER: u8 = 0b10;
+
+/// Reset requested by the commands of a played frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reset {
+    /// Reset button, the memory is kept
+    Soft,
+
+    /// Power switch, the console starts from a cleared memory
+    Power,
+}
+
+/// Errors that can happen while parsing a movie
+#[derive(Debug, PartialEq, Eq)]
+pub enum MovieError {
+    /// A header line is not a "key value" pair
+    InvalidHeader(usize),
+
+    /// An input line is malformed
+    InvalidInput(usize),
+
+    /// A binary movie, which is not supported
+    Binary,
+
+    /// The movie starts from a save state of another emulator, only power on movies can be played
+    ForeignSavestate,
+}
+
+impl Display for MovieError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MovieError::InvalidHeader(line) => write!(f, "invalid header on line {}", line),
+            MovieError::InvalidInput(line) => write!(f, "invalid input on line {}", line),
+            MovieError::Binary => write!(f, "binary fm2 movies are not supported"),
+            MovieError::ForeignSavestate => {
+                write!(f, "the movie starts from an FCEUX save state, only movies from power on can be played")
+            },
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+/// Input of a single frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameInput {
+    /// Commands issued before the frame (reset, power)
+    pub commands: u8,
+
+    /// Buttons held on the first controller, in `Joypad::buttons` format
+    pub port0: u8,
+}
+
+/// Class representing an input movie in the FCEUX .fm2 format
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Movie {
+    /// Name of the ROM the movie was recorded with
+    pub rom_filename: String,
+
+    /// "base64:" followed by the MD5 of the PRG and CHR ROM
+    pub rom_checksum: String,
+
+    /// Unique identifier of the movie
+    pub guid: String,
+
+    /// The movie was recorded on a PAL console
+    pub pal: bool,
+
+    /// Number of times the movie was re-recorded
+    pub rerecord_count: u32,
+
+    /// Free-form comments
+    pub comments: Vec<String>,
+
+    /// Save state the movie starts from, None for power on
+    /// It is a RustByte state, kept in a file next to the movie instead of the .fm2 (see `state_path`)
+    pub savestate: Option<Vec<u8>>,
+
+    /// Input of every frame
+    pub frames: Vec<FrameInput>,
+}
+
+impl Movie {
+    /// Create a new empty movie for the given ROM
+    pub fn new(rom_filename: &str, cartridge: &Cartridge) -> Self {
+        Movie {
+            rom_filename: rom_filename.to_string(),
+            rom_checksum: Movie::rom_checksum(cartridge),
+            guid: Movie::generate_guid(),
+            pal: cartridge.region == Region::PAL,
+            ..Movie::default()
+        }
+    }
+
+    /// Function that computes the checksum FCEUX uses to identify a ROM
+    pub fn rom_checksum(cartridge: &Cartridge) -> String {
+        let mut data = cartridge.prg_rom.clone();
+        if !cartridge.chr_ram {
+            data.extend_from_slice(&cartridge.chr_rom);
+        }
+
+        format!("base64:{}", base64_encode(&md5(&data)))
+    }
+
+    /// Function that creates a GUID from the current time
+    fn generate_guid() -> String {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let hex = to_hex(&md5(&nanos.to_le_bytes())).to_uppercase();
+
+        format!("{}-{}-{}-{}-{}", &hex[0 .. 8], &hex[8 .. 12], &hex[12 .. 16], &hex[16 .. 20], &hex[20 .. 32])
+    }
+
+    /// Path of the save state file of a movie that doesn't start from power on
+    pub fn state_path(path: &str) -> String {
+        format!("{}.state", path)
+    }
+
+    /// Function that reads a movie and the save state it starts from, when it has one
+    pub fn load(path: &str) -> Result<Movie, String> {
+        let text = std::fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+        let mut movie = Movie::parse_fm2(&text).map_err(|err| format!("Failed to parse {}: {}", path, err))?;
+
+        let state = Movie::state_path(path);
+        if std::path::Path::new(&state).exists() {
+            movie.savestate = Some(std::fs::read(&state).map_err(|err| format!("Failed to read {}: {}", state, err))?);
+        }
+        Ok(movie)
+    }
+
+    /// Function that writes a movie, the save state it starts from goes into its own file
+    /// so that FCEUX can still read the .fm2, only movies from power on replay the same there
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_fm2())?;
+
+        let state = Movie::state_path(path);
+        match &self.savestate {
+            Some(data) => std::fs::write(state, data),
+            None if std::path::Path::new(&state).exists() => std::fs::remove_file(state),
+            None => Ok(()),
+        }
+    }
+
+    /// Function that parses a movie from the .fm2 text format
+    pub fn parse_fm2(text: &str) -> Result<Movie, MovieError> {
+        let mut movie = Movie::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            let number = i + 1;
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('|') {
+                movie.frames.push(Movie::parse_input(line).ok_or(MovieError::InvalidInput(number))?);
+                continue;
+            }
+
+            let (key, value) = match line.split_once(' ') {
+                Some((key, value)) => (key, value),
+                None => (line, ""),
+            };
+
+            match key {
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => movie.rom_checksum = value.to_string(),
+                "guid" => movie.guid = value.to_string(),
+                "palFlag" => movie.pal = value == "1",
+                "rerecordCount" => movie.rerecord_count = value.parse().unwrap_or(0),
+                "comment" => movie.comments.push(value.to_string()),
+                "binary" if value == "1" => return Err(MovieError::Binary),
+                // an FCEUX state, which can't be loaded into this emulator
+                "savestate" => return Err(MovieError::ForeignSavestate),
+                _ if key.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                    // other keys (emuVersion, port0, NewPPU, ...) are not needed
+                },
+                _ => return Err(MovieError::InvalidHeader(number)),
+            }
+        }
+
+        Ok(movie)
+    }
+
+    /// Function that parses an input line "|commands|port0|port1|port2|"
+    fn parse_input(line: &str) -> Option<FrameInput> {
+        let mut fields = line.split('|').skip(1);
+        let commands = fields.next()?.parse().ok()?;
+
+        let port0 = fields.next()?;
+        let mut buttons = 0;
+        if !port0.is_empty() {
+            if port0.len() != 8 {
+                return None;
+            }
+
+            // any character other than ' ' and '.' means the button is pressed
+            for (i, c) in port0.chars().enumerate() {
+                if c != '.' && c != ' ' {
+                    buttons |= 0x80 >> i;
+                }
+            }
+        }
+
+        Some(FrameInput { commands, port0: buttons })
+    }
+
+    /// Function that exports the movie into the .fm2 text format, without the save state
+    pub fn to_fm2(&self) -> String {
+        let mut res = String::new();
+        res.push_str("version 3\n");
+        res.push_str(&format!("emuVersion {}\n", env!("CARGO_PKG_VERSION").replace('.', "")));
+        res.push_str(&format!("rerecordCount {}\n", self.rerecord_count));
+        res.push_str(&format!("palFlag {}\n", self.pal as u8));
+        res.push_str(&format!("romFilename {}\n", self.rom_filename));
+        res.push_str(&format!("romChecksum {}\n", self.rom_checksum));
+        res.push_str(&format!("guid {}\n", self.guid));
+        res.push_str("fourscore 0\n");
+        res.push_str("microphone 0\n");
+        res.push_str("port0 1\n");
+        res.push_str("port1 0\n");
+        res.push_str("port2 0\n");
+        res.push_str("FDS 0\n");
+        res.push_str("NewPPU 0\n");
+
+        for comment in &self.comments {
+            res.push_str(&format!("comment {}\n", comment));
+        }
+
+        for frame in &self.frames {
+            let buttons: String = BUTTONS
+                .iter()
+                .enumerate()
+                .map(|(i, c)| if frame.port0 & (0x80 >> i) != 0 { *c as char } else { '.' })
+                .collect();
+            res.push_str(&format!("|{}|{}|||\n", frame.commands, buttons));
+        }
+
+        res
+    }
+}
+
+/// State of a movie session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+    Finished,
+}
+
+/// Class recording or playing back a movie, one frame at a time
+#[derive(Debug)]
+pub struct MovieSession {
+    movie: Movie,
+    mode: MovieMode,
+
+    /// Current frame of the movie
+    frame: usize,
+
+    /// Reset requested by the current frame
+    reset: Option<Reset>,
+}
+
+impl MovieSession {
+    /// Create a session recording into the movie
+    pub fn record(movie: Movie) -> Self {
+        MovieSession {
+            movie,
+            mode: MovieMode::Recording,
+            frame: 0,
+            reset: None,
+        }
+    }
+
+    /// Create a session playing back the movie
+    pub fn play(movie: Movie) -> Self {
+        MovieSession {
+            movie,
+            mode: MovieMode::Playing,
+            frame: 0,
+            reset: None,
+        }
+    }
+
+    /// Function that is called at every frame boundary
+    /// Recording stores the joypad state, playback replaces it with the recorded one
+    pub fn frame(&mut self, joypad: &mut Joypad) {
+        match self.mode {
+            MovieMode::Recording => {
+                self.movie.frames.push(FrameInput {
+                    commands: 0,
+                    port0: joypad.buttons(),
+                });
+                self.frame += 1;
+            },
+            MovieMode::Playing => match self.movie.frames.get(self.frame) {
+                Some(input) => {
+                    joypad.set_buttons(input.port0);
+                    // a power cycle also covers a reset requested in the same frame
+                    if input.commands & COMMAND_POWER != 0 {
+                        self.reset = Some(Reset::Power);
+                    } else if input.commands & COMMAND_RESET != 0 && self.reset.is_none() {
+                        self.reset = Some(Reset::Soft);
+                    }
+                    self.frame += 1;
+                },
+                None => {
+                    joypad.set_buttons(0);
+                    self.mode = MovieMode::Finished;
+                },
+            },
+            MovieMode::Finished => {},
+        }
+    }
+
+    /// Function that returns the reset a played frame requested, once
+    pub fn take_reset(&mut self) -> Option<Reset> {
+        self.reset.take()
+    }
+
+    pub fn mode(&self) -> MovieMode {
+        self.mode
+    }
+
+    /// Number of frames recorded or played so far
+    pub fn frame_count(&self) -> usize {
+        self.frame
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn into_movie(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Alphabet of the standard base64 encoding
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Function that encodes the data as base64
+fn base64_encode(data: &[u8]) -> String {
+    let mut res = String::new();
+
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+
+        for i in 0 .. 4 {
+            if i <= chunk.len() {
+                res.push(BASE64[(n >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FM2: &str = "version 3
+emuVersion 22020
+rerecordCount 5
+palFlag 0
+romFilename balloon_fight
+romChecksum base64:AAECAwQFBgcICQoLDA0ODw==
+guid 01234567-89AB-CDEF-0123-456789ABCDEF
+fourscore 0
+microphone 0
+port0 1
+port1 0
+port2 0
+FDS 0
+NewPPU 0
+comment author someone
+|1|........|||
+|0|R......A|||
+|0|...U.S..|||
+";
+
+    #[test]
+    fn test_parse_fm2() {
+        let movie = Movie::parse_fm2(FM2).unwrap();
+        assert_eq!(movie.rom_filename, "balloon_fight");
+        assert_eq!(movie.rerecord_count, 5);
+        assert_eq!(movie.comments, vec!["author someone".to_string()]);
+        assert_eq!(movie.frames, vec![
+            FrameInput { commands: 1, port0: 0 },
+            FrameInput { commands: 0, port0: 0b1000_0001 },
+            FrameInput { commands: 0, port0: 0b0001_0100 },
+        ]);
+    }
+
+    #[test]
+    fn test_fm2_roundtrip() {
+        let mut movie = Movie::parse_fm2(FM2).unwrap();
+        let text = movie.to_fm2();
+        assert!(text.contains("|0|R......A|||\n"));
+        assert_eq!(Movie::parse_fm2(&text).unwrap(), movie);
+
+        // the state goes next to the movie, the .fm2 stays readable by FCEUX
+        let path = std::env::temp_dir().join(format!("rustbyte-movie-{}.fm2", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        movie.savestate = Some(vec![1, 2, 3, 4, 5]);
+        movie.save(&path).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("savestate"));
+        assert_eq!(Movie::load(&path).unwrap(), movie);
+
+        // saving a power on movie over it removes the old state
+        movie.savestate = None;
+        movie.save(&path).unwrap();
+        assert_eq!(Movie::load(&path).unwrap(), movie);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_fm2() {
+        assert_eq!(Movie::parse_fm2("version 3\n|0|RL|||"), Err(MovieError::InvalidInput(2)));
+        assert_eq!(Movie::parse_fm2("binary 1"), Err(MovieError::Binary));
+        assert_eq!(Movie::parse_fm2("savestate base64:AAAA"), Err(MovieError::ForeignSavestate));
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64_encode(b"Man"), "TWFu");
+        assert_eq!(base64_encode(b"Ma"), "TWE=");
+        assert_eq!(base64_encode(b"M"), "TQ==");
+    }
+}
@@ -0,0 +1,391 @@
+// https://zerosoft.zophar.net/ips.php
+// https://www.romhacking.net/documents/392/ (UPS)
+// https://www.romhacking.net/documents/746/ (BPS)
+//
+// Soft-patching, the patch is applied to the ROM in memory and the file on disk is never touched
+// [IPS] => "PATCH", records of (3 byte offset, 2 byte size, data) or RLE runs, "EOF", optional truncation
+// [UPS] => "UPS1", sizes, XOR runs at relative offsets, CRC-32 of the source, target and patch
+// [BPS] => "BPS1", sizes, metadata, copy actions from the source/target/patch, CRC-32 footer
+
+use crate::hash::crc32;
+use crate::rom_source::RomSource;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// Extensions looked for next to the ROM, in this order
+const EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+/// Errors that can happen while applying a patch
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchError {
+    /// The data doesn't start with the magic of a known format
+    UnknownFormat,
+
+    /// The patch ends in the middle of a record
+    UnexpectedEnd,
+
+    /// The patch file itself is damaged (expected, found)
+    PatchChecksum(u32, u32),
+
+    /// The patch was made for a different ROM (expected, found)
+    SourceMismatch(u32, u32),
+
+    /// The patched ROM doesn't match what the patch should produce (expected, found)
+    TargetMismatch(u32, u32),
+
+    /// A record points outside of the ROM
+    OutOfBounds,
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "not an IPS, UPS or BPS patch"),
+            PatchError::UnexpectedEnd => write!(f, "patch is truncated"),
+            PatchError::PatchChecksum(expected, found) => {
+                write!(f, "patch is damaged (CRC32 {:08X}, expected {:08X})", found, expected)
+            },
+            PatchError::SourceMismatch(expected, found) => {
+                write!(f, "patch is for a different ROM (ROM CRC32 {:08X}, patch expects {:08X})", found, expected)
+            },
+            PatchError::TargetMismatch(expected, found) => {
+                write!(f, "patched ROM is wrong (CRC32 {:08X}, patch expects {:08X})", found, expected)
+            },
+            PatchError::OutOfBounds => write!(f, "patch writes outside of the ROM"),
+        }
+    }
+}
+
+/// Class reading the fields of a patch
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Reader { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self.data.get(self.pos .. self.pos + len).ok_or(PatchError::UnexpectedEnd)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Big endian number of len bytes, used by IPS
+    fn be(&mut self, len: usize) -> Result<usize, PatchError> {
+        Ok(self.bytes(len)?.iter().fold(0, |res, byte| res << 8 | *byte as usize))
+    }
+
+    /// Variable length number of UPS and BPS, 7 bits per byte with the top bit ending the number
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut res: usize = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let byte = self.u8()?;
+            res = res.checked_add((byte & 0x7F) as usize * shift).ok_or(PatchError::OutOfBounds)?;
+            if byte & 0x80 != 0 {
+                return Ok(res);
+            }
+            shift = shift.checked_shl(7).ok_or(PatchError::OutOfBounds)?;
+            res += shift;
+        }
+    }
+}
+
+/// Function that applies an IPS, UPS or BPS patch to a ROM, the format is picked by the magic bytes
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut res = rom.to_vec();
+    let mut reader = Reader::new(patch, 5);
+
+    loop {
+        if reader.data[reader.pos ..].starts_with(b"EOF") {
+            reader.pos += 3;
+            break;
+        }
+
+        let offset = reader.be(3)?;
+        let size = reader.be(2)?;
+
+        // a size of 0 is a run of one byte repeated
+        let data = if size == 0 {
+            let len = reader.be(2)?;
+            vec![reader.u8()?; len]
+        } else {
+            reader.bytes(size)?.to_vec()
+        };
+
+        if res.len() < offset + data.len() {
+            res.resize(offset + data.len(), 0);
+        }
+        res[offset .. offset + data.len()].copy_from_slice(&data);
+    }
+
+    // some patches end with the size the ROM is truncated to
+    if let Ok(len) = reader.be(3) {
+        res.truncate(len);
+    }
+
+    Ok(res)
+}
+
+/// Function that checks the CRC-32 footer of UPS and BPS patches, returns the source and target CRCs
+fn footer(rom: &[u8], patch: &[u8]) -> Result<(u32, u32), PatchError> {
+    if patch.len() < 12 {
+        return Err(PatchError::UnexpectedEnd);
+    }
+
+    let crc = |at: usize| u32::from_le_bytes([patch[at], patch[at + 1], patch[at + 2], patch[at + 3]]);
+    let end = patch.len() - 12;
+
+    let expected = crc(end + 8);
+    let found = crc32(&patch[.. end + 8]);
+    if expected != found {
+        return Err(PatchError::PatchChecksum(expected, found));
+    }
+
+    let source = crc(end);
+    let found = crc32(rom);
+    if source != found {
+        return Err(PatchError::SourceMismatch(source, found));
+    }
+
+    Ok((source, crc(end + 4)))
+}
+
+/// Function that checks the patched ROM against the target CRC-32 of the footer
+fn check_target(res: Vec<u8>, target: u32) -> Result<Vec<u8>, PatchError> {
+    let found = crc32(&res);
+    if found != target {
+        return Err(PatchError::TargetMismatch(target, found));
+    }
+    Ok(res)
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (_, target) = footer(rom, patch)?;
+    let end = patch.len() - 12;
+
+    let mut reader = Reader::new(&patch[.. end], 4);
+    let _source_size = reader.number()?;
+    let target_size = reader.number()?;
+
+    let mut res = rom.to_vec();
+    res.resize(target_size, 0);
+
+    // every run starts after a gap and XORs bytes until a 0
+    let mut offset = 0;
+    while reader.pos < end {
+        offset += reader.number()?;
+
+        loop {
+            let byte = reader.u8()?;
+            if byte == 0 {
+                offset += 1;
+                break;
+            }
+
+            *res.get_mut(offset).ok_or(PatchError::OutOfBounds)? ^= byte;
+            offset += 1;
+        }
+    }
+
+    check_target(res, target)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (_, target) = footer(rom, patch)?;
+    let end = patch.len() - 12;
+
+    let mut reader = Reader::new(&patch[.. end], 4);
+    let _source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata = reader.number()?;
+    reader.bytes(metadata)?;
+
+    let mut res: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+
+    // relative offsets store the sign in the lowest bit
+    let relative = |reader: &mut Reader, offset: &mut isize| -> Result<usize, PatchError> {
+        let data = reader.number()?;
+        let delta = (data >> 1) as isize;
+        *offset += if data & 1 != 0 { -delta } else { delta };
+        usize::try_from(*offset).map_err(|_| PatchError::OutOfBounds)
+    };
+
+    while reader.pos < end {
+        let data = reader.number()?;
+        let len = (data >> 2) + 1;
+
+        match data & 0b11 {
+            // source read, the same bytes as at this position of the ROM
+            0 => {
+                let at = res.len();
+                res.extend_from_slice(rom.get(at .. at + len).ok_or(PatchError::OutOfBounds)?);
+            },
+            // target read, the bytes follow in the patch
+            1 => res.extend_from_slice(reader.bytes(len)?),
+            // source copy, from anywhere in the ROM
+            2 => {
+                let at = relative(&mut reader, &mut source_offset)?;
+                res.extend_from_slice(rom.get(at .. at + len).ok_or(PatchError::OutOfBounds)?);
+                source_offset += len as isize;
+            },
+            // target copy, byte by byte since the run can overlap what it is writing
+            _ => {
+                let at = relative(&mut reader, &mut target_offset)?;
+                for i in 0 .. len {
+                    let byte = *res.get(at + i).ok_or(PatchError::OutOfBounds)?;
+                    res.push(byte);
+                }
+                target_offset += len as isize;
+            },
+        }
+    }
+
+    if res.len() != target_size {
+        return Err(PatchError::OutOfBounds);
+    }
+
+    check_target(res, target)
+}
+
+/// Function that finds a patch next to the ROM, game.ips or game.nes.ips
+pub fn find(rom_path: &str) -> Option<PathBuf> {
+    let path = Path::new(rom_path);
+
+    EXTENSIONS.iter().find_map(|ext| {
+        [path.with_extension(ext), PathBuf::from(format!("{}.{}", rom_path, ext))]
+            .into_iter()
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// Function that reads a ROM and applies the patch given by the user or the one found next to it
+/// Returns the ROM and the path of the applied patch
+pub fn load_patched(source: &RomSource, patch_path: Option<&str>) -> Result<(Vec<u8>, Option<PathBuf>), String> {
+    let rom = source.read()?;
+
+    let patch_path = match patch_path {
+        Some(path) => Some(PathBuf::from(path)),
+        None => find(&source.path().to_string_lossy()),
+    };
+    let Some(patch_path) = patch_path else {
+        return Ok((rom, None));
+    };
+
+    let patch = std::fs::read(&patch_path).map_err(|err| format!("Failed to read {}: {}", patch_path.display(), err))?;
+    let rom = apply(&rom, &patch).map_err(|err| format!("Failed to apply {}: {}", patch_path.display(), err))?;
+    Ok((rom, Some(patch_path)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Function that encodes a UPS/BPS number
+    fn number(mut val: usize) -> Vec<u8> {
+        let mut res = vec![];
+        loop {
+            let byte = (val & 0x7F) as u8;
+            val >>= 7;
+            if val == 0 {
+                res.push(byte | 0x80);
+                return res;
+            }
+            res.push(byte);
+            val -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_ips() {
+        let rom = vec![0u8; 8];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend([0, 0, 2, 0, 2, 0xAA, 0xBB]);         // $02: AA BB
+        patch.extend([0, 0, 9, 0, 0, 0, 3, 0x11]);         // RLE $09: 11 11 11, grows the ROM
+        patch.extend(b"EOF");
+
+        assert_eq!(apply(&rom, &patch), Ok(vec![0, 0, 0xAA, 0xBB, 0, 0, 0, 0, 0, 0x11, 0x11, 0x11]));
+
+        patch.extend([0, 0, 4]);
+        assert_eq!(apply(&rom, &patch), Ok(vec![0, 0, 0xAA, 0xBB]));
+
+        assert_eq!(apply(&rom, b"PATCH\x00\x00"), Err(PatchError::UnexpectedEnd));
+        assert_eq!(apply(&rom, b"NOTAPATCH"), Err(PatchError::UnknownFormat));
+    }
+
+    #[test]
+    fn test_ups() {
+        let source = b"Hello world".to_vec();
+        let target = b"Hello there!".to_vec();
+
+        // one run from offset 6, XOR of the differing bytes
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target.len()));
+        patch.extend(number(6));
+        patch.extend(source[6 ..].iter().chain([0].iter()).zip(&target[6 ..]).map(|(a, b)| a ^ b));
+        patch.push(0);
+        let patch = with_footer(patch, &source, &target);
+
+        assert_eq!(apply(&source, &patch), Ok(target.clone()));
+        assert!(matches!(apply(&target, &patch), Err(PatchError::SourceMismatch(..))));
+
+        let mut damaged = patch.clone();
+        damaged[6] ^= 1;
+        assert!(matches!(apply(&source, &damaged), Err(PatchError::PatchChecksum(..))));
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = b"abcdefgh".to_vec();
+        let target = b"abcXYXYXYefgh".to_vec();
+
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target.len()));
+        patch.extend(number(0));
+        patch.extend(number((3 - 1) << 2));                 // source read "abc"
+        patch.extend(number((2 - 1) << 2 | 1));             // target read "XY"
+        patch.extend(b"XY");
+        patch.extend(number((4 - 1) << 2 | 3));             // target copy "XYXY" from 3, overlapping
+        patch.extend(number(3 << 1));
+        patch.extend(number((4 - 1) << 2 | 2));             // source copy "efgh" from 4
+        patch.extend(number(4 << 1));
+        let patch = with_footer(patch, &source, &target);
+
+        assert_eq!(apply(&source, &patch), Ok(target.clone()));
+
+        // a patch that produces something else than its footer says is reported
+        let bad = with_footer(patch[.. patch.len() - 12].to_vec(), &source, b"other");
+        assert!(matches!(apply(&source, &bad), Err(PatchError::TargetMismatch(..))));
+    }
+}
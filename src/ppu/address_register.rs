@@ -1,5 +1,7 @@
 // https://www.nesdev.org/wiki/PPU_registers#PPUADDR_-_VRAM_address_($2006_write)
 
+use crate::state::{Savestate, StateError, StateReader, StateWriter};
+
 /// Class representing a PPU Address Register $2006
 #[derive(Debug)]
 pub struct AddressRegister {
@@ -78,4 +80,18 @@ impl AddressRegister {
             self.internal_set(self.get() & 0x3FFF);
         }
     }
-}
\ No newline at end of file
+}
+
+impl Savestate for AddressRegister {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.value.0);
+        writer.u8(self.value.1);
+        writer.bool(self.high_byte);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.value = (reader.u8()?, reader.u8()?);
+        self.high_byte = reader.bool()?;
+        Ok(())
+    }
+}
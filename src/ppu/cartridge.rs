@@ -1,35 +1,207 @@
 // https://www.nesdev.org/wiki/Mirroring#Nametable_Mirroring
 // https://formats.kaitai.io/ines/index.html
 // https://www.nesdev.org/wiki/INES#iNES_file_format
+// https://www.nesdev.org/wiki/NES_2.0
+// https://www.nesdev.org/wiki/UNIF
 
+use crate::gamedb::{GameDb, EMBEDDED};
 use crate::ppu::mirroring::Mirroring;
+use crate::ppu::unif;
+use crate::region::Region;
+use std::fmt::{Display, Formatter};
+
+/// Class representing a header field that was replaced, with the value from the header and the new one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Correction {
+    pub field: &'static str,
+    pub header: String,
+    pub corrected: String,
+}
+
+impl Display for Correction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.header, self.corrected)
+    }
+}
+
+/// Version of the header the ROM was loaded from
+/// https://www.nesdev.org/wiki/INES#Variant_comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderFormat {
+    /// Only bytes 4 - 7 are defined, the rest is padding or garbage
+    Archaic,
+    INes,
+    Nes2,
+
+    /// Chunks with a board name instead of a mapper number
+    Unif,
+}
+
+impl Display for HeaderFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HeaderFormat::Archaic => "archaic iNES",
+            HeaderFormat::INes => "iNES",
+            HeaderFormat::Nes2 => "NES 2.0",
+            HeaderFormat::Unif => "UNIF",
+        })
+    }
+}
+
+/// Function that returns the board name of a mapper number
+/// https://www.nesdev.org/wiki/Mapper
+pub fn mapper_name(mapper: u16) -> Option<&'static str> {
+    Some(match mapper {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        13 => "CPROM",
+        19 => "Namco 163",
+        21 | 23 | 25 => "VRC4",
+        22 => "VRC2",
+        24 | 26 => "VRC6",
+        34 => "BNROM / NINA-001",
+        66 => "GxROM",
+        69 => "Sunsoft FME-7",
+        71 => "Camerica",
+        85 => "VRC7",
+        118 => "TxSROM",
+        119 => "TQROM",
+        206 => "DxROM",
+        _ => return None,
+    })
+}
 
 #[derive(Debug)]
 pub struct Cartridge {
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
-    mapper: u8,
+
+    /// The cartridge has writable CHR-RAM instead of CHR-ROM
+    /// https://www.nesdev.org/wiki/CHR_ROM_vs._CHR_RAM
+    pub chr_ram: bool,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub format: HeaderFormat,
     pub mirroring: Mirroring,
+    pub region: Region,
+
+    /// The PRG-RAM is battery backed and keeps the saves
+    pub battery: bool,
+    /// Size of the PRG-RAM, the bus allocates at least 8kB
+    pub prg_ram_size: usize,
+
+    /// 512 byte trainer stored between the header and the PRG-ROM, loaded at $7000
+    /// https://www.nesdev.org/wiki/INES#Trainer
+    pub trainer: Option<Vec<u8>>,
+
+    /// Name of the game when it is in the game database
+    pub title: Option<String>,
+
+    /// Header fields replaced by the game database or because the header is dirty
+    pub corrections: Vec<Correction>,
 }
 
 impl Cartridge {
+    /// Function that loads an iNES, NES 2.0 or UNIF file, correcting its header by the built in game database
     pub fn new(data: Vec<u8>) -> Result<Cartridge, &'static str> {
+        Cartridge::with_db(data, &EMBEDDED)
+    }
+
+    /// Function that loads an iNES, NES 2.0 or UNIF file to run it, correcting its header by the game database
+    /// Cartridges with a mapper other than NROM are refused, the bus has no bank switching
+    pub fn with_db(data: Vec<u8>, db: &GameDb) -> Result<Cartridge, &'static str> {
+        let cartridge = Cartridge::parse(data, db)?;
+        cartridge.check_mapper()?;
+        Ok(cartridge)
+    }
+
+    /// Function that loads an iNES, NES 2.0 or UNIF file whatever its mapper, to inspect it
+    /// The loader is picked by the magic at the start of the file
+    pub fn parse(data: Vec<u8>, db: &GameDb) -> Result<Cartridge, &'static str> {
+        let mut cartridge = match data.get(0 .. 4) {
+            Some([0x4E, 0x45, 0x53, 0x1A]) => Cartridge::from_ines(data)?,
+            Some(b"UNIF") => unif::load(&data)?,
+            _ => return Err("Unknown ROM format, expected an iNES or UNIF file"),
+        };
+        cartridge.correct(db);
+
+        Ok(cartridge)
+    }
+
+    /// Function that checks if the mapper of the cartridge is implemented
+    pub fn check_mapper(&self) -> Result<(), &'static str> {
+        match self.mapper {
+            0 => Ok(()),
+            _ => Err("the mapper is not implemented, only NROM (mapper 0) cartridges run"),
+        }
+    }
+
+    /// Function that loads an iNES / NES 2.0 file
+    fn from_ines(data: Vec<u8>) -> Result<Cartridge, &'static str> {
         // check if file is valid iNES file
-        if data[0..4] != [0x4E, 0x45, 0x53, 0x1A] {
+        if data.len() < 16 {
             return Err("Invalid iNES file");
         }
 
-        // mapper information
-        let mapper_id = (data[7] & 0xF0) | ((data[6] & 0xF0) >> 4);
-
         // iNes version
-        let ines_version = data[7] & 3;
-        if ines_version != 0 {
-            return Err("Only iNES version 0 is supported");
+        // https://www.nesdev.org/wiki/NES_2.0#Identification
+        let nes2 = data[7] & 0x0C == 0x08;
+        let ines_version = data[7] & 0x0C;
+
+        // iNES headers should end with zeros, tools like DiskDude! wrote their name over bytes 7 - 15
+        // such headers are read as the archaic iNES format, only bytes 4 - 6 are trusted
+        // https://www.nesdev.org/wiki/INES#Bad_headers
+        let dirty = !nes2 && data[12 .. 16].iter().any(|byte| *byte != 0);
+        if ines_version != 0 && !nes2 && !dirty {
+            return Err("Only iNES version 0 and NES 2.0 are supported");
+        }
+
+        let mut corrections = vec![];
+
+        // mapper information
+        // NES 2.0 adds 4 more bits of the mapper number and the submapper in byte 8
+        let mut mapper_id = (data[7] & 0xF0) as u16 | ((data[6] & 0xF0) >> 4) as u16;
+        let submapper = if nes2 { data[8] >> 4 } else { 0 };
+        if nes2 {
+            mapper_id |= ((data[8] & 0x0F) as u16) << 8;
+        }
+        if dirty && data[7] & 0xF0 != 0 {
+            corrections.push(Correction {
+                field: "mapper",
+                header: mapper_id.to_string(),
+                corrected: (mapper_id & 0x0F).to_string(),
+            });
+            mapper_id &= 0x0F;
         }
 
+        // region
+        // https://www.nesdev.org/wiki/NES_2.0#Byte_12_(CPU/PPU_Timing)
+        // https://www.nesdev.org/wiki/INES#Flags_9
+        let region = if nes2 {
+            match data[12] & 0b11 {
+                1 => Region::PAL,
+                3 => Region::Dendy,
+                // 2 is a multi-region game, which runs fine on NTSC
+                _ => Region::NTSC,
+            }
+        } else if data[9] & 1 != 0 && !dirty {
+            Region::PAL
+        } else {
+            Region::NTSC
+        };
+
         // mirroring
-        let four = data[6] & 4 != 0;
+        // bit 3 is four-screen VRAM, bit 2 is the trainer
+        // https://www.nesdev.org/wiki/INES#Flags_6
+        let four = data[6] & 8 != 0;
         let vert = data[6] & 1 != 0;
 
         let mirr = match (four, vert) {
@@ -39,21 +211,127 @@ impl Cartridge {
         };
 
         // rom sizes
-        let prg_rom_size = data[4] as usize * 0x4000;
-        let chr_rom_size = data[5] as usize * 0x2000;
+        // NES 2.0 stores the most significant bits of the sizes in byte 9
+        let (prg_banks, chr_banks) = if nes2 {
+            (
+                data[4] as usize | ((data[9] as usize & 0x0F) << 8),
+                data[5] as usize | ((data[9] as usize & 0xF0) << 4),
+            )
+        } else {
+            (data[4] as usize, data[5] as usize)
+        };
+
+        let prg_rom_size = prg_banks * 0x4000;
+        let chr_rom_size = chr_banks * 0x2000;
+
+        // chr ram is used when there is no chr rom
+        // NES 2.0 specifies the size as a shift count (64 << n), iNES always has 8kB
+        // https://www.nesdev.org/wiki/NES_2.0#CHR-ROM_Area
+        let chr_ram_size = match (chr_rom_size, nes2, data[11] & 0x0F) {
+            (0, true, shift) if shift != 0 => 64 << shift,
+            (0, _, _) => 0x2000,
+            _ => 0,
+        };
+
+        // battery backed PRG-RAM
+        // NES 2.0 has separate shift counts for volatile and battery backed RAM, iNES counts 8kB units
+        // https://www.nesdev.org/wiki/NES_2.0#PRG-(NV)RAM/EEPROM
+        let battery = data[6] & 2 != 0;
+        let prg_ram_size = if nes2 {
+            [data[10] & 0x0F, data[10] >> 4]
+                .iter()
+                .filter(|shift| **shift != 0)
+                .map(|shift| 64 << shift)
+                .sum()
+        } else if dirty || data[8] == 0 {
+            0x2000
+        } else {
+            data[8] as usize * 0x2000
+        };
 
         // trainer
+        // code copiers loaded it into PRG-RAM before starting the game, some hacked dumps need it
         let has_trainer = data[6] & 4 != 0;
 
         // starting indices
         let prg_rom_start = 16 + if has_trainer { 512 } else { 0 };
         let chr_rom_start = prg_rom_start + prg_rom_size;
 
+        if data.len() < chr_rom_start + chr_rom_size {
+            return Err("File is smaller than the ROM sizes in its header");
+        }
+
+        let chr_rom = if chr_ram_size > 0 {
+            vec![0; chr_ram_size]
+        } else {
+            data[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec()
+        };
+
         Ok(Cartridge {
             prg_rom: data[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
-            chr_rom: data[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
+            chr_rom,
+            chr_ram: chr_ram_size > 0,
             mapper: mapper_id,
+            submapper,
+            format: match (nes2, dirty) {
+                (true, _) => HeaderFormat::Nes2,
+                (false, true) => HeaderFormat::Archaic,
+                (false, false) => HeaderFormat::INes,
+            },
             mirroring: mirr,
+            region,
+            battery,
+            prg_ram_size,
+            trainer: has_trainer.then(|| data[16 .. 16 + 512].to_vec()),
+            title: None,
+            corrections,
         })
     }
+
+    /// Function that replaces the header fields the game database knows better
+    fn correct(&mut self, db: &GameDb) {
+        let chr_rom: &[u8] = if self.chr_ram { &[] } else { &self.chr_rom };
+        let Some(entry) = db.lookup(&self.prg_rom, chr_rom).cloned() else {
+            return;
+        };
+
+        fn correct<T: PartialEq + std::fmt::Debug>(
+            corrections: &mut Vec<Correction>,
+            field: &'static str,
+            value: &mut T,
+            corrected: Option<T>,
+        ) {
+            if let Some(corrected) = corrected.filter(|corrected| corrected != value) {
+                corrections.push(Correction {
+                    field,
+                    header: format!("{:?}", value),
+                    corrected: format!("{:?}", corrected),
+                });
+                *value = corrected;
+            }
+        }
+
+        let corrections = &mut self.corrections;
+        correct(corrections, "mapper", &mut self.mapper, entry.mapper);
+        correct(corrections, "submapper", &mut self.submapper, entry.submapper);
+        correct(corrections, "mirroring", &mut self.mirroring, entry.mirroring);
+        correct(corrections, "battery", &mut self.battery, entry.battery);
+        correct(corrections, "PRG-RAM size", &mut self.prg_ram_size, entry.prg_ram);
+        correct(corrections, "region", &mut self.region, entry.region);
+
+        // the CHR-RAM size only means something for games without CHR-ROM
+        if self.chr_ram {
+            let mut size = self.chr_rom.len();
+            correct(corrections, "CHR-RAM size", &mut size, entry.chr_ram.filter(|size| *size > 0));
+            self.chr_rom.resize(size, 0);
+        }
+
+        // UNIF files can have the name of the game
+        self.title = entry.title.or(self.title.take());
+    }
+
+    /// Function that returns the board name of the mapper
+    pub fn mapper_name(&self) -> Option<&'static str> {
+        mapper_name(self.mapper)
+    }
 }
\ No newline at end of file
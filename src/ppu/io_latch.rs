@@ -0,0 +1,81 @@
+// https://www.nesdev.org/wiki/Open_bus_behavior#PPU_open_bus
+
+use crate::state::{Savestate, StateError, StateReader, StateWriter};
+
+/// Number of frames after which an unrefreshed bit of the latch decays to 0
+/// The real hardware decays after roughly 600 ms, which is ~36 NTSC frames
+pub const DECAY_FRAMES: u64 = 36;
+
+/// Class representing the PPU I/O latch (the PPU data bus between CPU and PPU)
+/// Every write to a PPU register fills the latch and every read refreshes
+/// the bits that were actually driven by the PPU. Reads of write-only registers
+/// return the latch, whose bits slowly decay to 0 when they are not refreshed.
+#[derive(Debug)]
+pub struct IoLatch {
+    /// Current value of the latch
+    value: u8,
+
+    /// Frame in which each bit was last refreshed
+    refreshed: [u64; 8],
+}
+
+impl Default for IoLatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoLatch {
+    /// Create a new I/O latch
+    pub fn new() -> Self {
+        IoLatch {
+            value: 0,
+            refreshed: [0; 8],
+        }
+    }
+
+    /// Function that drives the bits selected by mask with the given value
+    pub fn refresh(&mut self, val: u8, mask: u8, frame: u64) {
+        self.value = (self.value & !mask) | (val & mask);
+
+        for bit in 0..8 {
+            if mask & (1 << bit) != 0 {
+                self.refreshed[bit] = frame;
+            }
+        }
+    }
+
+    /// Function that drives all 8 bits of the latch (used by register writes)
+    pub fn write(&mut self, val: u8, frame: u64) {
+        self.refresh(val, 0xFF, frame);
+    }
+
+    /// Function that returns the value of the latch, applying the decay first
+    pub fn read(&mut self, frame: u64) -> u8 {
+        for bit in 0..8 {
+            if frame.saturating_sub(self.refreshed[bit]) >= DECAY_FRAMES {
+                self.value &= !(1 << bit);
+            }
+        }
+
+        self.value
+    }
+}
+
+impl Savestate for IoLatch {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.value);
+        for frame in self.refreshed {
+            writer.u64(frame);
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.value = reader.u8()?;
+        for frame in self.refreshed.iter_mut() {
+            *frame = reader.u64()?;
+        }
+
+        Ok(())
+    }
+}
@@ -2,18 +2,26 @@
 
 use crate::byte_status::ByteStatus;
 use crate::flags::Mask;
+use crate::region::Region;
 
 #[derive(Debug)]
 pub struct MaskRegister {
     pub value: u8,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Color {
     Red,
     Green,
     Blue,
 }
 
+impl Default for MaskRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MaskRegister {
     pub fn new() -> Self {
         MaskRegister {
@@ -21,13 +29,21 @@ impl MaskRegister {
         }
     }
     
-    pub fn color_emphasis(&self) -> Vec<Color> {
+    /// Function that returns the emphasized colors
+    /// PAL and Dendy PPUs have the red and green bits swapped
+    pub fn color_emphasis(&self, region: Region) -> Vec<Color> {
+        let (red, green) = if region.swaps_emphasis() {
+            (Mask::Green, Mask::Red)
+        } else {
+            (Mask::Red, Mask::Green)
+        };
+
         let mut result = Vec::<Color>::new();
-        if self.is_set(Mask::Red.as_u8()) {
+        if self.is_set(red.as_u8()) {
             result.push(Color::Red);
         }
         
-        if self.is_set(Mask::Green.as_u8()) {
+        if self.is_set(green.as_u8()) {
             result.push(Color::Green);
         }
         
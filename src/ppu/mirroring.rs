@@ -1,11 +1,78 @@
 // https://www.nesdev.org/wiki/Mirroring
 
+use crate::state::{Savestate, StateError, StateReader, StateWriter};
+
 /// Mirroring modes for the PPU
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(non_camel_case_types)]
 pub enum Mirroring {
     Horizontal,
     Vertical,
     FourScreen,
-    // SingleScreen - only certain mappers
-}
\ No newline at end of file
+
+    /// Single-screen mirroring using the lower bank of CIRAM - only certain mappers
+    SingleScreenA,
+
+    /// Single-screen mirroring using the upper bank of CIRAM - only certain mappers
+    SingleScreenB,
+}
+
+impl Mirroring {
+    /// Function that maps a name table address ($2000 - $3EFF) to an index into
+    /// the name table memory ($0000 - $0FFF)
+    /// Pages 0 and 1 ($0000 - $07FF) are the 2kB CIRAM inside the console,
+    /// pages 2 and 3 ($0800 - $0FFF) are the extra VRAM of four-screen cartridges
+    /// https://www.nesdev.org/wiki/Mirroring#Nametable_Mirroring
+    pub fn nametable_index(&self, addr: u16) -> u16 {
+        // mirror down addresses in the range 0x3000-0x3EFF to 0x2000-0x2EFF
+        // and convert the address to a VRAM index (0x0000 - 0x0FFF)
+        let vram_index = (addr & 0x2FFF) - 0x2000;
+
+        // determine the logical name table and the offset inside of it
+        let name_table = vram_index / 0x400;
+        let offset = vram_index % 0x400;
+
+        // determine the physical page based on the mirroring mode
+        let page = match self {
+            // vertical mirroring: tables 2 and 3 map back to 0 and 1
+            Mirroring::Vertical => name_table & 1,
+
+            // horizontal mirroring: tables 0, 1 map to page 0 and tables 2, 3 to page 1
+            Mirroring::Horizontal => name_table >> 1,
+
+            // every table has its own page
+            Mirroring::FourScreen => name_table,
+
+            // all tables map to a single page
+            Mirroring::SingleScreenA => 0,
+            Mirroring::SingleScreenB => 1,
+        };
+
+        page * 0x400 + offset
+    }
+}
+
+impl Savestate for Mirroring {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(match self {
+            Mirroring::Horizontal => 0,
+            Mirroring::Vertical => 1,
+            Mirroring::FourScreen => 2,
+            Mirroring::SingleScreenA => 3,
+            Mirroring::SingleScreenB => 4,
+        });
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        *self = match reader.u8()? {
+            0 => Mirroring::Horizontal,
+            1 => Mirroring::Vertical,
+            2 => Mirroring::FourScreen,
+            3 => Mirroring::SingleScreenA,
+            4 => Mirroring::SingleScreenB,
+            _ => return Err(StateError::InvalidValue("mirroring mode")),
+        };
+
+        Ok(())
+    }
+}
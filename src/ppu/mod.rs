@@ -3,6 +3,8 @@ mod address_register;
 mod controller_register;
 pub mod mirroring;
 pub mod cartridge;
-mod mask_register;
+pub mod unif;
+pub mod mask_register;
 mod status_register;
-mod scroll_register;
\ No newline at end of file
+mod scroll_register;
+mod io_latch;
\ No newline at end of file
@@ -2,10 +2,13 @@ use crate::byte_status::ByteStatus;
 use crate::flags::{Mask, PPUStatus};
 use crate::ppu::address_register::AddressRegister;
 use crate::ppu::controller_register::ControllerRegister;
-use crate::ppu::mask_register::MaskRegister;
+use crate::ppu::io_latch::IoLatch;
+use crate::ppu::mask_register::{Color, MaskRegister};
 use crate::ppu::mirroring::Mirroring;
 use crate::ppu::scroll_register::ScrollRegister;
 use crate::ppu::status_register::StatusRegister;
+use crate::region::Region;
+use crate::state::{Savestate, StateError, StateReader, StateWriter};
 
 /// Class representing the PPU
 /// https://www.nesdev.org/wiki/PPU
@@ -16,6 +19,10 @@ pub struct PPU {
     /// 2kB of RAM dedicated to PPU
     pub ram: [u8; 2048],
 
+    /// Extra 2kB of name table memory on four-screen cartridges
+    /// https://www.nesdev.org/wiki/Mirroring#4-Screen
+    pub cart_vram: Vec<u8>,
+
     /// Palette tables
     /// 32 bytes of palette data
     pub palette: [u8; 32],
@@ -23,6 +30,9 @@ pub struct PPU {
     /// Visuals of the cartridge
     pub chr: Vec<u8>,
 
+    /// The visuals are stored in writable CHR-RAM
+    chr_ram: bool,
+
     /// Internal memory storing sprites
     /// max. 64 sprites (4 bytes each) = 256 bytes
     /// https://www.nesdev.org/wiki/PPU_OAM
@@ -51,14 +61,33 @@ pub struct PPU {
     /// Internal buffer for reading and writing
     internal_buffer: u8,
 
+    /// I/O latch between the CPU and the PPU (open bus)
+    /// https://www.nesdev.org/wiki/Open_bus_behavior#PPU_open_bus
+    io_latch: IoLatch,
+
     /// Cycle counter
     cycles: usize,
 
     /// Scanline counter
     scanline: u16,
 
+    /// Frame counter
+    frame: u64,
+
+    /// Odd frames skip the last dot of the pre-render scanline
+    odd_frame: bool,
+
+    /// Set when $2002 was read one dot before the vblank flag gets set
+    suppress_vblank: bool,
+
     /// NMI Interrupt
     pub nmi: bool,
+
+    /// Set when the visible part of a frame is done (start of vblank), whether NMI is enabled or not
+    frame_ready: bool,
+
+    /// Region determining the frame timing
+    region: Region,
 }
 
 impl PPU {
@@ -66,8 +95,13 @@ impl PPU {
     pub fn new(chr: Vec<u8>, mirroring: Mirroring) -> Self {
         PPU {
             ram: [0; 2048],
+            cart_vram: match mirroring {
+                Mirroring::FourScreen => vec![0; 0x800],
+                _ => vec![],
+            },
             palette: [0; 32],
             chr,
+            chr_ram: false,
             oam: [0; 256],
             oam_address: 0,
             mirroring,
@@ -77,59 +111,161 @@ impl PPU {
             scroll_register: ScrollRegister::new(),
             address_register: AddressRegister::new(),
             internal_buffer: 0,
+            io_latch: IoLatch::new(),
             cycles: 0,
             scanline: 0,
+            frame: 0,
+            odd_frame: false,
+            suppress_vblank: false,
             nmi: false,
+            frame_ready: false,
+            region: Region::NTSC,
         }
     }
 
+    /// Function that puts the PPU into its power on state
+    /// CHR-ROM stays, CHR-RAM is cleared, the frame counter keeps counting
+    pub fn power_cycle(&mut self) {
+        let mut chr = std::mem::take(&mut self.chr);
+        if self.chr_ram {
+            chr.fill(0);
+        }
+
+        let mut ppu = PPU::new(chr, self.mirroring);
+        ppu.chr_ram = self.chr_ram;
+        ppu.region = self.region;
+        ppu.frame = self.frame;
+        *self = ppu;
+    }
+
     /// Create a new PPU with an empty ROM
     pub fn new_empty_rom() -> Self {
         PPU::new(vec![0; 2048], Mirroring::Horizontal)
     }
 
+    /// Function that makes the pattern tables writable (CHR-RAM)
+    pub fn set_chr_ram(&mut self, chr_ram: bool) {
+        self.chr_ram = chr_ram;
+    }
+
+    /// Function that sets the region of the PPU
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// Function that returns the region of the PPU
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
     /// Function that ticks the PPU
-    /// It ticks 3 times faster than the CPU
+    /// It ticks 3 (NTSC, Dendy) or 3.2 (PAL) times faster than the CPU
     /// It's used to determine which scanline the PPU is currently rendering
+    /// Returns true when a new frame has started
     /// https://wiki.nesdev.com/w/index.php/PPU_rendering
     pub fn tick(&mut self, cycles: u8) -> bool {
-        // total scanlines: 262
-        // one scanline -> 341 cycles
-        // nmi interrupt on scanline 241
+        let mut new_frame = false;
 
-        self.cycles += cycles as usize;
+        for _ in 0 .. cycles {
+            new_frame |= self.step();
+        }
+
+        new_frame
+    }
 
-        if self.cycles >= 341 {
+    /// Function that advances the PPU by a single dot
+    /// https://www.nesdev.org/wiki/PPU_frame_timing
+    fn step(&mut self) -> bool {
+        // total scanlines: 262 (NTSC) or 312 (PAL, Dendy), the last one is the pre-render scanline
+        // one scanline -> 341 dots
+        // vblank flag and NMI on dot 1 of scanline 241 (NTSC, PAL) or 291 (Dendy)
+        let pre_render = self.region.scanlines() - 1;
+
+        // on odd NTSC frames with rendering enabled the last dot of the pre-render scanline is skipped
+        let skip = self.scanline == pre_render
+            && self.cycles == 339
+            && self.odd_frame
+            && self.region == Region::NTSC
+            && self.rendering_enabled();
+
+        if self.cycles == 340 || skip {
             if self.sprite_zero_hit(self.cycles) {
                 self.status_register.add(PPUStatus::Sprite0Hit.as_u8());
             }
-            
-            self.cycles -= 341;
+
+            self.cycles = 0;
             self.scanline += 1;
 
-            if self.scanline == 241 {
-                // set the vblank flag
-                self.status_register.add(PPUStatus::Vblank.as_u8());
-                self.status_register.remove(PPUStatus::Sprite0Hit.as_u8());
+            if self.scanline > pre_render {
+                self.scanline = 0;
+                self.frame += 1;
+                self.odd_frame = !self.odd_frame;
+                return true;
+            }
+
+            return false;
+        }
 
-                // trigger NMI
-                if self.controller_register.vblank() {
-                    self.nmi = true;
+        self.cycles += 1;
+
+        if self.cycles == 1 {
+            if self.scanline == self.region.vblank_scanline() {
+                self.frame_ready = true;
+
+                // set the vblank flag, unless $2002 was read one dot earlier
+                if !self.suppress_vblank {
+                    self.status_register.add(PPUStatus::Vblank.as_u8());
+
+                    // trigger NMI
+                    if self.controller_register.vblank() {
+                        self.nmi = true;
+                    }
                 }
-            }
 
-            if self.scanline >= 262 {
-                self.scanline = 0;
+                self.suppress_vblank = false;
+            } else if self.scanline == pre_render {
+                // clear the flags on the pre-render scanline
                 self.nmi = false;
-                self.status_register.remove(PPUStatus::Sprite0Hit.as_u8());
                 self.status_register.remove(PPUStatus::Vblank.as_u8());
-                return true;
+                self.status_register.remove(PPUStatus::Sprite0Hit.as_u8());
+                self.status_register.remove(PPUStatus::SpriteOverflow.as_u8());
             }
         }
 
         false
     }
 
+    /// Function that returns true once when a frame is done
+    /// Games turn NMI off while loading, the frame boundary doesn't depend on it
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
+    /// Function that checks if the background or sprite rendering is enabled
+    pub fn rendering_enabled(&self) -> bool {
+        self.mask_register.is_set(Mask::Background.as_u8()) || self.mask_register.is_set(Mask::Sprite.as_u8())
+    }
+
+    /// Value of the mask register ($2001)
+    pub fn mask(&self) -> u8 {
+        self.mask_register.value
+    }
+
+    /// Current scanline (0 - 239 visible, the last one is the pre-render scanline)
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    /// Current dot of the scanline (0 - 340)
+    pub fn dot(&self) -> usize {
+        self.cycles
+    }
+
+    /// Number of frames rendered since power on
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
     fn sprite_zero_hit(&self, cycle: usize) -> bool {
         let y = self.oam[0] as usize;
         let x = self.oam[3] as usize;
@@ -137,37 +273,94 @@ impl PPU {
     }
 
     /// Handle mirroring of the PPU
+    /// Every name table access goes through this function, mappers with
+    /// switchable mirroring change its behavior through `set_mirroring`
     /// https://www.nesdev.org/wiki/Mirroring#Nametable_Mirroring
     pub fn mirror(&self, addr: u16) -> u16 {
-        // mirror down addresses in the range 0x3000-0x3EFF to 0x2000-0x2EFF
-        let mirrored_vram = addr & 0x2FFF;
+        self.mirroring.nametable_index(addr)
+    }
 
-        // convert the address to a VRAM index (0x0000 - 0x0FFF)
-        let vram_index = mirrored_vram - 0x2000;
+    /// Function that changes the mirroring mode (used by mappers)
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        if mirroring == Mirroring::FourScreen && self.cart_vram.is_empty() {
+            self.cart_vram = vec![0; 0x800];
+        }
 
-        // determine the name table
-        let name_table = vram_index / 0x400;
+        self.mirroring = mirroring;
+    }
 
-        // calculate the effective VRAM index based on mirroring mode and name table
-        let effective_index = match (&self.mirroring, name_table) {
-            // vertical mirroring: map tables 2 and 3 back to 0 and 1
-            (Mirroring::Vertical, 2) | (Mirroring::Vertical, 3) => vram_index - 0x800,
+    /// Function that returns the mirroring mode
+    pub fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
 
-            // horizontal mirroring:
-            // table 2 maps to table 0
-            (Mirroring::Horizontal, 2) => vram_index - 0x400,
+    /// Function that reads a byte from the name table memory
+    fn read_vram(&self, addr: u16) -> u8 {
+        let index = self.mirror(addr) as usize;
 
-            // table 3 maps to table 1
-            (Mirroring::Horizontal, 1) => vram_index - 0x400,
+        if index < self.ram.len() {
+            self.ram[index]
+        } else {
+            self.cart_vram[index - self.ram.len()]
+        }
+    }
 
-            // table 3 maps to table 1
-            (Mirroring::Horizontal, 3) => vram_index - 0x800,
+    /// Function that writes a byte to the name table memory
+    fn write_vram(&mut self, addr: u16, val: u8) {
+        let index = self.mirror(addr) as usize;
 
-            // no adjustment needed for tables 0 and 1 in both mirroring types
-            _ => vram_index,
-        };
+        if index < self.ram.len() {
+            self.ram[index] = val;
+        } else {
+            self.cart_vram[index - self.ram.len()] = val;
+        }
+    }
+
+    /// Function that reads the PPU address space without side effects, used by the debugger
+    pub fn peek(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+
+        match addr {
+            0x0000 ..= 0x1FFF => self.chr[addr as usize % self.chr.len()],
+            0x2000 ..= 0x3EFF => self.read_vram(addr),
+            _ => self.palette[PPU::palette_index(addr)],
+        }
+    }
+
+    /// Function that returns the 1kB of memory backing one of the four logical name tables
+    pub fn nametable(&self, table: u16) -> &[u8] {
+        let start = self.mirror(0x2000 + (table & 0b11) * 0x400) as usize;
 
-        effective_index
+        if start < self.ram.len() {
+            &self.ram[start .. start + 0x400]
+        } else {
+            &self.cart_vram[start - self.ram.len() .. start - self.ram.len() + 0x400]
+        }
+    }
+
+    /// Function that maps a palette address ($3F00 - $3FFF) to an index into the palette RAM
+    /// $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
+    /// https://www.nesdev.org/wiki/PPU_palettes#Memory_Map
+    pub fn palette_index(addr: u16) -> usize {
+        let index = (addr & 0x1F) as usize;
+
+        if index & 0x13 == 0x10 {
+            index & 0x0F
+        } else {
+            index
+        }
+    }
+
+    /// Function that reads a value from the palette RAM
+    /// The palette RAM is only 6 bits wide and greyscale mode masks off the hue
+    fn read_palette(&self, addr: u16) -> u8 {
+        let val = self.palette[PPU::palette_index(addr)];
+
+        if self.mask_register.is_set(Mask::Greyscale.as_u8()) {
+            val & 0x30
+        } else {
+            val
+        }
     }
 
     // dummy read/write operations
@@ -182,64 +375,62 @@ impl PPU {
         self.address_register.add(self.controller_register.vram_increment());
 
         // https://www.nesdev.org/wiki/PPU_memory_map
-        match addr {
+        let res = match addr {
             0x0000 ..= 0x1FFF => {
                 // pattern tables
                 let res = self.internal_buffer;
-                self.internal_buffer = self.chr[addr as usize];
+                self.internal_buffer = self.chr[addr as usize % self.chr.len()];
                 res
             },
-            0x2000 ..= 0x2FFF => {
-                // name tables
+            0x2000 ..= 0x3EFF => {
+                // name tables (0x3000 - 0x3EFF mirrors 0x2000 - 0x2EFF)
                 let res = self.internal_buffer;
-                self.internal_buffer = self.ram[self.mirror(addr) as usize];
+                self.internal_buffer = self.read_vram(addr);
                 res
             },
-            0x3000 ..= 0x3EFF => {
-                // unused
-                // panic!("Reading from 0x3000 - 0x3EFF is not expected");
-                0
-            },
-            0x3F10 | 0x3F14 | 0x3F18 | 0x3F1C => {
-                // mirror of 0x3F00 - 0x3F0F
-                self.palette[(addr - 0x3F00 - 0x10) as usize]
-            },
             0x3F00 ..= 0x3FFF => {
                 // palette
-                self.palette[(addr - 0x3F00) as usize]
+                // palette reads are not buffered, but the buffer is still filled
+                // with the name table byte "underneath" the palette
+                self.internal_buffer = self.read_vram(addr - 0x1000);
+
+                // top two bits come from the open bus
+                let res = self.read_palette(addr) | (self.io_latch.read(self.frame) & 0xC0);
+                self.io_latch.refresh(res, 0x3F, self.frame);
+                return res;
             },
             _ => {
                 panic!("Reading from address {:04X} is not expected", addr);
             }
-        }
+        };
+
+        self.io_latch.write(res, self.frame);
+        res
     }
 
     /// Write to the PPU
     pub fn write(&mut self, val: u8) {
+        self.io_latch.write(val, self.frame);
+
         // get the address from the address register
         let addr = self.address_register.get();
 
         match addr {
             0x0000 ..= 0x1FFF => {
                 // pattern tables
-                // panic!("Writing to 0x0000 - 0x1FFF (CHR) is not expected");
-                
-            },
-            0x2000 ..= 0x2FFF => {
-                // name tables
-                self.ram[self.mirror(addr) as usize] = val;
-            },
-            0x3000 ..= 0x3EFF => {
-                // unused
-                // panic!("Writing to 0x3000 - 0x3EFF is not expected");
+                // writes to CHR-ROM are ignored
+                if self.chr_ram {
+                    let len = self.chr.len();
+                    self.chr[addr as usize % len] = val;
+                }
             },
-            0x3F10 | 0x3F14 | 0x3F18 | 0x3F1C => {
-                // mirror of 0x3F00 - 0x3F0F
-                self.palette[(addr - 0x3F00 - 0x10) as usize] = val;
+            0x2000 ..= 0x3EFF => {
+                // name tables (0x3000 - 0x3EFF mirrors 0x2000 - 0x2EFF)
+                self.write_vram(addr, val);
             },
             0x3F00 ..= 0x3FFF => {
                 // palette
-                self.palette[(addr - 0x3F00) as usize] = val;
+                self.palette[PPU::palette_index(addr)] = val & 0x3F;
             },
             _ => {
                 panic!("Writing to address {:04X} is not expected", addr);
@@ -250,8 +441,29 @@ impl PPU {
         self.address_register.add(self.controller_register.vram_increment());
     }
 
+    /// Function that returns the value of the I/O latch
+    /// This is what the CPU sees when reading a write-only PPU register
+    pub fn read_open_bus(&mut self) -> u8 {
+        self.io_latch.read(self.frame)
+    }
+
+    /// Function that reads PPUSTATUS
+    /// Reading it around the time the vblank flag gets set races with the flag and the NMI:
+    /// one dot before the flag reads as clear and is never set for this frame,
+    /// on the same dot or one dot later the flag reads as set, but the NMI is cancelled
+    /// https://www.nesdev.org/wiki/PPU_frame_timing#VBL_Flag_Timing
     pub fn read_status_register(&mut self) -> u8 {
-        let res = self.status_register.value;
+        if self.scanline == self.region.vblank_scanline() {
+            match self.cycles {
+                0 => self.suppress_vblank = true,
+                1 | 2 => self.nmi = false,
+                _ => {},
+            }
+        }
+
+        // only the top 3 bits are driven, the rest comes from the open bus
+        let res = (self.status_register.value & 0xE0) | (self.io_latch.read(self.frame) & 0x1F);
+        self.io_latch.refresh(res, 0xE0, self.frame);
 
         // clear the vblank flag
         self.status_register.remove(PPUStatus::Vblank.as_u8());
@@ -274,36 +486,55 @@ impl PPU {
     }
 
     pub fn read_oam_data(&mut self) -> u8 {
-        self.oam[self.oam_address as usize]
+        let res = self.oam[self.oam_address as usize];
+        self.io_latch.write(res, self.frame);
+        res
     }
 
     pub fn write_oam_data(&mut self, val: u8) {
+        self.io_latch.write(val, self.frame);
         self.oam[self.oam_address as usize] = val;
         self.oam_address = self.oam_address.wrapping_add(1);
     }
 
     pub fn write_oam_address(&mut self, val: u8) {
+        self.io_latch.write(val, self.frame);
         self.oam_address = val;
     }
 
     pub fn write_control_register(&mut self, val: u8) {
+        self.io_latch.write(val, self.frame);
         let before_nmi = self.controller_register.vblank();
         self.controller_register.set_bits(val);
 
+        // enabling NMI during vblank triggers it immediately
         if !before_nmi && self.controller_register.vblank() && self.status_register.is_set(PPUStatus::Vblank.as_u8()) {
             self.nmi = true;
         }
+
+        // disabling NMI before the CPU notices it cancels it
+        if !self.controller_register.vblank() {
+            self.nmi = false;
+        }
+    }
+
+    /// Function that returns the color emphasis bits of PPUMASK
+    pub fn color_emphasis(&self) -> Vec<Color> {
+        self.mask_register.color_emphasis(self.region)
     }
 
     pub fn write_mask_register(&mut self, val: u8) {
+        self.io_latch.write(val, self.frame);
         self.mask_register.set_bits(val);
     }
 
     pub fn write_scroll_register(&mut self, val: u8) {
+        self.io_latch.write(val, self.frame);
         self.scroll_register.write(val);
     }
 
     pub fn write_address_register(&mut self, val: u8) {
+        self.io_latch.write(val, self.frame);
         self.address_register.set(val);
     }
 
@@ -317,3 +548,82 @@ impl PPU {
     }
 }
 
+impl Savestate for PPU {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+        writer.buffer(&self.cart_vram);
+        writer.bytes(&self.palette);
+
+        // CHR-ROM can't change, so it's only part of the state with CHR-RAM
+        writer.bool(self.chr_ram);
+        if self.chr_ram {
+            writer.buffer(&self.chr);
+        }
+
+        writer.bytes(&self.oam);
+        writer.u8(self.oam_address);
+        self.mirroring.save(writer);
+
+        writer.u8(self.controller_register.value);
+        writer.u8(self.mask_register.value);
+        writer.u8(self.status_register.value);
+        self.scroll_register.save(writer);
+        self.address_register.save(writer);
+
+        writer.u8(self.internal_buffer);
+        self.io_latch.save(writer);
+
+        writer.u64(self.cycles as u64);
+        writer.u16(self.scanline);
+        writer.u64(self.frame);
+        writer.bool(self.odd_frame);
+        writer.bool(self.suppress_vblank);
+        writer.bool(self.nmi);
+        self.region.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.ram)?;
+        self.cart_vram = reader.buffer()?;
+        reader.bytes_into(&mut self.palette)?;
+
+        if reader.bool()? != self.chr_ram {
+            return Err(StateError::InvalidValue("CHR memory type"));
+        }
+
+        if self.chr_ram {
+            reader.buffer_into(&mut self.chr, "CHR-RAM size")?;
+        }
+
+        reader.bytes_into(&mut self.oam)?;
+        self.oam_address = reader.u8()?;
+        self.mirroring.load(reader)?;
+
+        self.controller_register.value = reader.u8()?;
+        self.mask_register.value = reader.u8()?;
+        self.status_register.value = reader.u8()?;
+        self.scroll_register.load(reader)?;
+        self.address_register.load(reader)?;
+
+        self.internal_buffer = reader.u8()?;
+        self.io_latch.load(reader)?;
+
+        self.cycles = reader.u64()? as usize;
+        self.scanline = reader.u16()?;
+        self.frame = reader.u64()?;
+        self.odd_frame = reader.bool()?;
+        self.suppress_vblank = reader.bool()?;
+        self.nmi = reader.bool()?;
+        self.region.load(reader)?;
+
+        if self.cycles > 340 || self.scanline >= self.region.scanlines() {
+            return Err(StateError::InvalidValue("PPU position"));
+        }
+
+        if self.mirroring == Mirroring::FourScreen && self.cart_vram.len() != 0x800 {
+            return Err(StateError::InvalidValue("four-screen VRAM size"));
+        }
+
+        Ok(())
+    }
+}
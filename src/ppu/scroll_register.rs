@@ -1,5 +1,7 @@
 // https://www.nesdev.org/wiki/PPU_registers#PPUSCROLL_-_X_and_Y_scroll_($2005_write)
 
+use crate::state::{Savestate, StateError, StateReader, StateWriter};
+
 /// Class representing a PPU Scroll Register $2005
 #[derive(Debug)]
 pub struct ScrollRegister {
@@ -29,4 +31,19 @@ impl ScrollRegister {
     pub fn reset_latch(&mut self) {
         self.latch = false;
     }
-}
\ No newline at end of file
+}
+
+impl Savestate for ScrollRegister {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.scroll_x);
+        writer.u8(self.scroll_y);
+        writer.bool(self.latch);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.scroll_x = reader.u8()?;
+        self.scroll_y = reader.u8()?;
+        self.latch = reader.bool()?;
+        Ok(())
+    }
+}
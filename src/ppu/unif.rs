@@ -0,0 +1,286 @@
+// https://www.nesdev.org/wiki/UNIF
+// https://www.nesdev.org/wiki/UNIF_to_NES_2.0_Mapping
+//
+// UNIF files describe the cartridge with chunks instead of a fixed header
+// [header] => "UNIF", revision number, 24 reserved bytes
+// [chunk] => 4 character ID, 32-bit little endian length, data
+//
+// MAPR    board name, mapped to a mapper number
+// PRG0-F  PRG-ROM chips, concatenated in the order of their number
+// CHR0-F  CHR-ROM chips, CHR-RAM is used when there are none
+// MIRR    mirroring, BATR battery, TVCI TV system, NAME game title
+// CTRL    input devices, only the standard controller is emulated so it is only validated
+// Other chunks (READ, DINF, PCK0, ...) are skipped
+
+use crate::ppu::cartridge::{Cartridge, HeaderFormat};
+use crate::ppu::mirroring::Mirroring;
+use crate::region::Region;
+
+/// Prefixes of the board names, NES- and HVC- are licensed boards, the rest pirate and multicart ones
+const BOARD_PREFIXES: [&str; 6] = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-"];
+
+/// Function that maps a UNIF board name to the iNES / NES 2.0 mapper number implementing it
+pub fn board_mapper(board: &str) -> Option<u16> {
+    let board = BOARD_PREFIXES.iter().find_map(|prefix| board.strip_prefix(prefix)).unwrap_or(board);
+
+    Some(match board {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => 0,
+        "SAROM" | "SBROM" | "SCROM" | "SEROM" | "SFROM" | "SGROM" | "SHROM" | "SJROM" | "SKROM" | "SLROM"
+        | "SL1ROM" | "SNROM" | "SOROM" | "SUROM" | "SXROM" => 1,
+        "UNROM" | "UOROM" | "UN1ROM" => 2,
+        "CNROM" => 3,
+        "TBROM" | "TEROM" | "TFROM" | "TGROM" | "TKROM" | "TLROM" | "TL1ROM" | "TNROM" | "TR1ROM" | "TSROM"
+        | "TVROM" | "B4" => 4,
+        "EKROM" | "ELROM" | "ETROM" | "EWROM" => 5,
+        "ANROM" | "AN1ROM" | "AMROM" | "AOROM" => 7,
+        "PNROM" | "PEEOROM" => 9,
+        "FJROM" | "FKROM" => 10,
+        "CPROM" => 13,
+        "BNROM" | "NINA-001" => 34,
+        "GNROM" | "MHROM" => 66,
+        "TQROM" => 119,
+        "TKSROM" | "TLSROM" => 118,
+        "DEROM" | "DE1ROM" | "DRROM" => 206,
+
+        // pirate and multicart boards that only have a number in NES 2.0
+        "SL1632" => 14,
+        "AC08" => 42,
+        "SuperHIK8in1" => 45,
+        "MARIO1-MALEE2" => 55,
+        "D1038" => 59,
+        "Super700in1" => 62,
+        "BB" => 108,
+        "H2288" => 123,
+        "LH32" => 125,
+        "22211" => 132,
+        "SA-72008" => 133,
+        "Sachen-8259D" => 137,
+        "Sachen-8259B" => 138,
+        "Sachen-8259C" => 139,
+        "Sachen-8259A" => 141,
+        "KS7032" => 142,
+        "SA-72007" => 145,
+        "SA-016-1M" => 146,
+        "TC-U01-1.5M" => 147,
+        "SA-0037" => 148,
+        "SA-0036" => 149,
+        "Sachen-74LS374N" => 150,
+        "FK23C" | "FK23CA" | "Super24in1SC03" => 176,
+        "8237" => 215,
+        "A9746" => 219,
+        "N625092" => 221,
+        "42in1ResetSwitch" => 233,
+        "70in1" | "70in1B" => 236,
+        "603-5052" => 238,
+        "OneBus" => 256,
+        "158B" => 258,
+        "810544-C-A1" => 261,
+        "SHERO" => 262,
+        "KOF97" => 263,
+        "YOKO" => 264,
+        "T-262" => 265,
+        "CITYFIGHT" => 266,
+        "GS-2004" | "GS-2013" => 283,
+        "A65AS" => 285,
+        "BS-5" => 286,
+        "411120-C" => 287,
+        "NTD-03" => 290,
+        "DRAGONFIGHTER" => 292,
+        "TF1201" => 298,
+        "190in1" => 300,
+        "8157" => 301,
+        "KS7057" => 302,
+        "KS7017" => 303,
+        "SMB2J" => 304,
+        "KS7031" => 305,
+        "KS7016" => 306,
+        "KS7037" => 307,
+        "KS7013B" => 312,
+        "64in1NoRepeat" => 314,
+        "MALISB" => 325,
+        "RT-01" => 328,
+        "EDU2000" => 329,
+        "12-IN-1" => 331,
+        "WS" => 332,
+        "830118C" => 348,
+        "EH8813A" => 519,
+        "T-230" => 529,
+        "AX5705" => 530,
+        _ => return None,
+    })
+}
+
+/// Function that loads a UNIF file
+pub fn load(data: &[u8]) -> Result<Cartridge, &'static str> {
+    if data.len() < 32 || &data[0 .. 4] != b"UNIF" {
+        return Err("Invalid UNIF file");
+    }
+
+    let mut board = None;
+    let mut prg_chips: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr_chips: [Option<&[u8]>; 16] = [None; 16];
+    let mut mirroring = Mirroring::Horizontal;
+    let mut battery = false;
+    let mut region = Region::NTSC;
+    let mut title = None;
+
+    let mut at = 32;
+    while at < data.len() {
+        let header = data.get(at .. at + 8).ok_or("UNIF chunk header is truncated")?;
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let chunk = data.get(at + 8 .. at + 8 + len).ok_or("UNIF chunk is truncated")?;
+        let first = chunk.first().copied();
+
+        // strings are null terminated
+        let text = || String::from_utf8_lossy(chunk.split(|byte| *byte == 0).next().unwrap_or_default()).trim().to_string();
+        let chip = || u8::from_str_radix(std::str::from_utf8(&header[3 .. 4]).unwrap_or("-"), 16).ok().map(|chip| chip as usize);
+
+        match &header[0 .. 4] {
+            b"MAPR" => board = Some(text()),
+            b"NAME" => title = Some(text()).filter(|name| !name.is_empty()),
+            [b'P', b'R', b'G', _] => prg_chips[chip().ok_or("invalid UNIF PRG chunk")?] = Some(chunk),
+            [b'C', b'H', b'R', _] => chr_chips[chip().ok_or("invalid UNIF CHR chunk")?] = Some(chunk),
+            b"MIRR" => {
+                mirroring = match first.ok_or("empty UNIF MIRR chunk")? {
+                    0 => Mirroring::Horizontal,
+                    1 => Mirroring::Vertical,
+                    2 => Mirroring::SingleScreenA,
+                    3 => Mirroring::SingleScreenB,
+                    4 => Mirroring::FourScreen,
+                    // controlled by the mapper, which starts as horizontal
+                    5 => Mirroring::Horizontal,
+                    _ => return Err("invalid UNIF mirroring"),
+                }
+            },
+            b"BATR" => battery = true,
+            b"TVCI" => {
+                region = match first.ok_or("empty UNIF TVCI chunk")? {
+                    1 => Region::PAL,
+                    // 2 works on both, which runs fine on NTSC
+                    0 | 2 => Region::NTSC,
+                    _ => return Err("invalid UNIF TV system"),
+                }
+            },
+            b"CTRL" => {
+                first.ok_or("empty UNIF CTRL chunk")?;
+            },
+            _ => {},
+        }
+
+        at += 8 + len;
+    }
+
+    let board = board.ok_or("UNIF file has no board name (MAPR)")?;
+    let mapper = board_mapper(&board).ok_or("UNIF board is not supported")?;
+
+    let prg_rom: Vec<u8> = prg_chips.iter().flatten().flat_map(|chip| chip.iter().copied()).collect();
+    if prg_rom.is_empty() {
+        return Err("UNIF file has no PRG-ROM");
+    }
+
+    let chr_rom: Vec<u8> = chr_chips.iter().flatten().flat_map(|chip| chip.iter().copied()).collect();
+    let chr_ram = chr_rom.is_empty();
+
+    Ok(Cartridge {
+        prg_rom,
+        chr_rom: if chr_ram { vec![0; 0x2000] } else { chr_rom },
+        chr_ram,
+        mapper,
+        submapper: 0,
+        format: HeaderFormat::Unif,
+        mirroring,
+        region,
+        battery,
+        prg_ram_size: 0x2000,
+        trainer: None,
+        title,
+        corrections: vec![],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gamedb::GameDb;
+
+    /// Function that builds a UNIF file from (ID, data) chunks
+    fn unif(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut res = b"UNIF".to_vec();
+        res.extend(7u32.to_le_bytes());
+        res.extend([0; 24]);
+
+        for (id, data) in chunks {
+            res.extend(*id);
+            res.extend((data.len() as u32).to_le_bytes());
+            res.extend(*data);
+        }
+        res
+    }
+
+    #[test]
+    fn test_board_names() {
+        assert_eq!(board_mapper("NES-NROM-256"), Some(0));
+        assert_eq!(board_mapper("NES-TLROM"), Some(4));
+        assert_eq!(board_mapper("HVC-SNROM"), Some(1));
+        assert_eq!(board_mapper("UNL-NINA-001"), Some(34));
+        assert_eq!(board_mapper("BMC-Super24in1SC03"), Some(176));
+        assert_eq!(board_mapper("UNL-Sachen-8259A"), Some(141));
+        assert_eq!(board_mapper("UNL-KOF97"), Some(263));
+        assert_eq!(board_mapper("BTL-MARIO1-MALEE2"), Some(55));
+        assert_eq!(board_mapper("NES-UNKNOWN"), None);
+        assert_eq!(board_mapper("BMC-UNKNOWN"), None);
+    }
+
+    #[test]
+    fn test_load() {
+        let data = unif(&[
+            (b"MAPR", b"NES-NROM-256\0"),
+            (b"NAME", b"Test Game\0"),
+            (b"PRG1", &[2; 0x4000]),
+            (b"DINF", &[0; 204]),
+            (b"PRG0", &[1; 0x4000]),
+            (b"CHR0", &[3; 0x2000]),
+            (b"MIRR", &[1]),
+            (b"BATR", &[1]),
+            (b"TVCI", &[1]),
+            (b"CTRL", &[1]),
+        ]);
+
+        // loaded through the same entry point as iNES files
+        let cartridge = Cartridge::with_db(data, &GameDb::new()).unwrap();
+        assert_eq!(cartridge.format, HeaderFormat::Unif);
+        assert_eq!(cartridge.mapper, 0);
+        assert_eq!(cartridge.title.as_deref(), Some("Test Game"));
+        assert_eq!(cartridge.prg_rom.len(), 0x8000);
+        assert_eq!((cartridge.prg_rom[0], cartridge.prg_rom[0x4000]), (1, 2));
+        assert!(!cartridge.chr_ram);
+        assert_eq!(cartridge.chr_rom, vec![3; 0x2000]);
+        assert_eq!(cartridge.mirroring, Mirroring::Vertical);
+        assert_eq!(cartridge.region, Region::PAL);
+        assert!(cartridge.battery);
+    }
+
+    #[test]
+    fn test_errors() {
+        let prg: &[u8] = &[0; 0x4000];
+        assert!(load(&unif(&[(b"PRG0", prg)])).is_err());
+        assert!(load(&unif(&[(b"MAPR", b"BMC-Unknown99in1\0"), (b"PRG0", prg)])).is_err());
+        assert!(load(&unif(&[(b"MAPR", b"NES-NROM-128\0")])).is_err());
+
+        let mut truncated = unif(&[(b"MAPR", b"NES-NROM-128\0"), (b"PRG0", prg)]);
+        truncated.truncate(truncated.len() - 1);
+        assert!(load(&truncated).is_err());
+
+        // no CHR chips means 8kB of CHR-RAM
+        let cartridge = load(&unif(&[(b"MAPR", b"NES-UNROM\0"), (b"PRG0", prg)])).unwrap();
+        assert_eq!(cartridge.mapper, 2);
+        assert!(cartridge.chr_ram);
+        assert_eq!(cartridge.chr_rom.len(), 0x2000);
+        assert!(Cartridge::new(b"NOPE".to_vec()).is_err());
+
+        // boards with a mapper the bus doesn't implement are only read for `rustbyte info`
+        let data = unif(&[(b"MAPR", b"BMC-Super24in1SC03\0"), (b"PRG0", prg)]);
+        assert_eq!(Cartridge::parse(data.clone(), &GameDb::new()).unwrap().mapper, 176);
+        assert!(Cartridge::with_db(data, &GameDb::new()).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+// https://www.nesdev.org/wiki/Cycle_reference_chart
+// https://www.nesdev.org/wiki/NES_2.0#Byte_12_(CPU/PPU_Timing)
+
+use crate::state::{Savestate, StateError, StateReader, StateWriter};
+use std::time::Duration;
+
+/// TV system / console region that determines the timing of the CPU and PPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    NTSC,
+    PAL,
+    Dendy,
+}
+
+impl Region {
+    /// Function that parses a region name given by the user
+    pub fn from_name(name: &str) -> Option<Region> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Some(Region::NTSC),
+            "pal" => Some(Region::PAL),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    /// Number of scanlines per frame, including the pre-render scanline
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Region::NTSC => 262,
+            Region::PAL | Region::Dendy => 312,
+        }
+    }
+
+    /// Scanline on which the vblank flag is set and NMI is triggered
+    /// Dendy keeps the PAL frame length but starts vblank 50 lines later,
+    /// so that the vblank period is as long as on NTSC
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Region::NTSC | Region::PAL => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// PPU:CPU clock ratio as a fraction (PPU dots, CPU cycles)
+    pub fn clock_ratio(&self) -> (usize, usize) {
+        match self {
+            Region::NTSC | Region::Dendy => (3, 1),
+            Region::PAL => (16, 5),
+        }
+    }
+
+    /// CPU clock rate in Hz
+    pub fn cpu_clock(&self) -> u32 {
+        match self {
+            Region::NTSC => 1_789_773,
+            Region::PAL => 1_662_607,
+            Region::Dendy => 1_773_448,
+        }
+    }
+
+    /// Number of frames per second
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Region::NTSC => 60.0988,
+            Region::PAL | Region::Dendy => 50.0070,
+        }
+    }
+
+    /// Duration of a single frame
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.frame_rate())
+    }
+
+    /// The 2C07 (PAL) and UA6538 (Dendy) PPUs swap the red and green emphasis bits
+    /// https://www.nesdev.org/wiki/PPU_registers#Color_control
+    pub fn swaps_emphasis(&self) -> bool {
+        match self {
+            Region::NTSC => false,
+            Region::PAL | Region::Dendy => true,
+        }
+    }
+}
+
+impl Savestate for Region {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(match self {
+            Region::NTSC => 0,
+            Region::PAL => 1,
+            Region::Dendy => 2,
+        });
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        *self = match reader.u8()? {
+            0 => Region::NTSC,
+            1 => Region::PAL,
+            2 => Region::Dendy,
+            _ => return Err(StateError::InvalidValue("region")),
+        };
+
+        Ok(())
+    }
+}
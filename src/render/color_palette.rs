@@ -1,4 +1,7 @@
 // https://www.nesdev.org/wiki/PPU_palettes#Palettes
+// https://www.nesdev.org/wiki/PPU_palettes#2C07
+
+use crate::region::Region;
 
 /// The NES color palette.
 /// Kindly borrowed from https://github.com/bugzmanov/nes_ebook/blob/master/code/ch6.3/src/render/palette.rs
@@ -16,4 +19,31 @@ pub static PALETTE: [(u8, u8, u8); 64] = [
     (0xB3, 0xEC, 0xFF), (0xDA, 0xAB, 0xEB), (0xFF, 0xA8, 0xF9), (0xFF, 0xAB, 0xB3), (0xFF, 0xD2, 0xB0),
     (0xFF, 0xEF, 0xA6), (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA),
     (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11),
-];
\ No newline at end of file
+];
+
+/// The 2C07 (PAL) color palette.
+/// The NTSC palette with the chroma of every entry rotated by -15 degrees in YUV, the grays are unchanged
+pub static PALETTE_PAL: [(u8, u8, u8); 64] = [
+    (0x80, 0x80, 0x80), (0x00, 0x4A, 0x89), (0x00, 0x20, 0x9D), (0x33, 0x07, 0xA0), (0x98, 0x00, 0x8C),
+    (0xC6, 0x00, 0x67), (0xBE, 0x00, 0x3D), (0x91, 0x0C, 0x29), (0x63, 0x28, 0x13), (0x18, 0x43, 0x00),
+    (0x0D, 0x49, 0x00), (0x02, 0x4A, 0x18), (0x00, 0x49, 0x4D), (0x00, 0x00, 0x00), (0x05, 0x05, 0x05),
+    (0x05, 0x05, 0x05), (0xC7, 0xC7, 0xC7), (0x00, 0x8B, 0xCC), (0x0B, 0x66, 0xE0), (0x6B, 0x41, 0xFF),
+    (0xDE, 0x2C, 0xE9), (0xFE, 0x1C, 0x94), (0xFF, 0x0F, 0x4D), (0xDF, 0x22, 0x3B), (0xD2, 0x53, 0x29),
+    (0x44, 0x7B, 0x00), (0x14, 0x8E, 0x00), (0x04, 0x90, 0x2B), (0x00, 0xA8, 0x95), (0x21, 0x21, 0x21),
+    (0x09, 0x09, 0x09), (0x09, 0x09, 0x09), (0xFF, 0xFF, 0xFF), (0x06, 0xE9, 0xB9), (0x5C, 0xAE, 0xE4),
+    (0xC6, 0x84, 0xFF), (0xED, 0x45, 0xFF), (0xFD, 0x59, 0xBC), (0xFF, 0x78, 0x62), (0xFF, 0x8A, 0x3E),
+    (0xFF, 0xAB, 0x42), (0xB8, 0xD7, 0x09), (0x3E, 0xF0, 0x03), (0x11, 0xFB, 0x5E), (0x00, 0xFF, 0xAD),
+    (0x5E, 0x5E, 0x5E), (0x0D, 0x0D, 0x0D), (0x0D, 0x0D, 0x0D), (0xFF, 0xFF, 0xFF), (0xA4, 0xFF, 0xE2),
+    (0xB0, 0xF2, 0xEA), (0xD3, 0xAD, 0xF5), (0xF7, 0xA8, 0xFF), (0xFF, 0xA5, 0xCE), (0xFF, 0xCC, 0xC2),
+    (0xFF, 0xE8, 0xB1), (0xFF, 0xEF, 0xA6), (0xE1, 0xE3, 0x96), (0xAC, 0xED, 0x9C), (0xA3, 0xF6, 0xC1),
+    (0x97, 0xFF, 0xDA), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11),
+];
+
+/// Function that returns the color palette of the PPU of a region
+/// The Dendy drives a PAL television, so it shares the PAL colors
+pub fn palette(region: Region) -> &'static [(u8, u8, u8); 64] {
+    match region {
+        Region::NTSC => &PALETTE,
+        Region::PAL | Region::Dendy => &PALETTE_PAL,
+    }
+}
@@ -2,6 +2,8 @@
 /// It serves as an abstraction layer for SLD2
 pub struct Frame {
     pub data: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
 }
 
 impl Default for Frame {
@@ -15,18 +17,53 @@ impl Frame {
     const HEIGHT: usize = 240;
 
     pub fn new() -> Self {
+        Frame::with_size(Frame::WIDTH, Frame::HEIGHT)
+    }
+
+    /// Function that creates a frame of any size, used by the debug viewers
+    pub fn with_size(width: usize, height: usize) -> Self {
         Frame {
-            data: vec![0; Frame::WIDTH * Frame::HEIGHT * 3],
+            data: vec![0; width * height * 3],
+            width,
+            height,
         }
     }
 
     pub fn set_pixel(&mut self, x: usize, y: usize, color: (u8, u8, u8)) {
-        let index = (y * Frame::WIDTH + x) * 3;
+        if x >= self.width || y >= self.height {
+            return;
+        }
 
-        if index + 2 < self.data.len() {
-            self.data[index] = color.0;
-            self.data[index + 1] = color.1;
-            self.data[index + 2] = color.2;
+        let index = (y * self.width + x) * 3;
+        self.data[index] = color.0;
+        self.data[index + 1] = color.1;
+        self.data[index + 2] = color.2;
+    }
+
+    /// Function that fills a rectangle with one color
+    pub fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: (u8, u8, u8)) {
+        for py in y .. y + height {
+            for px in x .. x + width {
+                self.set_pixel(px, py, color);
+            }
         }
     }
-}
\ No newline at end of file
+
+    /// Function that copies another frame into this one with its top left corner at x, y
+    pub fn blit(&mut self, other: &Frame, x: usize, y: usize) {
+        for py in 0 .. other.height {
+            for px in 0 .. other.width {
+                let index = (py * other.width + px) * 3;
+                let color = (other.data[index], other.data[index + 1], other.data[index + 2]);
+                self.set_pixel(x + px, y + py, color);
+            }
+        }
+    }
+
+    /// Function that encodes the frame as a binary PPM (P6) image
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut res = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        res.extend_from_slice(&self.data);
+        res
+    }
+}
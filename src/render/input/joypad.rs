@@ -1,6 +1,7 @@
 use crate::byte_status::ByteStatus;
 use crate::flags::Button;
 use crate::render::input::button_status::ButtonStatus;
+use crate::state::{Savestate, StateError, StateReader, StateWriter};
 
 pub struct Joypad {
     strobe: bool,
@@ -51,6 +52,31 @@ impl Joypad {
     pub fn remove(&mut self, button: Button) {
         self.status.remove(button.as_u8());
     }
+
+    /// Function that returns the pressed buttons as a bit mask (A is bit 0, RIGHT is bit 7)
+    pub fn buttons(&self) -> u8 {
+        self.status.value
+    }
+
+    /// Function that replaces all pressed buttons, used by movie playback
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.status.value = buttons;
+    }
+}
+
+impl Savestate for Joypad {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bool(self.strobe);
+        writer.u8(self.index);
+        writer.u8(self.status.value);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.strobe = reader.bool()?;
+        self.index = reader.u8()?;
+        self.status.value = reader.u8()?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
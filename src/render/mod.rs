@@ -5,4 +5,5 @@ pub mod color_palette;
 pub mod frame;
 pub mod renderer;
 pub mod input;
-mod tile;
\ No newline at end of file
+mod tile;
+pub mod viewer;
\ No newline at end of file
@@ -1,9 +1,12 @@
-use crate::ppu::mirroring::Mirroring;
+use crate::ppu::mask_register::Color;
 use crate::ppu::ppu::PPU;
-use crate::render::color_palette::PALETTE;
+use crate::render::color_palette::palette;
 use crate::render::frame::Frame;
 use crate::render::tile::Slice;
 
+/// Share of the intensity kept by the color channels an emphasis bit darkens
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
 /// Renderer struct responsible for rendering the game state to the screen
 pub struct Renderer { }
 
@@ -13,27 +16,23 @@ impl Renderer {
     }
 
     pub fn render(ppu: &PPU, frame: &mut Frame) {
+        let colors = Self::colors(ppu);
         let offset_x = ppu.scroll_register.scroll_x as usize;
         let offset_y = ppu.scroll_register.scroll_y as usize;
         
-        let (main_name_table, second_name_table) = match (&ppu.mirroring, ppu.controller_register.nametable()) {
-            (Mirroring::Vertical, 0x2000) | (Mirroring::Vertical, 0x2800) | (Mirroring::Horizontal, 0x2000) | (Mirroring::Horizontal, 0x2400) => {
-                (&ppu.ram[0..0x400], &ppu.ram[0x400..0x800])
-            },
-        
-            (Mirroring::Vertical, 0x2400) | (Mirroring::Vertical, 0x2C00) | (Mirroring::Horizontal, 0x2800) | (Mirroring::Horizontal, 0x2C00) => {
-                (&ppu.ram[0x400..0x800], &ppu.ram[0..0x400])
-            },
-        
-            (_,_) => panic!("unsupported mirroring mode"),
-        };
-        
-        Self::render_slice(ppu, frame, main_name_table, Slice::new(offset_x, offset_y, 256, 240), -(offset_x as isize), -(offset_y as isize));
+        // the second name table is the one scrolled into view next to the main one
+        let main_index = (ppu.controller_register.nametable() - 0x2000) / 0x400;
+        let second_index = if offset_x > 0 { main_index ^ 0b01 } else { main_index ^ 0b10 };
+
+        let main_name_table = ppu.nametable(main_index);
+        let second_name_table = ppu.nametable(second_index);
+
+        Self::render_slice(ppu, &colors, frame, main_name_table, Slice::new(offset_x, offset_y, 256, 240), -(offset_x as isize), -(offset_y as isize));
         
         if offset_x > 0 {
-            Self::render_slice(ppu, frame, second_name_table, Slice::new(0, 0, offset_x, 240), (256 - offset_x) as isize, 0);
+            Self::render_slice(ppu, &colors, frame, second_name_table, Slice::new(0, 0, offset_x, 240), (256 - offset_x) as isize, 0);
         } else if offset_y > 0 {
-            Self::render_slice(ppu, frame, second_name_table, Slice::new(0, 0, 256, offset_y), 0, (240 - offset_y) as isize);
+            Self::render_slice(ppu, &colors, frame, second_name_table, Slice::new(0, 0, 256, offset_y), 0, (240 - offset_y) as isize);
         }
         
         for i in (0..ppu.oam.len()).step_by(4).rev() {
@@ -60,9 +59,9 @@ impl Renderer {
                     lower >>= 1;
                     let rgb = match value {
                         0 => continue 'ololo, // skip coloring the pixel
-                        1 => PALETTE[sprite_palette[1] as usize],
-                        2 => PALETTE[sprite_palette[2] as usize],
-                        3 => PALETTE[sprite_palette[3] as usize],
+                        1 => colors[sprite_palette[1] as usize],
+                        2 => colors[sprite_palette[2] as usize],
+                        3 => colors[sprite_palette[3] as usize],
                         _ => panic!("can't be"),
                     };
                     match (flip_horizontal, flip_vertical) {
@@ -76,6 +75,27 @@ impl Renderer {
         }
     }
 
+    /// Function that returns the palette of the PPU region with the PPUMASK color emphasis applied
+    /// Every emphasized color darkens the two other channels
+    fn colors(ppu: &PPU) -> [(u8, u8, u8); 64] {
+        let mut colors = *palette(ppu.region());
+
+        for color in ppu.color_emphasis() {
+            for (r, g, b) in colors.iter_mut() {
+                let others = match color {
+                    Color::Red => [g, b],
+                    Color::Green => [r, b],
+                    Color::Blue => [r, g],
+                };
+                for channel in others {
+                    *channel = (*channel as f32 * EMPHASIS_ATTENUATION) as u8;
+                }
+            }
+        }
+
+        colors
+    }
+
     fn bg_pal(ppu: &PPU, attribute_table: &[u8], tile_column: usize, tile_row : usize) -> [u8; 4] {
         let attr_table_idx = tile_row / 4 * 8 +  tile_column / 4;
         let attr_byte = attribute_table[attr_table_idx];
@@ -102,7 +122,7 @@ impl Renderer {
         ]
     }
     
-    fn render_slice(ppu: &PPU, frame: &mut Frame, name_table: &[u8], slice: Slice, offset_x: isize, offset_y: isize) {
+    fn render_slice(ppu: &PPU, colors: &[(u8, u8, u8); 64], frame: &mut Frame, name_table: &[u8], slice: Slice, offset_x: isize, offset_y: isize) {
         let background = ppu.controller_register.background_pattern_table();
         let attr = &name_table[0x3C0 .. 0x400];
         
@@ -123,10 +143,10 @@ impl Renderer {
                     lower >>= 1;
                     
                     let rgb = match value {
-                        0 => PALETTE[ppu.palette[0] as usize],
-                        1 => PALETTE[palette[1] as usize],
-                        2 => PALETTE[palette[2] as usize],
-                        3 => PALETTE[palette[3] as usize],
+                        0 => colors[ppu.palette[0] as usize],
+                        1 => colors[palette[1] as usize],
+                        2 => colors[palette[2] as usize],
+                        3 => colors[palette[3] as usize],
                         _ => panic!("can't be"),
                     };
                     
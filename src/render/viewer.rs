@@ -0,0 +1,251 @@
+// https://www.nesdev.org/wiki/PPU_pattern_tables
+// https://www.nesdev.org/wiki/PPU_nametables
+// https://www.nesdev.org/wiki/PPU_OAM
+//
+// Debug views of the PPU memory, each one rendered into its own frame
+// The combined view is laid out as
+// +-----------------------------+
+// |      nametables 512x480     |
+// +--------------+------+-------+
+// | pattern 256  | OAM  | pal   |
+// | tables x128  | 128  | 128   |
+// +--------------+------+-------+
+
+use crate::ppu::ppu::PPU;
+use crate::render::color_palette::PALETTE;
+use crate::render::frame::Frame;
+
+pub const VIEW_WIDTH: usize = 512;
+pub const VIEW_HEIGHT: usize = 480 + 128;
+
+/// Color of the scroll viewport outline and the OAM grid
+const OUTLINE: (u8, u8, u8) = (0xFF, 0x00, 0xFF);
+const GRID: (u8, u8, u8) = (0x40, 0x40, 0x40);
+
+/// Function that returns the 4 colors of a palette, 0 - 3 are background and 4 - 7 sprite palettes
+fn palette_colors(ppu: &PPU, palette: u8) -> [(u8, u8, u8); 4] {
+    let start = (palette as usize & 0b111) * 4;
+    [
+        PALETTE[ppu.palette[0] as usize & 0x3F],
+        PALETTE[ppu.palette[start + 1] as usize & 0x3F],
+        PALETTE[ppu.palette[start + 2] as usize & 0x3F],
+        PALETTE[ppu.palette[start + 3] as usize & 0x3F],
+    ]
+}
+
+/// Function that returns the 2-bit color indices of one row of a tile, a missing tile is blank
+fn tile_row(ppu: &PPU, addr: usize, row: usize) -> [u8; 8] {
+    let lower = ppu.chr.get(addr + row).copied().unwrap_or(0);
+    let upper = ppu.chr.get(addr + row + 8).copied().unwrap_or(0);
+
+    let mut res = [0; 8];
+    for (x, pixel) in res.iter_mut().enumerate() {
+        let bit = 7 - x;
+        *pixel = (upper >> bit & 1) << 1 | (lower >> bit & 1);
+    }
+    res
+}
+
+/// Function that draws a tile, color 0 is skipped when transparent is set
+fn draw_tile(frame: &mut Frame, ppu: &PPU, addr: usize, (x, y): (usize, usize), colors: &[(u8, u8, u8); 4], flip: (bool, bool), transparent: bool) {
+    for row in 0 .. 8 {
+        let pixels = tile_row(ppu, addr, row);
+
+        for (col, pixel) in pixels.iter().enumerate() {
+            if *pixel == 0 && transparent {
+                continue;
+            }
+
+            let px = if flip.0 { 7 - col } else { col };
+            let py = if flip.1 { 7 - row } else { row };
+            frame.set_pixel(x + px, y + py, colors[*pixel as usize]);
+        }
+    }
+}
+
+/// Function that renders both pattern tables side by side (256x128) with one of the 8 palettes
+pub fn pattern_tables(ppu: &PPU, palette: u8) -> Frame {
+    let mut frame = Frame::with_size(256, 128);
+    let colors = palette_colors(ppu, palette);
+
+    for table in 0 .. 2 {
+        for tile in 0 .. 256 {
+            let addr = table * 0x1000 + tile * 16;
+            draw_tile(&mut frame, ppu, addr, (table * 128 + tile % 16 * 8, tile / 16 * 8), &colors, (false, false), false);
+        }
+    }
+
+    frame
+}
+
+/// Function that renders the four nametables (512x480) with the visible area outlined
+pub fn nametables(ppu: &PPU) -> Frame {
+    let mut frame = Frame::with_size(512, 480);
+    let background = ppu.controller_register.background_pattern_table() as usize;
+
+    for table in 0 .. 4 {
+        let name_table = ppu.nametable(table);
+        let (base_x, base_y) = ((table as usize % 2) * 256, (table as usize / 2) * 240);
+
+        for i in 0 .. 0x3C0 {
+            let (col, row) = (i % 32, i / 32);
+
+            // each attribute byte covers 4x4 tiles, 2 bits for every 2x2 quadrant
+            let attr = name_table[0x3C0 + row / 4 * 8 + col / 4];
+            let shift = (row % 4 / 2) * 4 + (col % 4 / 2) * 2;
+            let colors = palette_colors(ppu, attr >> shift & 0b11);
+
+            let addr = background + name_table[i] as usize * 16;
+            draw_tile(&mut frame, ppu, addr, (base_x + col * 8, base_y + row * 8), &colors, (false, false), false);
+        }
+    }
+
+    // the viewport wraps around the edges of the four tables
+    let table = (ppu.controller_register.nametable() - 0x2000) / 0x400;
+    let x = (table as usize % 2) * 256 + ppu.scroll_register.scroll_x as usize;
+    let y = (table as usize / 2) * 240 + ppu.scroll_register.scroll_y as usize;
+
+    for i in 0 .. 256 {
+        frame.set_pixel((x + i) % 512, y % 480, OUTLINE);
+        frame.set_pixel((x + i) % 512, (y + 239) % 480, OUTLINE);
+    }
+    for i in 0 .. 240 {
+        frame.set_pixel(x % 512, (y + i) % 480, OUTLINE);
+        frame.set_pixel((x + 255) % 512, (y + i) % 480, OUTLINE);
+    }
+
+    frame
+}
+
+/// Function that renders the 64 sprites as an 8x8 grid of 16x16 cells (128x128), in OAM order
+pub fn oam(ppu: &PPU) -> Frame {
+    let mut frame = Frame::with_size(128, 128);
+    let backdrop = PALETTE[ppu.palette[0] as usize & 0x3F];
+    let height = ppu.controller_register.sprite_size() as usize;
+
+    for sprite in 0 .. 64 {
+        let (x, y) = (sprite % 8 * 16, sprite / 8 * 16);
+        frame.fill(x, y, 16, 16, GRID);
+        frame.fill(x + 1, y + 1, 14, 14, backdrop);
+
+        let [_, tile, attr, _] = sprite_bytes(ppu, sprite);
+        let colors = palette_colors(ppu, 4 + (attr & 0b11));
+        let flip = (attr >> 6 & 1 == 1, attr >> 7 & 1 == 1);
+        let top = y + 8 - height / 2;
+
+        if height == 8 {
+            let addr = ppu.controller_register.sprite_pattern_table() as usize + tile as usize * 16;
+            draw_tile(&mut frame, ppu, addr, (x + 4, top), &colors, flip, true);
+        } else {
+            // 8x16 sprites take the bank from bit 0 and swap the halves when flipped vertically
+            let addr = (tile as usize & 1) * 0x1000 + (tile as usize & 0xFE) * 16;
+            let (first, second) = if flip.1 { (addr + 16, addr) } else { (addr, addr + 16) };
+            draw_tile(&mut frame, ppu, first, (x + 4, top), &colors, flip, true);
+            draw_tile(&mut frame, ppu, second, (x + 4, top + 8), &colors, flip, true);
+        }
+    }
+
+    frame
+}
+
+fn sprite_bytes(ppu: &PPU, sprite: usize) -> [u8; 4] {
+    [ppu.oam[sprite * 4], ppu.oam[sprite * 4 + 1], ppu.oam[sprite * 4 + 2], ppu.oam[sprite * 4 + 3]]
+}
+
+/// Function that lists the attributes of the 64 sprites, one per line
+pub fn oam_table(ppu: &PPU) -> String {
+    let mut res = String::new();
+
+    for sprite in 0 .. 64 {
+        let [y, tile, attr, x] = sprite_bytes(ppu, sprite);
+        res.push_str(&format!(
+            "{:02}  X:{:3} Y:{:3} tile:${:02X} pal:{} {} {}{}\n",
+            sprite, x, y, tile, attr & 0b11,
+            if attr & 0x20 != 0 { "back " } else { "front" },
+            if attr & 0x40 != 0 { "H" } else { "-" },
+            if attr & 0x80 != 0 { "V" } else { "-" },
+        ));
+    }
+
+    res
+}
+
+/// Function that renders the 32 palette entries (128x128), one palette of 4 colors per row
+pub fn palettes(ppu: &PPU) -> Frame {
+    let mut frame = Frame::with_size(128, 128);
+
+    for (i, color) in ppu.palette.iter().enumerate() {
+        frame.fill(i % 4 * 32, i / 4 * 16, 32, 16, PALETTE[*color as usize & 0x3F]);
+    }
+
+    frame
+}
+
+/// Function that combines all of the views into one frame of VIEW_WIDTH x VIEW_HEIGHT
+pub fn debug_view(ppu: &PPU, palette: u8) -> Frame {
+    let mut frame = Frame::with_size(VIEW_WIDTH, VIEW_HEIGHT);
+
+    frame.blit(&nametables(ppu), 0, 0);
+    frame.blit(&pattern_tables(ppu, palette), 0, 480);
+    frame.blit(&oam(ppu), 256, 480);
+    frame.blit(&palettes(ppu), 384, 480);
+
+    frame
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ppu::mirroring::Mirroring;
+
+    fn pixel(frame: &Frame, x: usize, y: usize) -> (u8, u8, u8) {
+        let index = (y * frame.width + x) * 3;
+        (frame.data[index], frame.data[index + 1], frame.data[index + 2])
+    }
+
+    #[test]
+    fn test_pattern_tables() {
+        let mut chr = vec![0; 0x2000];
+        // tile 1 of the second table: top row color 1, second row color 3
+        chr[0x1010] = 0xFF;
+        chr[0x1011] = 0xFF;
+        chr[0x1019] = 0xFF;
+
+        let mut ppu = PPU::new(chr, Mirroring::Horizontal);
+        ppu.palette[0] = 0x0F;
+        ppu.palette[1 + 4 * 2] = 0x30;
+        ppu.palette[3 + 4 * 2] = 0x16;
+
+        let frame = pattern_tables(&ppu, 2);
+        assert_eq!(pixel(&frame, 136, 0), PALETTE[0x30]);
+        assert_eq!(pixel(&frame, 143, 1), PALETTE[0x16]);
+        assert_eq!(pixel(&frame, 0, 0), PALETTE[0x0F]);
+    }
+
+    #[test]
+    fn test_nametables_viewport() {
+        let mut ppu = PPU::new(vec![0; 0x2000], Mirroring::Vertical);
+        ppu.scroll_register.scroll_x = 16;
+        ppu.scroll_register.scroll_y = 8;
+
+        let frame = nametables(&ppu);
+        assert_eq!(pixel(&frame, 16, 8), OUTLINE);
+        assert_eq!(pixel(&frame, 271, 100), OUTLINE);
+        assert_eq!(pixel(&frame, 100, 247), OUTLINE);
+        assert_ne!(pixel(&frame, 100, 100), OUTLINE);
+
+        let view = debug_view(&ppu, 0);
+        assert_eq!((view.width, view.height), (VIEW_WIDTH, VIEW_HEIGHT));
+        assert!(view.to_ppm().starts_with(b"P6\n512 608\n255\n"));
+    }
+
+    #[test]
+    fn test_oam_table() {
+        let mut ppu = PPU::new(vec![0; 0x2000], Mirroring::Horizontal);
+        ppu.oam[4 .. 8].copy_from_slice(&[0x20, 0xA2, 0b1010_0001, 0x78]);
+
+        let table = oam_table(&ppu);
+        assert_eq!(table.lines().nth(1), Some("01  X:120 Y: 32 tile:$A2 pal:1 back  -V"));
+        assert_eq!(table.lines().count(), 64);
+    }
+}
@@ -0,0 +1,258 @@
+// Rewind buffer
+// Keeps the newest save state in full and every older one as a delta against
+// its successor. Each delta is the XOR of the two states, which is mostly zeros,
+// compressed with a simple run-length encoding:
+//
+// [u32]        => length of the older state
+// repeated:
+// [varint]     => number of zero bytes
+// [varint]     => number of literal bytes
+// [u8; n]      => literal bytes
+
+use crate::cpu::cpu::CPU;
+use crate::region::Region;
+use std::collections::VecDeque;
+
+/// Class representing a ring buffer of save states used to run the game backwards
+#[derive(Debug)]
+pub struct Rewind {
+    /// Newest snapshot, stored in full
+    current: Option<Vec<u8>>,
+
+    /// Compressed deltas, the last one turns `current` into the snapshot before it
+    deltas: VecDeque<Vec<u8>>,
+
+    /// Maximum number of snapshots kept
+    capacity: usize,
+
+    /// Number of frames between two snapshots
+    interval: usize,
+
+    /// Frames since the last snapshot
+    frames: usize,
+}
+
+impl Rewind {
+    /// Create a new rewind buffer holding up to capacity snapshots taken every interval frames
+    pub fn new(capacity: usize, interval: usize) -> Self {
+        Rewind {
+            current: None,
+            deltas: VecDeque::new(),
+            capacity: capacity.max(1),
+            interval: interval.max(1),
+            frames: 0,
+        }
+    }
+
+    /// Create a new rewind buffer covering the given number of seconds
+    pub fn with_duration(seconds: f64, region: Region, interval: usize) -> Self {
+        let frames = (seconds * region.frame_rate()).ceil() as usize;
+        Rewind::new(frames / interval.max(1), interval)
+    }
+
+    /// Function that is called once per frame and takes a snapshot every interval frames
+    pub fn record(&mut self, cpu: &CPU) {
+        if self.frames == 0 {
+            self.push(cpu.save_state());
+        }
+
+        self.frames = (self.frames + 1) % self.interval;
+    }
+
+    /// Function that adds a new snapshot to the buffer
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.current.take() {
+            self.deltas.push_back(Rewind::encode(&state, &previous));
+
+            // the oldest snapshot is dropped first
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+
+        self.current = Some(state);
+    }
+
+    /// Function that removes and returns the newest snapshot
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.current.take()?;
+
+        self.current = self
+            .deltas
+            .pop_back()
+            .map(|delta| Rewind::decode(&state, &delta));
+
+        // start a new interval after rewinding
+        self.frames = 0;
+
+        Some(state)
+    }
+
+    /// Function that removes all snapshots
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+        self.frames = 0;
+    }
+
+    /// Number of snapshots in the buffer
+    pub fn len(&self) -> usize {
+        match self.current {
+            Some(_) => self.deltas.len() + 1,
+            None => 0,
+        }
+    }
+
+    /// Checks if there is nothing to rewind to
+    pub fn is_empty(&self) -> bool {
+        self.current.is_none()
+    }
+
+    /// Number of bytes used by the snapshots
+    pub fn memory_usage(&self) -> usize {
+        self.current.as_ref().map_or(0, |state| state.len())
+            + self.deltas.iter().map(|delta| delta.len()).sum::<usize>()
+    }
+
+    /// Function that creates a delta which turns newer into older
+    fn encode(newer: &[u8], older: &[u8]) -> Vec<u8> {
+        let mut res = Vec::new();
+        res.extend_from_slice(&(older.len() as u32).to_le_bytes());
+
+        let xor = |i: usize| older[i] ^ newer.get(i).copied().unwrap_or(0);
+
+        let mut i = 0;
+        while i < older.len() {
+            // run of unchanged bytes
+            let start = i;
+            while i < older.len() && xor(i) == 0 {
+                i += 1;
+            }
+            Rewind::write_varint(&mut res, i - start);
+
+            // run of changed bytes
+            let start = i;
+            while i < older.len() && xor(i) != 0 {
+                i += 1;
+            }
+            Rewind::write_varint(&mut res, i - start);
+            res.extend((start .. i).map(xor));
+        }
+
+        res
+    }
+
+    /// Function that applies a delta to newer, returning the older snapshot
+    fn decode(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+        let len = u32::from_le_bytes([delta[0], delta[1], delta[2], delta[3]]) as usize;
+        let mut older: Vec<u8> = (0 .. len).map(|i| newer.get(i).copied().unwrap_or(0)).collect();
+
+        let mut pos = 4;
+        let mut i = 0;
+        while pos < delta.len() {
+            i += Rewind::read_varint(delta, &mut pos);
+
+            let literals = Rewind::read_varint(delta, &mut pos);
+            for byte in &delta[pos .. pos + literals] {
+                older[i] ^= byte;
+                i += 1;
+            }
+            pos += literals;
+        }
+
+        older
+    }
+
+    /// Function that writes a LEB128 encoded number
+    fn write_varint(out: &mut Vec<u8>, mut val: usize) {
+        while val >= 0x80 {
+            out.push((val as u8 & 0x7F) | 0x80);
+            val >>= 7;
+        }
+        out.push(val as u8);
+    }
+
+    /// Function that reads a LEB128 encoded number
+    fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+        let mut res = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = data[*pos];
+            *pos += 1;
+            res |= ((byte & 0x7F) as usize) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return res;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rewind_order() {
+        let mut rewind = Rewind::new(10, 1);
+        let states: Vec<Vec<u8>> = (0 .. 5u8)
+            .map(|i| {
+                let mut state = vec![0; 300];
+                state[i as usize * 50] = i + 1;
+                state[299] = i;
+                state
+            })
+            .collect();
+
+        for state in &states {
+            rewind.push(state.clone());
+        }
+
+        assert_eq!(rewind.len(), 5);
+        for state in states.iter().rev() {
+            assert_eq!(rewind.pop().as_ref(), Some(state));
+        }
+        assert!(rewind.pop().is_none());
+    }
+
+    #[test]
+    fn test_rewind_capacity() {
+        let mut rewind = Rewind::new(3, 1);
+        for i in 0 .. 10u8 {
+            rewind.push(vec![i; 1000]);
+        }
+
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.pop(), Some(vec![9; 1000]));
+        assert_eq!(rewind.pop(), Some(vec![8; 1000]));
+        assert_eq!(rewind.pop(), Some(vec![7; 1000]));
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn test_rewind_different_lengths() {
+        let mut rewind = Rewind::new(10, 1);
+        rewind.push(vec![1, 2, 3]);
+        rewind.push(vec![1, 2, 3, 4, 5]);
+        rewind.push(vec![7]);
+
+        assert_eq!(rewind.pop(), Some(vec![7]));
+        assert_eq!(rewind.pop(), Some(vec![1, 2, 3, 4, 5]));
+        assert_eq!(rewind.pop(), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_rewind_deltas_are_small() {
+        let mut rewind = Rewind::new(100, 1);
+        for i in 0 .. 100u32 {
+            let mut state = vec![0xAA; 8192];
+            state[100 .. 104].copy_from_slice(&i.to_le_bytes());
+            rewind.push(state);
+        }
+
+        // one full state and 99 tiny deltas
+        assert!(rewind.memory_usage() < 8192 + 99 * 16);
+    }
+}
@@ -0,0 +1,186 @@
+// https://www.nesdev.org/wiki/INES
+// https://www.nesdev.org/wiki/NES_2.0
+//
+// Everything known about a ROM file, printed by "rustbyte info" as text or JSON
+
+use crate::gamedb::GameDb;
+use crate::hash::{crc32, sha1, to_hex};
+use crate::ppu::cartridge::{Cartridge, HeaderFormat};
+
+/// Class representing the parsed cartridge with the hashes and the problems of its header
+#[derive(Debug)]
+pub struct RomInfo {
+    pub cartridge: Cartridge,
+
+    /// CRC-32 and SHA-1 of the PRG-ROM followed by the CHR-ROM, the game database key
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+
+    /// Problems with the header that don't stop the game from loading
+    pub anomalies: Vec<String>,
+}
+
+impl RomInfo {
+    /// Function that loads the cartridge and inspects the header of the file
+    pub fn new(data: &[u8], db: &GameDb) -> Result<RomInfo, String> {
+        let cartridge = Cartridge::parse(data.to_vec(), db).map_err(|err| err.to_string())?;
+
+        let chr_rom: &[u8] = if cartridge.chr_ram { &[] } else { &cartridge.chr_rom };
+        let rom = [&cartridge.prg_rom[..], chr_rom].concat();
+
+        let mut anomalies = vec![];
+        if cartridge.format == HeaderFormat::Archaic {
+            let garbage: String = data[7 .. 16].iter().map(|byte| if byte.is_ascii_graphic() { *byte as char } else { '.' }).collect();
+            anomalies.push(format!("bytes 7 - 15 hold garbage \"{}\", read as an archaic iNES header", garbage));
+        } else if cartridge.format == HeaderFormat::INes && data[10 .. 16].iter().any(|byte| *byte != 0) {
+            anomalies.push("unused bytes 10 - 15 are not zero".to_string());
+        }
+
+        // NES 2.0 can declare miscellaneous ROMs after the CHR-ROM in byte 14, UNIF chunks have their own lengths
+        let len = 16 + cartridge.trainer.as_ref().map_or(0, |trainer| trainer.len()) + rom.len();
+        let misc_roms = cartridge.format == HeaderFormat::Nes2 && data[14] & 3 != 0;
+        if data.len() > len && !misc_roms && cartridge.format != HeaderFormat::Unif {
+            anomalies.push(format!("{} bytes of extra data after the ROM", data.len() - len));
+        }
+
+        if cartridge.prg_rom.is_empty() {
+            anomalies.push("no PRG-ROM".to_string());
+        }
+        for correction in &cartridge.corrections {
+            anomalies.push(format!("{} corrected", correction));
+        }
+
+        Ok(RomInfo { crc32: crc32(&rom), sha1: sha1(&rom), cartridge, anomalies })
+    }
+
+    fn mapper(&self) -> String {
+        let name = self.cartridge.mapper_name().unwrap_or("unknown");
+        match self.cartridge.submapper {
+            0 => format!("{} ({})", self.cartridge.mapper, name),
+            submapper => format!("{}.{} ({})", self.cartridge.mapper, submapper, name),
+        }
+    }
+
+    /// Function that formats the information as "name: value" lines
+    pub fn to_text(&self) -> String {
+        let cartridge = &self.cartridge;
+        let chr_kind = if cartridge.chr_ram { "RAM" } else { "ROM" };
+
+        let mut lines = vec![
+            format!("Title:     {}", cartridge.title.as_deref().unwrap_or("not in the game database")),
+            format!("Header:    {}", cartridge.format),
+            format!("Mapper:    {}", self.mapper()),
+            format!("PRG-ROM:   {} kB", cartridge.prg_rom.len() / 1024),
+            format!("CHR-{}:   {} kB", chr_kind, cartridge.chr_rom.len() / 1024),
+            format!("PRG-RAM:   {} kB{}", cartridge.prg_ram_size / 1024, if cartridge.battery { ", battery backed" } else { "" }),
+            format!("Mirroring: {:?}", cartridge.mirroring),
+            format!("Trainer:   {}", if cartridge.trainer.is_some() { "yes, loaded at $7000" } else { "no" }),
+            format!("Region:    {:?}", cartridge.region),
+            format!("CRC-32:    {:08X}", self.crc32),
+            format!("SHA-1:     {}", to_hex(&self.sha1)),
+        ];
+
+        if self.anomalies.is_empty() {
+            lines.push("Anomalies: none".to_string());
+        } else {
+            lines.push("Anomalies:".to_string());
+            lines.extend(self.anomalies.iter().map(|anomaly| format!("  {}", anomaly)));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Function that formats the information as a JSON object
+    pub fn to_json(&self) -> String {
+        let cartridge = &self.cartridge;
+        let strings = |values: &[String]| format!("[{}]", values.iter().map(|value| json_string(value)).collect::<Vec<_>>().join(", "));
+        let corrections: Vec<String> = cartridge.corrections.iter().map(|correction| correction.to_string()).collect();
+
+        let fields = [
+            ("title", cartridge.title.as_deref().map_or("null".to_string(), json_string)),
+            ("format", json_string(&cartridge.format.to_string())),
+            ("mapper", cartridge.mapper.to_string()),
+            ("submapper", cartridge.submapper.to_string()),
+            ("mapper_name", cartridge.mapper_name().map_or("null".to_string(), json_string)),
+            ("prg_rom", cartridge.prg_rom.len().to_string()),
+            ("chr_rom", if cartridge.chr_ram { 0 } else { cartridge.chr_rom.len() }.to_string()),
+            ("chr_ram", if cartridge.chr_ram { cartridge.chr_rom.len() } else { 0 }.to_string()),
+            ("prg_ram", cartridge.prg_ram_size.to_string()),
+            ("battery", cartridge.battery.to_string()),
+            ("mirroring", json_string(&format!("{:?}", cartridge.mirroring).to_lowercase())),
+            ("trainer", cartridge.trainer.is_some().to_string()),
+            ("region", json_string(&format!("{:?}", cartridge.region).to_lowercase())),
+            ("crc32", json_string(&format!("{:08X}", self.crc32))),
+            ("sha1", json_string(&to_hex(&self.sha1))),
+            ("corrections", strings(&corrections)),
+            ("anomalies", strings(&self.anomalies)),
+        ];
+
+        let body: Vec<String> = fields.iter().map(|(name, value)| format!("  \"{}\": {}", name, value)).collect();
+        format!("{{\n{}\n}}\n", body.join(",\n"))
+    }
+}
+
+/// Function that quotes a string for JSON
+fn json_string(value: &str) -> String {
+    let mut res = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rom(header: [u8; 16]) -> Vec<u8> {
+        let mut rom = header.to_vec();
+        rom.extend(vec![0xEA; 0x4000 * header[4] as usize + 0x2000 * header[5] as usize]);
+        rom
+    }
+
+    #[test]
+    fn test_text() {
+        let data = rom([0x4E, 0x45, 0x53, 0x1A, 2, 1, 0x13, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let info = RomInfo::new(&data, &GameDb::new()).unwrap();
+        let text = info.to_text();
+
+        assert!(text.contains("Header:    iNES\n"));
+        assert!(text.contains("Mapper:    1 (MMC1)\n"));
+        assert!(text.contains("PRG-ROM:   32 kB\n"));
+        assert!(text.contains("CHR-ROM:   8 kB\n"));
+        assert!(text.contains("PRG-RAM:   8 kB, battery backed\n"));
+        assert!(text.contains(&format!("CRC-32:    {:08X}\n", crc32(&data[16 ..]))));
+        assert!(text.ends_with("Anomalies: none\n"));
+    }
+
+    #[test]
+    fn test_anomalies_and_json() {
+        let mut data = rom([0x4E, 0x45, 0x53, 0x1A, 1, 0, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data[7 .. 16].copy_from_slice(b"DiskDude!");
+        data.extend([0; 128]);
+
+        let info = RomInfo::new(&data, &GameDb::new()).unwrap();
+        assert_eq!(info.cartridge.format, HeaderFormat::Archaic);
+        assert_eq!(info.cartridge.mapper, 4);
+        assert_eq!(info.anomalies, vec![
+            "bytes 7 - 15 hold garbage \"DiskDude!\", read as an archaic iNES header",
+            "128 bytes of extra data after the ROM",
+            "mapper: 68 -> 4 corrected",
+        ]);
+
+        let json = info.to_json();
+        assert!(json.starts_with("{\n  \"title\": null,\n  \"format\": \"archaic iNES\",\n  \"mapper\": 4,\n"));
+        assert!(json.contains("\"chr_rom\": 0,\n  \"chr_ram\": 8192,\n"));
+        assert!(json.contains("garbage \\\"DiskDude!\\\", read"));
+        assert!(json.ends_with("\"mapper: 68 -> 4 corrected\"]\n}\n"));
+    }
+}
@@ -0,0 +1,284 @@
+// https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+// https://www.rfc-editor.org/rfc/rfc1952
+//
+// Where the bytes of a ROM come from, a plain file or a file inside an archive
+// [zip] => the central directory at the end lists the entries, each stored or DEFLATE compressed
+// [gzip] => 10 byte header, optional name/comment fields, DEFLATE data, CRC-32 and size
+
+use crate::hash::crc32;
+use crate::inflate::inflate;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the files picked from an archive, in order of preference
+const ROM_EXTENSIONS: [&str; 4] = ["nes", "unf", "unif", "fds"];
+
+/// Source the ROM is loaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomSource {
+    File(PathBuf),
+
+    /// Entry of a zip archive, None picks the first ROM in it
+    Zip { path: PathBuf, entry: Option<String> },
+
+    /// Gzip compressed ROM (game.nes.gz)
+    Gzip(PathBuf),
+}
+
+impl RomSource {
+    /// Function that picks the source by the extension of the file
+    pub fn new(path: &str, entry: Option<&str>) -> RomSource {
+        let extension = Path::new(path).extension().map(|ext| ext.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("zip") => RomSource::Zip { path: PathBuf::from(path), entry: entry.map(|entry| entry.to_string()) },
+            Some("gz") => RomSource::Gzip(PathBuf::from(path)),
+            _ => RomSource::File(PathBuf::from(path)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            RomSource::File(path) | RomSource::Zip { path, .. } | RomSource::Gzip(path) => path,
+        }
+    }
+
+    /// Function that reads the ROM, decompressing it if needed
+    pub fn read(&self) -> Result<Vec<u8>, String> {
+        let path = self.path();
+        let data = std::fs::read(path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+        match self {
+            RomSource::File(_) => Ok(data),
+            RomSource::Gzip(_) => gunzip(&data).map_err(|err| format!("Failed to decompress {}: {}", path.display(), err)),
+            RomSource::Zip { entry, .. } => {
+                let entries = zip_entries(&data).map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+
+                let found = match entry {
+                    Some(name) => entries.iter().find(|candidate| candidate.name == *name),
+                    None => ROM_EXTENSIONS.iter().find_map(|ext| entries.iter().find(|candidate| candidate.is_rom(ext))),
+                };
+
+                let Some(found) = found else {
+                    let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+                    let wanted = entry.as_deref().unwrap_or("ROM");
+                    return Err(format!("{} has no {}, it contains: {}", path.display(), wanted, names.join(", ")));
+                };
+
+                found.extract(&data).map_err(|err| format!("Failed to extract {}: {}", found.name, err))
+            },
+        }
+    }
+}
+
+/// Class representing a file listed in the central directory of a zip archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    pub name: String,
+    pub size: usize,
+    method: u16,
+    flags: u16,
+    crc: u32,
+    compressed_size: usize,
+    offset: usize,
+}
+
+fn u16_at(data: &[u8], at: usize) -> Result<u16, &'static str> {
+    let bytes = data.get(at .. at + 2).ok_or("archive is truncated")?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32, &'static str> {
+    let bytes = data.get(at .. at + 4).ok_or("archive is truncated")?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+impl ZipEntry {
+    fn is_rom(&self, extension: &str) -> bool {
+        Path::new(&self.name).extension().is_some_and(|ext| ext.to_string_lossy().eq_ignore_ascii_case(extension))
+    }
+
+    /// Function that returns the decompressed content of the entry
+    pub fn extract(&self, data: &[u8]) -> Result<Vec<u8>, &'static str> {
+        if self.flags & 1 != 0 {
+            return Err("encrypted entries are not supported");
+        }
+        if u32_at(data, self.offset)? != 0x0403_4B50 {
+            return Err("invalid local file header");
+        }
+
+        // the local header repeats the name, its extra field can differ from the central one
+        let start = self.offset + 30 + u16_at(data, self.offset + 26)? as usize + u16_at(data, self.offset + 28)? as usize;
+        let compressed = data.get(start .. start + self.compressed_size).ok_or("archive is truncated")?;
+
+        let res = match self.method {
+            0 => compressed.to_vec(),
+            8 => inflate(compressed)?,
+            _ => return Err("compression method is not supported, only stored and DEFLATE"),
+        };
+
+        if res.len() != self.size || crc32(&res) != self.crc {
+            return Err("CRC-32 of the extracted file doesn't match");
+        }
+        Ok(res)
+    }
+}
+
+/// Function that lists the files of a zip archive
+pub fn zip_entries(data: &[u8]) -> Result<Vec<ZipEntry>, &'static str> {
+    // the end of central directory record is followed by a comment of up to 64kB
+    let end = (0 .. data.len().saturating_sub(21))
+        .rev()
+        .take(0x10000)
+        .find(|at| data[*at .. *at + 4] == [0x50, 0x4B, 0x05, 0x06])
+        .ok_or("not a zip archive")?;
+
+    let count = u16_at(data, end + 10)? as usize;
+    let mut at = u32_at(data, end + 16)? as usize;
+    let mut res = vec![];
+
+    for _ in 0 .. count {
+        if u32_at(data, at)? != 0x0201_4B50 {
+            return Err("invalid central directory");
+        }
+
+        let name_len = u16_at(data, at + 28)? as usize;
+        let name = data.get(at + 46 .. at + 46 + name_len).ok_or("archive is truncated")?;
+
+        res.push(ZipEntry {
+            name: String::from_utf8_lossy(name).to_string(),
+            size: u32_at(data, at + 24)? as usize,
+            method: u16_at(data, at + 10)?,
+            flags: u16_at(data, at + 8)?,
+            crc: u32_at(data, at + 16)?,
+            compressed_size: u32_at(data, at + 20)? as usize,
+            offset: u32_at(data, at + 42)? as usize,
+        });
+
+        at += 46 + name_len + u16_at(data, at + 30)? as usize + u16_at(data, at + 32)? as usize;
+    }
+
+    Ok(res)
+}
+
+/// Function that decompresses a gzip file
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if data.len() < 18 || data[0 .. 3] != [0x1F, 0x8B, 0x08] {
+        return Err("not a gzip file");
+    }
+
+    let flags = data[3];
+    let mut at = 10;
+
+    // optional extra field, file name, comment and header CRC
+    if flags & 0x04 != 0 {
+        at += 2 + u16_at(data, at)? as usize;
+    }
+    for flag in [0x08, 0x10] {
+        if flags & flag != 0 {
+            at += data.get(at ..).and_then(|rest| rest.iter().position(|byte| *byte == 0)).ok_or("gzip header is truncated")? + 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        at += 2;
+    }
+
+    let trailer = data.len() - 8;
+    let res = inflate(data.get(at .. trailer).ok_or("gzip header is truncated")?)?;
+
+    if crc32(&res) != u32_at(data, trailer)? || res.len() as u32 != u32_at(data, trailer + 4)? {
+        return Err("CRC-32 of the decompressed file doesn't match");
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// "stored" compressed by zlib as a stored DEFLATE block
+    const DEFLATED: [u8; 11] = [0x01, 0x06, 0x00, 0xF9, 0xFF, 0x73, 0x74, 0x6F, 0x72, 0x65, 0x64];
+
+    /// Function that builds a zip archive from (name, method, content, uncompressed content)
+    fn zip(files: &[(&str, u16, &[u8], &[u8])]) -> Vec<u8> {
+        let mut res = vec![];
+        let mut central = vec![];
+
+        for (name, method, content, original) in files {
+            let mut common = vec![];
+            common.extend(20u16.to_le_bytes());
+            common.extend(0u16.to_le_bytes());
+            common.extend(method.to_le_bytes());
+            common.extend([0; 4]);
+            common.extend(crc32(original).to_le_bytes());
+            common.extend((content.len() as u32).to_le_bytes());
+            common.extend((original.len() as u32).to_le_bytes());
+            common.extend((name.len() as u16).to_le_bytes());
+            common.extend(0u16.to_le_bytes());
+
+            central.extend([0x50, 0x4B, 0x01, 0x02, 20, 0]);
+            central.extend(&common);
+            central.extend([0; 10]);
+            central.extend((res.len() as u32).to_le_bytes());
+            central.extend(name.as_bytes());
+
+            res.extend([0x50, 0x4B, 0x03, 0x04]);
+            res.extend(&common);
+            res.extend(name.as_bytes());
+            res.extend(*content);
+        }
+
+        let offset = res.len() as u32;
+        res.extend(&central);
+        res.extend([0x50, 0x4B, 0x05, 0x06, 0, 0, 0, 0]);
+        res.extend((files.len() as u16).to_le_bytes());
+        res.extend((files.len() as u16).to_le_bytes());
+        res.extend((central.len() as u32).to_le_bytes());
+        res.extend(offset.to_le_bytes());
+        res.extend([0, 0]);
+        res
+    }
+
+    #[test]
+    fn test_zip() {
+        let data = zip(&[
+            ("readme.txt", 0, b"hello", b"hello"),
+            ("Game.NES", 8, &DEFLATED, b"stored"),
+            ("other.nes", 0, b"second", b"second"),
+        ]);
+
+        let entries = zip_entries(&data).unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["readme.txt", "Game.NES", "other.nes"]);
+        assert_eq!(entries[1].extract(&data), Ok(b"stored".to_vec()));
+        assert_eq!(entries[2].extract(&data), Ok(b"second".to_vec()));
+
+        let path = std::env::temp_dir().join("rustbyte_rom_source_test.zip");
+        std::fs::write(&path, &data).unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        assert_eq!(RomSource::new(&path, None).read(), Ok(b"stored".to_vec()));
+        assert_eq!(RomSource::new(&path, Some("other.nes")).read(), Ok(b"second".to_vec()));
+        assert!(RomSource::new(&path, Some("missing.nes")).read().unwrap_err().ends_with("readme.txt, Game.NES, other.nes"));
+        std::fs::remove_file(&path).unwrap();
+
+        let mut damaged = data.clone();
+        damaged[30 + "readme.txt".len()] ^= 1;
+        assert!(entries[0].extract(&damaged).is_err());
+    }
+
+    #[test]
+    fn test_gzip() {
+        let mut data = vec![0x1F, 0x8B, 0x08, 0x08, 0, 0, 0, 0, 0, 0xFF];
+        data.extend(b"game.nes\0");
+        data.extend(DEFLATED);
+        data.extend(crc32(b"stored").to_le_bytes());
+        data.extend(6u32.to_le_bytes());
+        assert_eq!(gunzip(&data), Ok(b"stored".to_vec()));
+
+        let len = data.len();
+        data[len - 5] ^= 1;
+        assert!(gunzip(&data).is_err());
+        assert!(gunzip(b"plain file, not compressed").is_err());
+        assert_eq!(RomSource::new("game.nes.gz", None), RomSource::Gzip(PathBuf::from("game.nes.gz")));
+    }
+}
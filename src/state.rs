@@ -0,0 +1,211 @@
+// Save states
+// The whole machine is serialized into a versioned little-endian binary format:
+//
+// [0x00 - 0x03] => magic "RBST"
+// [0x04 - 0x05] => format version
+// [0x06 - 0x09] => CRC-32 of the PRG and CHR ROM the state was made with
+// [0x0A - ....] => CPU, Bus, PPU and Joypad state in that order
+
+use std::fmt::{Display, Formatter};
+
+/// Magic bytes at the start of every save state
+pub const MAGIC: [u8; 4] = *b"RBST";
+
+/// Version of the save state format, bumped on every incompatible change
+pub const VERSION: u16 = 2;
+
+/// Errors that can happen while loading a save state
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start with the magic bytes
+    InvalidMagic,
+
+    /// The state was made by an incompatible version of the emulator
+    UnsupportedVersion(u16),
+
+    /// The state was made with a different ROM (expected, found)
+    RomMismatch(u32, u32),
+
+    /// The state is truncated
+    UnexpectedEnd,
+
+    /// A value in the state is out of range
+    InvalidValue(&'static str),
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::InvalidMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "save state version {} is not supported (expected {})", version, VERSION)
+            },
+            StateError::RomMismatch(expected, found) => {
+                write!(f, "save state belongs to a different ROM (CRC32 {:08X}, loaded {:08X})", found, expected)
+            },
+            StateError::UnexpectedEnd => write!(f, "save state is truncated"),
+            StateError::InvalidValue(what) => write!(f, "save state contains an invalid {}", what),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Trait implemented by every component that is part of a save state
+pub trait Savestate {
+    /// Function that serializes the component
+    fn save(&self, writer: &mut StateWriter);
+
+    /// Function that restores the component
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError>;
+}
+
+/// Class serializing values into a save state
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter {
+            data: Vec::new(),
+        }
+    }
+
+    /// Function that writes the save state header
+    pub fn header(&mut self, rom_hash: u32) {
+        self.bytes(&MAGIC);
+        self.u16(VERSION);
+        self.u32(rom_hash);
+    }
+
+    pub fn u8(&mut self, val: u8) {
+        self.data.push(val);
+    }
+
+    pub fn bool(&mut self, val: bool) {
+        self.data.push(val as u8);
+    }
+
+    pub fn u16(&mut self, val: u16) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, val: u32) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, val: u64) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    /// Function that writes a fixed amount of bytes
+    pub fn bytes(&mut self, val: &[u8]) {
+        self.data.extend_from_slice(val);
+    }
+
+    /// Function that writes a length-prefixed buffer
+    pub fn buffer(&mut self, val: &[u8]) {
+        self.u32(val.len() as u32);
+        self.bytes(val);
+    }
+
+    /// Function that returns the serialized data
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Class deserializing values from a save state
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader {
+            data,
+            position: 0,
+        }
+    }
+
+    /// Function that validates the save state header
+    pub fn header(&mut self, rom_hash: u32) -> Result<(), StateError> {
+        if self.bytes(MAGIC.len())? != MAGIC {
+            return Err(StateError::InvalidMagic);
+        }
+
+        let version = self.u16()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let hash = self.u32()?;
+        if hash != rom_hash {
+            return Err(StateError::RomMismatch(rom_hash, hash));
+        }
+
+        Ok(())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Function that reads a fixed amount of bytes
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.position + len > self.data.len() {
+            return Err(StateError::UnexpectedEnd);
+        }
+
+        let res = &self.data[self.position .. self.position + len];
+        self.position += len;
+        Ok(res)
+    }
+
+    /// Function that reads a fixed amount of bytes into the destination
+    pub fn bytes_into(&mut self, dest: &mut [u8]) -> Result<(), StateError> {
+        dest.copy_from_slice(self.bytes(dest.len())?);
+        Ok(())
+    }
+
+    /// Function that reads a length-prefixed buffer
+    pub fn buffer(&mut self) -> Result<Vec<u8>, StateError> {
+        let len = self.u32()? as usize;
+        Ok(self.bytes(len)?.to_vec())
+    }
+
+    /// Function that reads a length-prefixed buffer, which must have the size of the destination
+    pub fn buffer_into(&mut self, dest: &mut [u8], what: &'static str) -> Result<(), StateError> {
+        let len = self.u32()? as usize;
+        if len != dest.len() {
+            return Err(StateError::InvalidValue(what));
+        }
+
+        self.bytes_into(dest)
+    }
+}
@@ -1,130 +1,245 @@
-use crate::cpu::addressing::Addressing;
 use crate::cpu::cpu::CPU;
-use crate::cpu::instructions::{Instruction, INSTRUCTION_MAP};
+use crate::disassembler::{disassemble_one, Disassembled, Operand, Symbols};
 use crate::ppu::cartridge::Cartridge;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 
-pub fn trace(cpu: &mut CPU) -> String {
-    let code = cpu.read(cpu.prog_counter);
-    let ops: &Instruction = INSTRUCTION_MAP.get(&code).unwrap();
+/// Function that formats the instruction at PC and the registers like nestest.log
+/// Memory is read without side effects so tracing doesn't change the emulation
+pub fn trace(cpu: &CPU) -> String {
+    let pc = cpu.prog_counter;
+    let data = [cpu.bus.peek(pc), cpu.bus.peek(pc.wrapping_add(1)), cpu.bus.peek(pc.wrapping_add(2))];
+    let ins = disassemble_one(&data, 0, pc);
 
-    let begin = cpu.prog_counter;
-    let mut hex_dump = vec![];
-    hex_dump.push(code);
+    let asm_str = match &ins.mnemonic {
+        Some(mnemonic) => {
+            let hex_str: Vec<String> = ins.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
 
-    let (mem_addr, stored_value) = match ops.mode {
-        Addressing::Immediate | Addressing::None => (0, 0),
-        _ => {
-            let (addr, _) = cpu.get_param_address(&ops.mode, begin + 1);
-            (addr, cpu.read(addr))
-        }
+            // the mnemonic is right aligned so that the unofficial "*" sticks out to the left
+            let text = ins.format(&Symbols::default());
+            let operand = text.strip_prefix(mnemonic.as_str()).unwrap_or_default();
+            format!("{:04x}  {:8} {: >4}{}{}", pc, hex_str.join(" "), mnemonic, operand, operand_values(cpu, &ins))
+        },
+        None => format!("{:04x}  {:02x}         ???", pc, data[0]),
     };
 
-    let tmp = match ops.bytes {
-        1 => match ops.address {
-            0x0a | 0x4a | 0x2a | 0x6a => "A ".to_string(),
-            _ => String::from(""),
+    format!(
+        "{:47} A:{:02x} X:{:02x} Y:{:02x} P:{:02x} SP:{:02x}",
+        asm_str, cpu.a.value(), cpu.x.value(), cpu.y.value(), cpu.status.value, cpu.stack_pointer,
+    )
+        .to_ascii_uppercase()
+}
+
+/// Function that formats the addresses and values the operand resolves to at runtime
+/// e.g. " @ 0300 = 55" for "$02FF,X"
+fn operand_values(cpu: &CPU, ins: &Disassembled) -> String {
+    let bus = &cpu.bus;
+    let (x, y) = (cpu.x.value(), cpu.y.value());
+
+    // pointers in the zero page wrap around inside of it
+    let zp_word = |ptr: u8| u16::from_le_bytes([bus.peek(ptr as u16), bus.peek(ptr.wrapping_add(1) as u16)]);
+
+    match ins.operand {
+        // branches and jumps only show their target
+        _ if ins.target().is_some() => String::new(),
+        Operand::Implied | Operand::Accumulator | Operand::Immediate(_) | Operand::Relative(_) => String::new(),
+        Operand::ZeroPage(zp) => format!(" = {:02x}", bus.peek(zp as u16)),
+        Operand::ZeroPageX(zp) | Operand::ZeroPageY(zp) => {
+            let index = if matches!(ins.operand, Operand::ZeroPageX(_)) { x } else { y };
+            let addr = zp.wrapping_add(index) as u16;
+            format!(" @ {:02x} = {:02x}", addr, bus.peek(addr))
+        },
+        Operand::Absolute(addr) => format!(" = {:02x}", bus.peek(addr)),
+        Operand::AbsoluteX(base) | Operand::AbsoluteY(base) => {
+            let index = if matches!(ins.operand, Operand::AbsoluteX(_)) { x } else { y };
+            let addr = base.wrapping_add(index as u16);
+            format!(" @ {:04x} = {:02x}", addr, bus.peek(addr))
+        },
+        Operand::Indirect(ptr) => {
+            // JMP ($xxFF) reads the high byte from $xx00
+            let hi = (ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF);
+            format!(" = {:04x}", u16::from_le_bytes([bus.peek(ptr), bus.peek(hi)]))
         },
-        2 => {
-            let address: u8 = cpu.read(begin + 1);
-            // let value = cpu.mem_read(address));
-            hex_dump.push(address);
-
-            match ops.mode {
-                Addressing::Immediate => format!("#${:02x}", address),
-                Addressing::ZeroPage => format!("${:02x} = {:02x}", mem_addr, stored_value),
-                Addressing::ZeroPageX => format!(
-                    "${:02x},X @ {:02x} = {:02x}",
-                    address, mem_addr, stored_value
-                ),
-                Addressing::ZeroPageY => format!(
-                    "${:02x},Y @ {:02x} = {:02x}",
-                    address, mem_addr, stored_value
-                ),
-                Addressing::IndirectX => format!(
-                    "(${:02x},X) @ {:02x} = {:04x} = {:02x}",
-                    address,
-                    address.wrapping_add(cpu.x.value()),
-                    mem_addr,
-                    stored_value
-                ),
-                Addressing::IndirectY => format!(
-                    "(${:02x}),Y = {:04x} @ {:04x} = {:02x}",
-                    address,
-                    mem_addr.wrapping_sub(cpu.y.value() as u16),
-                    mem_addr,
-                    stored_value
-                ),
-                Addressing::None => {
-                    // assuming local jumps: BNE, BVS, etc....
-                    let address: usize =
-                        (begin as usize + 2).wrapping_add((address as i8) as usize);
-                    format!("${:04x}", address)
+        Operand::IndirectX(zp) => {
+            let ptr = zp.wrapping_add(x);
+            let addr = zp_word(ptr);
+            format!(" @ {:02x} = {:04x} = {:02x}", ptr, addr, bus.peek(addr))
+        },
+        Operand::IndirectY(zp) => {
+            let base = zp_word(zp);
+            let addr = base.wrapping_add(y as u16);
+            format!(" = {:04x} @ {:04x} = {:02x}", base, addr, bus.peek(addr))
+        },
+    }
+}
+
+/// Function that formats a trace line with the "PPU:scanline,dot CYC:cycles" columns of nestest.log
+pub fn trace_ppu(cpu: &CPU) -> String {
+    let ppu = cpu.bus.ppu();
+    format!("{} PPU:{:>3},{:>3} CYC:{}", trace(cpu), ppu.scanline(), ppu.dot(), cpu.bus.cycles)
+}
+
+/// Class representing the instructions that get logged, every condition has to match
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    /// PC ranges (inclusive), empty logs every address
+    pub ranges: Vec<(u16, u16)>,
+
+    /// PRG bank mapped at PC, RAM is never in a bank
+    pub bank: Option<usize>,
+
+    /// Frames (inclusive)
+    pub frames: Option<(u64, u64)>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, cpu: &CPU) -> bool {
+        let pc = cpu.prog_counter;
+        let frame = cpu.bus.ppu().frame();
+
+        (self.ranges.is_empty() || self.ranges.iter().any(|(start, end)| (*start ..= *end).contains(&pc)))
+            && self.bank.is_none_or(|bank| cpu.bus.prg_bank(pc) == Some(bank))
+            && self.frames.is_none_or(|(start, end)| (start ..= end).contains(&frame))
+    }
+
+    /// Function that parses "start-end" or a single value, numbers may start with $ for hex
+    pub fn parse_range(text: &str) -> Option<(u64, u64)> {
+        let number = |text: &str| match text.trim().strip_prefix('$') {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => text.trim().parse().ok(),
+        };
+
+        let (start, end) = text.split_once('-').unwrap_or((text, text));
+        let (start, end) = (number(start)?, number(end)?);
+        (start <= end).then_some((start, end))
+    }
+}
+
+/// Where the trace lines go
+enum Sink {
+    Writer(Box<dyn Write>),
+
+    /// Only the last lines are kept
+    Ring(VecDeque<String>, usize),
+}
+
+/// Class representing a trace logger, `log` is meant to be called from `CPU::interpret_callback`
+pub struct TraceLogger {
+    sink: Sink,
+    pub filter: TraceFilter,
+
+    /// Adds the "PPU:scanline,dot CYC:cycles" columns of nestest.log
+    pub ppu_columns: bool,
+    pub enabled: bool,
+
+    /// File the ring buffer is written to when the logger is finished or dropped
+    ring_file: Option<String>,
+}
+
+impl TraceLogger {
+    /// Create a logger that streams every line into the writer
+    pub fn new<W: Write + 'static>(writer: W) -> Self {
+        TraceLogger {
+            sink: Sink::Writer(Box::new(writer)),
+            filter: TraceFilter::default(),
+            ppu_columns: false,
+            enabled: true,
+            ring_file: None,
+        }
+    }
+
+    /// Create a logger that streams into a buffered file
+    pub fn to_file(path: &str) -> std::io::Result<Self> {
+        Ok(TraceLogger::new(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Create a logger that keeps only the last instructions in memory
+    pub fn ring(capacity: usize) -> Self {
+        TraceLogger {
+            sink: Sink::Ring(VecDeque::with_capacity(capacity), capacity),
+            filter: TraceFilter::default(),
+            ppu_columns: false,
+            enabled: true,
+            ring_file: None,
+        }
+    }
+
+    /// Create a ring buffer logger that replaces the file with its lines when it is finished,
+    /// dropping it does the same so the lines also end up in the file when the emulator panics
+    pub fn ring_to_file(capacity: usize, path: &str) -> Self {
+        let mut logger = TraceLogger::ring(capacity);
+        logger.ring_file = Some(path.to_string());
+        logger
+    }
+
+    /// Function that logs the instruction at PC when the logger is enabled and the filter matches
+    pub fn log(&mut self, cpu: &CPU) -> std::io::Result<()> {
+        if !self.enabled || !self.filter.matches(cpu) {
+            return Ok(());
+        }
+
+        let line = if self.ppu_columns { trace_ppu(cpu) } else { trace(cpu) };
+
+        match &mut self.sink {
+            Sink::Writer(writer) => writeln!(writer, "{}", line),
+            Sink::Ring(lines, capacity) => {
+                if lines.len() == *capacity {
+                    lines.pop_front();
+                }
+                if *capacity > 0 {
+                    lines.push_back(line);
                 }
+                Ok(())
+            },
+        }
+    }
 
-                _ => panic!(
-                    "unexpected addressing mode {:?} has ops-len 2. code {:02x}",
-                    ops.mode, ops.address
-                ),
-            }
+    /// Function that turns the logging on or off, returns the new state
+    pub fn toggle(&mut self) -> bool {
+        self.enabled = !self.enabled;
+        self.enabled
+    }
+
+    /// Lines kept by a ring buffer logger, oldest first
+    pub fn lines(&self) -> Vec<&str> {
+        match &self.sink {
+            Sink::Writer(_) => vec![],
+            Sink::Ring(lines, _) => lines.iter().map(|line| line.as_str()).collect(),
         }
-        3 => {
-            let address_lo = cpu.read(begin + 1);
-            let address_hi = cpu.read(begin + 2);
-            hex_dump.push(address_lo);
-            hex_dump.push(address_hi);
-
-            let address = cpu.read_u16(begin + 1);
-
-            match ops.mode {
-                Addressing::None => {
-                    if ops.address == 0x6c {
-                        //jmp indirect
-                        let jmp_addr = if address & 0x00FF == 0x00FF {
-                            let lo = cpu.read(address);
-                            let hi = cpu.read(address & 0xFF00);
-                            (hi as u16) << 8 | (lo as u16)
-                        } else {
-                            cpu.read_u16(address)
-                        };
-
-                        // let jmp_addr = cpu.mem_read_u16(address);
-                        format!("(${:04x}) = {:04x}", address, jmp_addr)
-                    } else {
-                        format!("${:04x}", address)
-                    }
+    }
+
+    /// Function that flushes the writer, or writes the ring buffer into the writer
+    pub fn flush_into<W: Write>(&mut self, out: &mut W) -> std::io::Result<()> {
+        match &mut self.sink {
+            Sink::Writer(writer) => writer.flush(),
+            Sink::Ring(lines, _) => {
+                for line in lines.iter() {
+                    writeln!(out, "{}", line)?;
                 }
-                Addressing::Absolute => format!("${:04x} = {:02x}", mem_addr, stored_value),
-                Addressing::AbsoluteX => format!(
-                    "${:04x},X @ {:04x} = {:02x}",
-                    address, mem_addr, stored_value
-                ),
-                Addressing::AbsoluteY => format!(
-                    "${:04x},Y @ {:04x} = {:02x}",
-                    address, mem_addr, stored_value
-                ),
-                _ => panic!(
-                    "unexpected addressing mode {:?} has ops-len 3. code {:02x}",
-                    ops.mode, ops.address
-                ),
-            }
+                out.flush()
+            },
         }
-        _ => String::from(""),
-    };
+    }
 
-    let hex_str = hex_dump
-        .iter()
-        .map(|z| format!("{:02x}", z))
-        .collect::<Vec<String>>()
-        .join(" ");
-    let asm_str = format!("{:04x}  {:8}  {: >4} {}", begin, hex_str, ops.name, tmp)
-        .trim_end()
-        .to_string();
+    /// Function that flushes the writer, or writes the ring buffer over its file
+    /// The ring buffer is written only once, later calls only flush
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        match self.ring_file.take() {
+            Some(path) => self.flush_into(&mut BufWriter::new(File::create(path)?)),
+            None => self.flush_into(&mut std::io::sink()),
+        }
+    }
+}
 
-    format!(
-        "{:47} A:{:02x} X:{:02x} Y:{:02x} P:{:02x} SP:{:02x}",
-        asm_str, cpu.a.value(), cpu.x.value(), cpu.y.value(), cpu.status.value, cpu.stack_pointer,
-    )
-        .to_ascii_uppercase()
+impl Drop for TraceLogger {
+    fn drop(&mut self) {
+        if let Some(path) = self.ring_file.clone() {
+            if let Err(err) = self.finish() {
+                eprintln!("Failed to write the trace log {}: {}", path, err);
+            }
+        }
+    }
 }
 
 struct TestRom {
@@ -154,7 +269,7 @@ fn create_rom(rom: TestRom) -> Vec<u8> {
 pub fn test_rom() -> Cartridge {
     let test_rom = create_rom(TestRom {
         header: vec![
-            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00,
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x01, 00, 00, 00, 00, 00, 00, 00, 00, 00,
         ],
         trainer: None,
         pgp_rom: vec![1; 2 * 16384],
@@ -230,4 +345,101 @@ mod test {
             result[0]
         );
     }
+
+    #[test]
+    fn test_format_operand_values() {
+        let mut bus = Bus::new(test_rom(), |_ppu: &PPU, _joy: &mut Joypad| {});
+        let program = [
+            0xB5, 0x10,         // $0064 LDA $10,X
+            0xA1, 0x80,         // $0066 LDA ($80,X)
+            0xBE, 0xFF, 0x01,   // $0068 LDX $01FF,Y
+            0x4A,               // $006B LSR A
+            0x20, 0x00, 0x80,   // $006C JSR $8000
+            0xD0, 0xFE,         // $006F BNE $006F
+            0x6C, 0xFF, 0x02,   // $0071 JMP ($02FF)
+            0xAD, 0x00, 0x03,   // $0074 LDA $0300
+        ];
+        for (i, byte) in program.iter().enumerate() {
+            bus.write(0x64 + i as u16, *byte);
+        }
+        bus.write(0x12, 0x77);
+        bus.write(0x82, 0x00);
+        bus.write(0x83, 0x03);
+        bus.write(0x2FF, 0x34);
+        bus.write(0x200, 0x12);
+        bus.write(0x300, 0x5A);
+
+        let mut cpu = CPU::new(bus);
+        cpu.x.set(2);
+        cpu.y.set(1);
+        let mut line = |pc: u16| {
+            cpu.prog_counter = pc;
+            trace(&cpu)[.. 47].trim_end().to_string()
+        };
+
+        assert_eq!(line(0x64), "0064  B5 10     LDA $10,X @ 12 = 77");
+        assert_eq!(line(0x66), "0066  A1 80     LDA ($80,X) @ 82 = 0300 = 5A");
+        assert_eq!(line(0x68), "0068  BE FF 01  LDX $01FF,Y @ 0200 = 12");
+        assert_eq!(line(0x6B), "006B  4A        LSR A");
+        assert_eq!(line(0x6C), "006C  20 00 80  JSR $8000");
+        assert_eq!(line(0x6F), "006F  D0 FE     BNE $006F");
+        // the page wrap of JMP ($xxFF) reads the high byte from $0200
+        assert_eq!(line(0x71), "0071  6C FF 02  JMP ($02FF) = 1234");
+        assert_eq!(line(0x74), "0074  AD 00 03  LDA $0300 = 5A");
+    }
+
+    #[test]
+    fn test_logger_ring_and_filter() {
+        let mut bus = Bus::new(test_rom(), |_ppu: &PPU, _joy: &mut Joypad| {});
+        // LDX #$03, DEX, BNE -3, then an unknown opcode
+        for (i, byte) in [0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x02].iter().enumerate() {
+            bus.write(100 + i as u16, *byte);
+        }
+
+        let mut cpu = CPU::new(bus);
+        cpu.prog_counter = 0x64;
+
+        let mut logger = TraceLogger::ring(3);
+        logger.ppu_columns = true;
+        logger.filter.ranges.push((0x66, 0x69));
+        cpu.interpret_callback(|cpu| {
+            logger.log(cpu).unwrap();
+        });
+
+        let lines = logger.lines();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("0066  CA        DEX"));
+        assert!(lines[1].starts_with("0067  D0 FD     BNE $0066"));
+        assert!(lines[2].starts_with("0069  02         ???"));
+        assert!(lines[2].contains(" PPU:"));
+        assert!(lines[2].contains(" CYC:"));
+
+        assert_eq!(TraceFilter::parse_range("$10-$20"), Some((0x10, 0x20)));
+        assert_eq!(TraceFilter::parse_range("7"), Some((7, 7)));
+        assert_eq!(TraceFilter::parse_range("9-1"), None);
+    }
+
+    #[test]
+    fn test_ring_written_on_panic() {
+        let path = std::env::temp_dir().join(format!("rustbyte-ring-{}.log", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        std::fs::write(&path, "old log that is much longer than the new one\n".repeat(10)).unwrap();
+
+        let res = std::panic::catch_unwind(|| {
+            let mut cpu = CPU::new(Bus::new(test_rom(), |_ppu: &PPU, _joy: &mut Joypad| {}));
+            cpu.bus.write(100, 0xca);
+            cpu.prog_counter = 0x64;
+
+            let mut logger = TraceLogger::ring_to_file(2, &path);
+            logger.log(&cpu).unwrap();
+            panic!("emulator crashed");
+        });
+        assert!(res.is_err());
+
+        // the file is replaced, not appended to
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(log.lines().count(), 1);
+        assert!(log.starts_with("0064  CA        DEX"));
+    }
 }
@@ -0,0 +1,272 @@
+// Deterministic replay verification
+// Plays a movie without a window and logs the state at every frame boundary
+// (PPU::frame_ready at the start of vblank, where the movie input is applied, whether or not
+// the game has NMI enabled), one line per frame:
+//
+// <frame> <crc32 of Frame.data> <crc32 of CPU RAM> A:00 X:00 Y:00 P:24 SP:FD PC:C000 CTRL:80 MASK:1E STATUS:80 SL:241 DOT:4
+//
+// Comparing the log against a stored one catches emulation regressions.
+
+use crate::cpu::bus::Bus;
+use crate::cpu::cpu::CPU;
+use crate::hash::crc32;
+use crate::movie::{Movie, MovieSession, Reset};
+use crate::ppu::cartridge::Cartridge;
+use crate::ppu::ppu::PPU;
+use crate::region::Region;
+use crate::render::frame::Frame;
+use crate::render::input::joypad::Joypad;
+use crate::render::renderer::Renderer;
+use crate::state::StateError;
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+/// Names of the registers in a snapshot, in log order
+const REGISTERS: [&str; 11] = ["A", "X", "Y", "P", "SP", "PC", "CTRL", "MASK", "STATUS", "SL", "DOT"];
+
+/// CPU and PPU registers at a frame boundary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Registers {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub scanline: u16,
+    pub dot: u16,
+}
+
+impl Registers {
+    /// Function that captures the registers of a running machine
+    pub fn capture(cpu: &CPU) -> Self {
+        let ppu = cpu.bus.ppu();
+
+        Registers {
+            a: cpu.a.value(),
+            x: cpu.x.value(),
+            y: cpu.y.value(),
+            p: cpu.status.value,
+            sp: cpu.stack_pointer,
+            pc: cpu.prog_counter,
+            ctrl: ppu.controller_register.value,
+            mask: ppu.mask(),
+            status: ppu.status_register.value,
+            scanline: ppu.scanline(),
+            dot: ppu.dot() as u16,
+        }
+    }
+
+    /// Register values in log order
+    fn values(&self) -> [u16; REGISTERS.len()] {
+        [
+            self.a as u16, self.x as u16, self.y as u16, self.p as u16, self.sp as u16, self.pc,
+            self.ctrl as u16, self.mask as u16, self.status as u16, self.scanline, self.dot,
+        ]
+    }
+
+    /// Function that lists the registers that differ, e.g. "A: 10 != 11"
+    pub fn diff(&self, other: &Registers) -> Vec<String> {
+        self.values()
+            .iter()
+            .zip(other.values().iter())
+            .zip(REGISTERS.iter())
+            .filter(|((a, b), _)| a != b)
+            .map(|((a, b), name)| format!("{}: {:02X} != {:02X}", name, a, b))
+            .collect()
+    }
+}
+
+impl Display for Registers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let values = self.values();
+        write!(
+            f,
+            "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PC:{:04X} CTRL:{:02X} MASK:{:02X} STATUS:{:02X} SL:{} DOT:{}",
+            values[0], values[1], values[2], values[3], values[4], values[5],
+            values[6], values[7], values[8], values[9], values[10],
+        )
+    }
+}
+
+/// Hashes of a single frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHash {
+    pub frame: usize,
+
+    /// CRC-32 of the rendered frame
+    pub video: u32,
+
+    /// CRC-32 of the CPU RAM
+    pub ram: u32,
+
+    pub registers: Registers,
+}
+
+impl FrameHash {
+    /// Function that parses a single line of the log
+    pub fn parse(line: &str) -> Option<FrameHash> {
+        let mut fields = line.split_whitespace();
+        let frame = fields.next()?.parse().ok()?;
+        let video = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let ram = u32::from_str_radix(fields.next()?, 16).ok()?;
+
+        let mut values = [0u16; REGISTERS.len()];
+        for (i, value) in values.iter_mut().enumerate() {
+            let (name, val) = fields.next()?.split_once(':')?;
+            if name != REGISTERS[i] {
+                return None;
+            }
+
+            // scanline and dot are decimal, the rest is hex
+            *value = if i >= 9 { val.parse().ok()? } else { u16::from_str_radix(val, 16).ok()? };
+        }
+
+        Some(FrameHash {
+            frame,
+            video,
+            ram,
+            registers: Registers {
+                a: values[0] as u8,
+                x: values[1] as u8,
+                y: values[2] as u8,
+                p: values[3] as u8,
+                sp: values[4] as u8,
+                pc: values[5],
+                ctrl: values[6] as u8,
+                mask: values[7] as u8,
+                status: values[8] as u8,
+                scanline: values[9],
+                dot: values[10],
+            },
+        })
+    }
+}
+
+impl Display for FrameHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:08X} {:08X} {}", self.frame, self.video, self.ram, self.registers)
+    }
+}
+
+/// Function that plays the movie without a window and hashes every frame
+pub fn run_movie(cartridge: Cartridge, movie: Movie) -> Result<Vec<FrameHash>, StateError> {
+    let frames = movie.frames.len();
+    let region = if movie.pal { Region::PAL } else { cartridge.region };
+
+    // the game callback renders the frame, the hash is picked up after the instruction
+    let video = Rc::new(RefCell::new(Vec::new()));
+    let hashes = video.clone();
+    let mut frame = Frame::new();
+
+    let mut bus = Bus::new(cartridge, move |ppu: &PPU, _joy: &mut Joypad| {
+        Renderer::render(ppu, &mut frame);
+        hashes.borrow_mut().push(crc32(&frame.data));
+    });
+    bus.set_region(region);
+
+    let mut cpu = CPU::new(bus);
+    cpu.reset();
+
+    if let Some(state) = &movie.savestate {
+        cpu.load_state(state)?;
+    }
+    cpu.bus.set_movie(MovieSession::play(movie));
+
+    // the loop is bounded by the frames the PPU produces, a run that stops producing hashes
+    // ends short and shows up as a divergence instead of hanging
+    let last_frame = cpu.bus.ppu().frame() + frames as u64 + 1;
+
+    let mut res = Vec::with_capacity(frames);
+    while res.len() < frames && cpu.bus.ppu().frame() <= last_frame {
+        cpu.poll_interrupts();
+
+        if !cpu.step() {
+            break;
+        }
+
+        let video = video.borrow();
+        if video.len() > res.len() {
+            res.push(FrameHash {
+                frame: res.len(),
+                video: video[res.len()],
+                ram: crc32(cpu.bus.ram()),
+                registers: Registers::capture(&cpu),
+            });
+
+            match cpu.bus.movie().and_then(|movie| movie.take_reset()) {
+                Some(Reset::Soft) => cpu.reset(),
+                Some(Reset::Power) => cpu.power_cycle(),
+                None => {},
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// Function that formats the hashes as a log
+pub fn to_log(hashes: &[FrameHash]) -> String {
+    hashes.iter().map(|hash| format!("{}\n", hash)).collect()
+}
+
+/// Function that parses a log, returning the number of the first invalid line on failure
+pub fn parse_log(text: &str) -> Result<Vec<FrameHash>, usize> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| FrameHash::parse(line).ok_or(i + 1))
+        .collect()
+}
+
+/// First frame where two runs differ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub frame: usize,
+
+    /// None if the run ended before this frame
+    pub expected: Option<FrameHash>,
+    pub actual: Option<FrameHash>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Diverged at frame {}", self.frame)?;
+
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) => {
+                if expected.video != actual.video {
+                    writeln!(f, "  video: {:08X} != {:08X}", expected.video, actual.video)?;
+                }
+                if expected.ram != actual.ram {
+                    writeln!(f, "  ram: {:08X} != {:08X}", expected.ram, actual.ram)?;
+                }
+                for line in expected.registers.diff(&actual.registers) {
+                    writeln!(f, "  {}", line)?;
+                }
+                writeln!(f, "  expected: {}", expected.registers)?;
+                write!(f, "  actual:   {}", actual.registers)
+            },
+            (Some(_), None) => write!(f, "  the run ended early"),
+            (None, Some(_)) => write!(f, "  the run has more frames than expected"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Function that finds the first frame where the runs differ
+pub fn compare(expected: &[FrameHash], actual: &[FrameHash]) -> Option<Divergence> {
+    let len = expected.len().max(actual.len());
+
+    (0 .. len)
+        .find(|i| expected.get(*i) != actual.get(*i))
+        .map(|i| Divergence {
+            frame: i,
+            expected: expected.get(i).copied(),
+            actual: actual.get(i).copied(),
+        })
+}
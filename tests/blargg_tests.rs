@@ -0,0 +1,113 @@
+mod common;
+
+use common::nrom;
+use rust_byte::blargg::{format_table, run_test_rom, TestStatus, DEFAULT_FRAMES};
+use rust_byte::ppu::cartridge::Cartridge;
+
+/// Directory searched for real test ROMs (or $BLARGG_DIR), the test is ignored unless run with --ignored
+const BLARGG_DIR: &str = "tests/roms/blargg";
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// Assembles a test ROM, `report` is the code that runs after the protocol is set up
+    fn test_rom(report: &str) -> Cartridge {
+        let source = format!("
+            .org $C000
+            reset:
+                lda #$80
+                sta $6000
+                lda #$DE
+                sta $6001
+                lda #$B0
+                sta $6002
+                lda #$61
+                sta $6003
+                lda #$80        ; enable NMI so the frames keep counting
+                sta $2000
+            {}
+            done:
+                jmp done
+            nmi:
+                rti
+            text:
+                .byte \"Passed\", 0
+            .org $FFFA
+                .word nmi, reset, reset
+        ", report);
+
+        nrom(&source).0
+    }
+
+    #[test]
+    fn validate_pass_after_reset() {
+        // the first boot asks for a reset, the second one reports the result
+        let rom = test_rom("
+                lda $6010
+                bne second
+                inc $6010
+                lda #$81
+                sta $6000
+                jmp done
+            second:
+                ldx #0
+            @copy:
+                lda text,x
+                sta $6004,x
+                beq @end
+                inx
+                bne @copy
+            @end:
+                lda #0
+                sta $6000
+        ");
+
+        let result = run_test_rom(rom, 120);
+        assert_eq!(result.status, TestStatus::Passed);
+        assert_eq!(result.message, "Passed");
+        assert!(result.frames >= 6);
+    }
+
+    #[test]
+    fn validate_failure_and_timeout() {
+        let result = run_test_rom(test_rom("lda #3\nsta $6000"), 120);
+        assert_eq!(result.status, TestStatus::Failed(3));
+
+        let result = run_test_rom(test_rom(""), 10);
+        assert_eq!(result.status, TestStatus::Timeout);
+        assert_eq!(result.frames, 10);
+
+        let table = format_table(&[("fails.nes".to_string(), result)]);
+        assert!(table.contains("fails.nes  timeout"));
+        assert!(table.ends_with("0 of 1 passed"));
+    }
+
+    #[test]
+    #[ignore = "needs the test ROMs, run with --ignored"]
+    fn validate_rom_directory() {
+        let dir = std::env::var("BLARGG_DIR").unwrap_or(BLARGG_DIR.to_string());
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            panic!("put the test ROMs in {}", dir);
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "nes"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "no .nes files in {}", dir);
+
+        let results: Vec<_> = paths
+            .iter()
+            .map(|path| {
+                let cartridge = Cartridge::new(std::fs::read(path).unwrap()).unwrap();
+                (path.file_name().unwrap().to_string_lossy().to_string(), run_test_rom(cartridge, DEFAULT_FRAMES))
+            })
+            .collect();
+
+        let table = format_table(&results);
+        println!("{}", table);
+        assert!(results.iter().all(|(_, result)| result.status == TestStatus::Passed), "{}", table);
+    }
+}
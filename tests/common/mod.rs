@@ -0,0 +1,17 @@
+// Fixtures shared by the integration tests
+
+use rust_byte::assembler::{assemble, Assembly};
+use rust_byte::ppu::cartridge::Cartridge;
+
+/// Function that assembles a program into an NROM cartridge
+/// The 32kB of PRG-ROM are mapped at $8000, the source sets the vectors with ".org $FFFA"
+pub fn nrom(source: &str) -> (Cartridge, Assembly) {
+    let asm = assemble(source).unwrap_or_else(|err| panic!("{}", err));
+    let mut prg = vec![0; 0x8000];
+    asm.patch(&mut prg, 0x8000).unwrap();
+
+    let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    rom.extend(prg);
+    rom.extend(vec![0; 0x2000]);
+    (Cartridge::new(rom).unwrap(), asm)
+}
@@ -1,6 +1,271 @@
+mod common;
+
+use common::nrom;
+use rust_byte::assembler::Assembly;
+use rust_byte::cpu::bus::Bus;
+use rust_byte::cpu::cpu::CPU;
+use rust_byte::flags::Status;
+use rust_byte::ppu::cartridge::Cartridge;
+use rust_byte::ppu::ppu::PPU;
+use rust_byte::render::input::joypad::Joypad;
+use rust_byte::trace::trace_ppu;
+
+/// nestest.nes and its reference log aren't distributed with the emulator,
+/// the ignored conformance test looks for them in this directory (or in $NESTEST_DIR) and fails without them
+const NESTEST_DIR: &str = "tests/roms";
+
 #[cfg(test)]
 mod test {
     use super::*;
 
-    // TODO: Add tests for the CPU since the old tests are not working
+    /// Assembles the program at $C000 into an NROM cartridge and runs it until it reaches the "done" label
+    fn run<'a>(source: &str) -> (CPU<'a>, Assembly) {
+        // vectors: NMI = RESET = IRQ = $C000
+        let (cartridge, asm) = nrom(&format!(".org $C000\n{}\ndone: jmp done\n.org $FFFA\n.word $C000, $C000, $C000", source));
+        let done = asm.label("done").unwrap();
+
+        let bus = Bus::new(cartridge, |_ppu: &PPU, _joy: &mut Joypad| {});
+        let mut cpu = CPU::new(bus);
+        cpu.reset();
+
+        for _ in 0 .. 100_000 {
+            if cpu.prog_counter == done {
+                return (cpu, asm);
+            }
+            assert!(cpu.step(), "unknown opcode at ${:04X}", cpu.prog_counter);
+        }
+
+        panic!("the program didn't reach done");
+    }
+
+    fn flag(cpu: &CPU, status: Status) -> bool {
+        cpu.status.value & status.as_u8() != 0
+    }
+
+    #[test]
+    fn test_loads_and_stores() {
+        let (mut cpu, _) = run("
+            lda #$42
+            ldx #$05
+            ldy #$80
+            sta $10
+            stx $0300
+            sty $10,x
+            lda $10
+            ldx $15
+        ");
+
+        assert_eq!(cpu.a.value(), 0x42);
+        assert_eq!(cpu.x.value(), 0x80);
+        assert_eq!(cpu.y.value(), 0x80);
+        assert_eq!(cpu.bus.read(0x0300), 0x05);
+        assert!(flag(&cpu, Status::Negative));
+        assert!(!flag(&cpu, Status::Zero));
+    }
+
+    #[test]
+    fn test_adc_sbc_flags() {
+        let (cpu, _) = run("
+            clc
+            lda #$7F
+            adc #$01    ; signed overflow
+            php
+            pla
+            sta $00
+            sec
+            lda #$00
+            sbc #$01    ; borrow
+        ");
+
+        assert_eq!(cpu.a.value(), 0xFF);
+        assert!(!flag(&cpu, Status::Carry));
+        assert!(flag(&cpu, Status::Negative));
+        assert_eq!(cpu.bus.peek(0x00) & (Status::Overflow.as_u8() | Status::Negative.as_u8()), 0xC0);
+    }
+
+    #[test]
+    fn test_loops_and_branches() {
+        let (cpu, _) = run("
+                ldx #10
+                lda #0
+                clc
+            @loop:
+                adc #3
+                dex
+                bne @loop
+                cmp #30
+                beq @equal
+                lda #$FF
+            @equal:
+        ");
+
+        assert_eq!(cpu.a.value(), 30);
+        assert_eq!(cpu.x.value(), 0);
+        assert!(flag(&cpu, Status::Zero));
+        assert!(flag(&cpu, Status::Carry));
+    }
+
+    #[test]
+    fn test_subroutines_and_stack() {
+        let (cpu, _) = run("
+                ldx #$FF
+                txs
+                lda #1
+                jsr double
+                jsr double
+                jmp done
+            double:
+                pha
+                pla
+                asl a
+                rts
+        ");
+
+        assert_eq!(cpu.a.value(), 4);
+        assert_eq!(cpu.stack_pointer, 0xFF);
+    }
+
+    #[test]
+    fn test_indirect_addressing() {
+        let (cpu, _) = run("
+            table = $0400
+                lda #<table
+                sta $20
+                lda #>table
+                sta $21
+                ldy #3
+                lda #$AB
+                sta ($20),y     ; $0403
+                ldx #$02
+                lda #$11
+                sta ($1E,x)     ; pointer at $20 -> $0400
+                lda table+3
+                ldx table
+        ");
+
+        assert_eq!(cpu.a.value(), 0xAB);
+        assert_eq!(cpu.x.value(), 0x11);
+    }
+
+    #[test]
+    fn test_jmp_indirect_page_wrap() {
+        let (cpu, asm) = run("
+                lda #<target
+                sta $02FF
+                lda #>target
+                sta $0200       ; the high byte comes from the start of the same page
+                lda #0
+                sta $0300
+                jmp ($02FF)
+            target:
+                lda #$77
+        ");
+
+        assert_eq!(cpu.a.value(), 0x77);
+        assert!(asm.label("target").is_some());
+    }
+
+    #[test]
+    fn test_shifts_and_rotates() {
+        let (cpu, _) = run("
+            lda #%10000001
+            sec
+            ror a       ; 11000000, carry = 1
+            rol a       ; 10000001, carry = 1
+            lsr a       ; 01000000, carry = 1
+            sta $30
+            asl $30     ; 10000000, carry = 0
+            rol $30     ; 00000000, carry = 1
+        ");
+
+        assert_eq!(cpu.a.value(), 0b0100_0000);
+        assert_eq!(cpu.bus.peek(0x30), 0);
+        assert!(flag(&cpu, Status::Carry));
+    }
+
+    #[test]
+    fn test_unofficial_opcodes() {
+        let (cpu, _) = run("
+            lda #$40
+            sta $10
+            lax $10         ; A = X = $40
+            lda #$0F
+            sax $11         ; $40 & $0F
+            lda #$41
+            dcp $10         ; $10 = $3F, compare with $41
+            php
+            isb $10         ; $10 = $40, A = $41 - $40 with the carry set by dcp
+            sta $12
+            plp
+            lda #$01
+            slo $12         ; $12 = $02, A = $03, carry clear
+            *nop $10
+            *sbc #$02       ; A = $03 - $02 - 1
+        ");
+
+        assert_eq!(cpu.x.value(), 0x40);
+        assert_eq!(cpu.bus.peek(0x10), 0x40);
+        assert_eq!(cpu.bus.peek(0x11), 0x00);
+        assert_eq!(cpu.bus.peek(0x12), 0x02);
+        assert_eq!(cpu.a.value(), 0x00);
+        assert!(flag(&cpu, Status::Zero));
+    }
+
+    /// Function that shows the lines around the first difference side by side, with a marker under the differing column
+    fn side_by_side(line: usize, context: &[(String, String)], expected: &str, actual: &str) -> String {
+        let width = context.iter().map(|(expected, _)| expected.len()).chain([expected.len()]).max().unwrap_or(0);
+        let mut res = format!("nestest.log differs on line {}\n", line);
+
+        res.push_str(&format!("  {:width$} | {}\n", "expected", "actual", width = width));
+        for (expected, actual) in context {
+            res.push_str(&format!("  {:width$} | {}\n", expected, actual, width = width));
+        }
+        res.push_str(&format!("> {:width$} | {}\n", expected, actual, width = width));
+
+        let column = expected.chars().zip(actual.chars()).take_while(|(a, b)| a == b).count();
+        res.push_str(&format!("  {}^\n", " ".repeat(column)));
+        res
+    }
+
+    #[test]
+    #[ignore = "needs nestest.nes and nestest.log, run with --ignored"]
+    fn test_nestest() {
+        let dir = std::env::var("NESTEST_DIR").unwrap_or(NESTEST_DIR.to_string());
+        let rom = std::fs::read(format!("{}/nestest.nes", dir));
+        let log = std::fs::read_to_string(format!("{}/nestest.log", dir));
+
+        let (Ok(rom), Ok(log)) = (rom, log) else {
+            panic!("put nestest.nes and nestest.log in {}", dir);
+        };
+
+        let bus = Bus::new(Cartridge::new(rom).unwrap(), |_ppu: &PPU, _joy: &mut Joypad| {});
+        let mut cpu = CPU::new(bus);
+        cpu.reset();
+
+        // automation mode starts at $C000 after the 7 cycles of the reset sequence
+        cpu.prog_counter = 0xC000;
+        cpu.status.value = 0x24;
+        cpu.bus.tick(7);
+
+        let mut context: Vec<(String, String)> = vec![];
+        for (i, expected) in log.lines().map(|line| line.trim_end()).filter(|line| !line.is_empty()).enumerate() {
+            cpu.poll_interrupts();
+
+            let actual = trace_ppu(&cpu);
+            if actual != expected {
+                panic!("{}", side_by_side(i + 1, &context, expected, &actual));
+            }
+
+            context.push((expected.to_string(), actual));
+            if context.len() > 5 {
+                context.remove(0);
+            }
+
+            assert!(cpu.step(), "unknown opcode on line {}", i + 1);
+        }
+
+        // nestest stores the number of the first failed official / unofficial test here
+        assert_eq!(cpu.bus.peek(0x02), 0, "official opcode test failed");
+        assert_eq!(cpu.bus.peek(0x03), 0, "unofficial opcode test failed");
+    }
 }
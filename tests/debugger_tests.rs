@@ -0,0 +1,344 @@
+mod common;
+
+use common::nrom;
+use rust_byte::cpu::bus::{AddressSpace, Bus};
+use rust_byte::cpu::cpu::CPU;
+use rust_byte::debugger::engine::{Breakpoint, Debugger, StopReason, Watchpoint};
+use rust_byte::debugger::expression::Expression;
+use rust_byte::debugger::gdb::GdbStub;
+use rust_byte::debugger::repl::Repl;
+use rust_byte::ppu::cartridge::Cartridge;
+use rust_byte::ppu::ppu::PPU;
+use rust_byte::render::input::joypad::Joypad;
+use std::io::{Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// NROM cartridge with a main loop calling a subroutine that writes to VRAM
+    fn debug_rom() -> Cartridge {
+        nrom("
+            .org $8000
+            reset:
+                lda #$80        ; $8000
+                sta $2000       ; $8002
+            main:
+                jsr write       ; $8005
+                inc $10         ; $8008
+                jmp main        ; $800A
+
+            .org $8010
+            write:
+                lda #$20        ; $8010
+                sta $2006       ; $8012
+                lda #$00        ; $8015
+                sta $2006       ; $8017
+                lda #$55        ; $801A
+                sta $2007       ; $801C
+                rts             ; $801F
+
+            .org $8100
+            nmi:
+                rti
+
+            .org $FFFA
+                .word nmi, reset, reset
+        ").0
+    }
+
+    fn machine<'a>() -> CPU<'a> {
+        let bus = Bus::new(debug_rom(), |_ppu: &PPU, _joy: &mut Joypad| {});
+        let mut cpu = CPU::new(bus);
+        cpu.reset();
+        cpu
+    }
+
+    /// Runs the CPU the same way as interpret_callback until the debugger stops it
+    fn run(cpu: &mut CPU, debugger: &mut Debugger) -> Option<StopReason> {
+        for _ in 0 .. 100_000 {
+            cpu.poll_interrupts();
+
+            if let Some(reason) = debugger.check(cpu) {
+                return Some(reason);
+            }
+
+            cpu.step();
+        }
+
+        None
+    }
+
+    #[test]
+    fn validate_breakpoint() {
+        let mut cpu = machine();
+        let mut debugger = Debugger::new();
+        debugger.breakpoints.push(Breakpoint { addr: 0x8008, condition: None });
+
+        assert_eq!(run(&mut cpu, &mut debugger), Some(StopReason::Breakpoint(0)));
+        assert_eq!(cpu.prog_counter, 0x8008);
+
+        // resuming from a breakpoint doesn't stop at it again before executing it
+        cpu.step();
+        assert_eq!(run(&mut cpu, &mut debugger), Some(StopReason::Breakpoint(0)));
+        assert_eq!(cpu.bus.peek(0x10), 1);
+    }
+
+    #[test]
+    fn validate_conditional_breakpoint() {
+        let mut cpu = machine();
+        let mut debugger = Debugger::new();
+        debugger.breakpoints.push(Breakpoint {
+            addr: 0x8008,
+            condition: Some(Expression::parse("A == #$55 && [$10] > 3").unwrap()),
+        });
+
+        assert_eq!(run(&mut cpu, &mut debugger), Some(StopReason::Breakpoint(0)));
+        assert_eq!(cpu.bus.peek(0x10), 4);
+    }
+
+    #[test]
+    fn validate_watchpoints() {
+        let mut cpu = machine();
+        let mut debugger = Debugger::new();
+
+        // code fetches don't trigger read watchpoints
+        debugger.watchpoints.push(Watchpoint {
+            space: AddressSpace::CPU,
+            start: 0x8000,
+            end: 0x80FF,
+            read: true,
+            write: false,
+            execute: false,
+        });
+        debugger.watchpoints.push(Watchpoint {
+            space: AddressSpace::PPU,
+            start: 0x2000,
+            end: 0x23FF,
+            read: false,
+            write: true,
+            execute: false,
+        });
+
+        let reason = run(&mut cpu, &mut debugger).unwrap();
+        match reason {
+            StopReason::Watchpoint(1, access) => {
+                assert_eq!(access.addr, 0x2000);
+                assert!(access.write);
+            },
+            _ => panic!("unexpected stop {:?}", reason),
+        }
+        assert_eq!(cpu.prog_counter, 0x801F);
+
+        debugger.watchpoints.clear();
+        debugger.watchpoints.push(Watchpoint {
+            space: AddressSpace::CPU,
+            start: 0x8010,
+            end: 0x8010,
+            read: false,
+            write: false,
+            execute: true,
+        });
+        cpu.step();
+        assert!(matches!(run(&mut cpu, &mut debugger), Some(StopReason::Watchpoint(0, _))));
+        assert_eq!(cpu.prog_counter, 0x8010);
+    }
+
+    #[test]
+    fn validate_stepping() {
+        let mut cpu = machine();
+        let mut debugger = Debugger::new();
+        debugger.breakpoints.push(Breakpoint { addr: 0x8005, condition: None });
+        run(&mut cpu, &mut debugger);
+        debugger.breakpoints.clear();
+
+        // step into the subroutine
+        debugger.step_into();
+        cpu.step();
+        assert_eq!(run(&mut cpu, &mut debugger), Some(StopReason::Step));
+        assert_eq!(cpu.prog_counter, 0x8010);
+
+        // step out of it
+        debugger.step_out(&cpu);
+        cpu.step();
+        assert_eq!(run(&mut cpu, &mut debugger), Some(StopReason::Step));
+        assert_eq!(cpu.prog_counter, 0x8008);
+
+        // step over the next call
+        debugger.step_into();
+        cpu.step();
+        run(&mut cpu, &mut debugger);
+        debugger.step_into();
+        cpu.step();
+        run(&mut cpu, &mut debugger);
+        assert_eq!(cpu.prog_counter, 0x8005);
+
+        debugger.step_over(&cpu);
+        cpu.step();
+        assert_eq!(run(&mut cpu, &mut debugger), Some(StopReason::Step));
+        assert_eq!(cpu.prog_counter, 0x8008);
+    }
+
+    #[test]
+    fn validate_nmi_and_scanline() {
+        let mut cpu = machine();
+        let mut debugger = Debugger::new();
+        debugger.break_on_nmi = true;
+
+        assert_eq!(run(&mut cpu, &mut debugger), Some(StopReason::Nmi));
+        assert_eq!(cpu.prog_counter, 0x8100);
+
+        debugger.break_on_nmi = false;
+        debugger.run_to_scanline(100);
+        cpu.step();
+        assert_eq!(run(&mut cpu, &mut debugger), Some(StopReason::Scanline(100)));
+        assert_eq!(cpu.bus.ppu().scanline(), 100);
+    }
+
+    #[test]
+    fn validate_repl_commands() {
+        let mut cpu = machine();
+        let input = Cursor::new("b $8008 if [$10] == 2\nbogus\nc\nx $10 1\np A + 1\nq\n");
+        let mut output = vec![];
+
+        let mut debugger = Debugger::new();
+        debugger.pause();
+
+        {
+            let mut repl = Repl::new(debugger, input, &mut output);
+            let mut running = true;
+            for _ in 0 .. 100_000 {
+                cpu.poll_interrupts();
+                running = repl.hook(&mut cpu);
+                if !running {
+                    break;
+                }
+                cpu.step();
+            }
+            assert!(!running);
+        }
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Stopped: paused"));
+        assert!(output.contains("Breakpoint 0 at $8008"));
+        assert!(output.contains("Error: unknown command bogus"));
+        assert!(output.contains("Stopped: breakpoint 0"));
+        assert!(output.contains("0010: 02"));
+        assert!(output.contains("86 ($56)"));
+    }
+
+    #[test]
+    fn validate_repl_memory_commands() {
+        let mut cpu = machine();
+        let mut output = vec![];
+
+        {
+            let mut repl = Repl::new(Debugger::new(), Cursor::new(""), &mut output);
+            let commands = [
+                "poke $0300 5 5", "poke pal $3F01 $21", "x pal $3F00 2", "poke oam $FF 1 2",
+                "search ram", "poke $0300 4", "search dec", "search = 4", "x prg $8000",
+            ];
+            let results: Vec<_> = commands.iter().map(|command| repl.execute(command, &mut cpu)).collect();
+
+            assert!(results[3].is_err());
+            assert_eq!(results[8], Err("$8000 is outside of $6000-$7FFF".to_string()));
+        }
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("3F00: 00 21"));
+        assert!(output.contains("2048 candidates"));
+        assert!(output.contains("1 candidates\n  $0300: 4 -> 4"));
+    }
+
+    /// Sends a packet and returns the reply, acknowledging it like GDB does
+    fn request(stream: &mut TcpStream, data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        stream.write_all(format!("${}#{:02x}", data, checksum).as_bytes()).unwrap();
+        reply(stream)
+    }
+
+    fn reply(stream: &mut TcpStream) -> String {
+        let mut byte = [0u8; 1];
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+
+        let mut data = vec![];
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+
+        let mut checksum = [0u8; 2];
+        stream.read_exact(&mut checksum).unwrap();
+        stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn validate_gdb_stub() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let mut replies = vec![];
+
+            for packet in ["qSupported:swbreak+", "?", "g", "m8000,5", "Z0,8008,1", "c"] {
+                replies.push(request(&mut stream, packet));
+            }
+            for packet in ["p5", "s"] {
+                replies.push(request(&mut stream, packet));
+            }
+            for packet in ["p5", "M0010,1:07", "m0010,1", "M8000,1:00", "P0=42", "p0", "z0,8008,1"] {
+                replies.push(request(&mut stream, packet));
+            }
+
+            // Ctrl-C while running
+            stream.write_all(b"$c#63").unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            stream.write_all(&[0x03]).unwrap();
+            replies.push(reply(&mut stream));
+
+            stream.write_all(b"$k#6b").unwrap();
+            replies
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(stream).unwrap();
+        let mut cpu = machine();
+
+        loop {
+            cpu.poll_interrupts();
+            if !stub.hook(&mut cpu) {
+                break;
+            }
+            cpu.step();
+        }
+
+        let replies = client.join().unwrap();
+        assert!(replies[0].contains("qXfer:features:read+"));
+        assert_eq!(replies[1], "S05");
+        assert_eq!(replies[2], "00000024fd0080");
+        assert_eq!(replies[3], "a9808d0020");
+        assert_eq!(replies[4], "OK");
+        assert_eq!(replies[5], "S05");
+        assert_eq!(replies[6], "0880");
+        assert_eq!(replies[7], "S05");
+        assert_eq!(replies[8], "0a80");
+        assert_eq!(replies[9], "OK");
+        assert_eq!(replies[10], "07");
+        assert_eq!(replies[11], "E01");
+        assert_eq!(replies[12], "OK");
+        assert_eq!(replies[13], "42");
+        assert_eq!(replies[14], "OK");
+        assert_eq!(replies[15], "S02");
+    }
+}
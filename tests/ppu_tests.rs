@@ -2,7 +2,16 @@ use rust_byte::ppu::ppu::PPU;
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use rust_byte::cpu::bus::Bus;
+    use rust_byte::gamedb::GameDb;
+    use rust_byte::hash::crc32;
+    use rust_byte::ppu::cartridge::Cartridge;
     use rust_byte::ppu::mirroring::Mirroring;
+    use rust_byte::region::Region;
+    use rust_byte::render::color_palette::{PALETTE, PALETTE_PAL};
+    use rust_byte::render::frame::Frame;
+    use rust_byte::render::input::joypad::Joypad;
+    use rust_byte::render::renderer::Renderer;
 
     #[test]
     fn verify_vram_write_behavior() {
@@ -194,4 +203,399 @@ pub mod test {
         ppu.write_oam_address(0xFF);
         assert_eq!(ppu.read_oam_data(), 0xBB);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn validate_palette_mirroring() {
+        let mut ppu = PPU::new_empty_rom();
+
+        // $3F10 is a mirror of $3F00
+        ppu.write_address_register(0x3F);
+        ppu.write_address_register(0x10);
+        ppu.write(0x2A);
+        assert_eq!(ppu.palette[0x00], 0x2A);
+
+        // $3F11 is not mirrored
+        ppu.write(0x15);
+        assert_eq!(ppu.palette[0x11], 0x15);
+
+        // $3F0C reads back the value written through $3F1C
+        ppu.write_address_register(0x3F);
+        ppu.write_address_register(0x1C);
+        ppu.write(0x07);
+        ppu.write_address_register(0x3F);
+        ppu.write_address_register(0x0C);
+        assert_eq!(ppu.read() & 0x3F, 0x07);
+
+        // $3F20 - $3FFF mirrors $3F00 - $3F1F
+        ppu.write_address_register(0x3F);
+        ppu.write_address_register(0xE0);
+        assert_eq!(ppu.read() & 0x3F, 0x2A);
+    }
+
+    #[test]
+    fn validate_palette_read_fills_buffer() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.ram[0x0705] = 0x66;
+        ppu.palette[0x05] = 0x21;
+
+        ppu.write_address_register(0x3F);
+        ppu.write_address_register(0x05);
+
+        // palette read is not buffered
+        assert_eq!(ppu.read() & 0x3F, 0x21);
+
+        // the buffer now contains the name table byte at $2F05 (mirrored to $2705)
+        ppu.write_address_register(0x20);
+        ppu.write_address_register(0x00);
+        assert_eq!(ppu.read(), 0x66);
+    }
+
+    #[test]
+    fn validate_palette_open_bus_bits() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.palette[0x01] = 0x3F;
+
+        // fill the latch with 0xC0 through a write-only register
+        ppu.write_mask_register(0x00);
+        ppu.write_scroll_register(0xC0);
+
+        ppu.write_address_register(0x3F);
+        ppu.write_address_register(0x01);
+        assert_eq!(ppu.read_open_bus(), 0x01);
+
+        ppu.write_scroll_register(0xC0);
+        ppu.write_address_register(0x3F);
+        ppu.write_address_register(0x01);
+        ppu.write_scroll_register(0xC0);
+        assert_eq!(ppu.read(), 0xFF);
+    }
+
+    #[test]
+    fn validate_status_open_bus_bits() {
+        let mut ppu = PPU::new_empty_rom();
+
+        ppu.write_oam_address(0x1F);
+        assert_eq!(ppu.read_status_register() & 0x1F, 0x1F);
+        assert_eq!(ppu.read_open_bus(), 0x1F);
+    }
+
+    #[test]
+    fn validate_open_bus_decay() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_oam_address(0xFF);
+        assert_eq!(ppu.read_open_bus(), 0xFF);
+
+        // tick the PPU for a bit more than 36 frames
+        for _ in 0 .. 37 * 262 * 341 / 240 + 1 {
+            ppu.tick(240);
+        }
+
+        assert_eq!(ppu.read_open_bus(), 0x00);
+    }
+
+    #[test]
+    fn test_four_screen_mirroring_logic() {
+        let mut ppu = PPU::new(vec![0; 2048], Mirroring::FourScreen);
+
+        // every name table has its own memory
+        for (i, hi) in [0x20u8, 0x24, 0x28, 0x2C].iter().enumerate() {
+            ppu.write_address_register(*hi);
+            ppu.write_address_register(0x42);
+            ppu.write(0x10 + i as u8);
+        }
+
+        assert_eq!(ppu.ram[0x0042], 0x10);
+        assert_eq!(ppu.ram[0x0442], 0x11);
+        assert_eq!(ppu.cart_vram[0x0042], 0x12);
+        assert_eq!(ppu.cart_vram[0x0442], 0x13);
+
+        // $3C42 mirrors $2C42
+        ppu.write_address_register(0x3C);
+        ppu.write_address_register(0x42);
+
+        // pre-fetch
+        ppu.read();
+        assert_eq!(ppu.read(), 0x13);
+        assert_eq!(ppu.nametable(3)[0x42], 0x13);
+    }
+
+    #[test]
+    fn test_single_screen_mirroring_logic() {
+        let mut ppu = PPU::new(vec![0; 2048], Mirroring::SingleScreenB);
+
+        ppu.write_address_register(0x28);
+        ppu.write_address_register(0x01);
+        ppu.write(0x77);
+
+        assert_eq!(ppu.ram[0x0401], 0x77);
+        for table in 0..4 {
+            assert_eq!(ppu.nametable(table)[0x01], 0x77);
+        }
+
+        ppu.set_mirroring(Mirroring::SingleScreenA);
+        assert_eq!(ppu.nametable(2)[0x01], 0x00);
+    }
+
+    /// Ticks the PPU by whole scanlines
+    fn tick_scanlines(ppu: &mut PPU, scanlines: usize) -> bool {
+        let mut frame_done = false;
+        for _ in 0 .. scanlines {
+            frame_done |= ppu.tick(170);
+            frame_done |= ppu.tick(171);
+        }
+        frame_done
+    }
+
+    #[test]
+    fn validate_pal_frame_timing() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.set_region(Region::PAL);
+        ppu.write_control_register(0b1000_0000);
+
+        assert!(!tick_scanlines(&mut ppu, 241));
+        ppu.tick(1);
+        assert!(ppu.nmi);
+
+        // PAL frames are 312 scanlines long
+        assert!(!tick_scanlines(&mut ppu, 262 - 241));
+        assert!(tick_scanlines(&mut ppu, 312 - 262));
+    }
+
+    #[test]
+    fn validate_dendy_vblank_timing() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.set_region(Region::Dendy);
+        ppu.write_control_register(0b1000_0000);
+
+        tick_scanlines(&mut ppu, 241);
+        assert!(!ppu.nmi);
+
+        // Dendy starts vblank on scanline 291
+        tick_scanlines(&mut ppu, 291 - 241);
+        ppu.tick(1);
+        assert!(ppu.nmi);
+    }
+
+    /// Renders a frame of backdrop color $16 and returns its top left pixel
+    fn backdrop_pixel(region: Region, mask: u8) -> (u8, u8, u8) {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.set_region(region);
+        ppu.write_mask_register(mask);
+        ppu.palette[0] = 0x16;
+
+        let mut frame = Frame::new();
+        Renderer::render(&ppu, &mut frame);
+        (frame.data[0], frame.data[1], frame.data[2])
+    }
+
+    #[test]
+    fn validate_pal_palette_and_emphasis() {
+        assert_eq!(backdrop_pixel(Region::NTSC, 0), PALETTE[0x16]);
+        assert_eq!(backdrop_pixel(Region::PAL, 0), PALETTE_PAL[0x16]);
+        assert_eq!(backdrop_pixel(Region::Dendy, 0), PALETTE_PAL[0x16]);
+
+        // bit 5 emphasizes red on NTSC, it darkens green and blue
+        let (r, g, b) = PALETTE[0x16];
+        assert_eq!(backdrop_pixel(Region::NTSC, 0b0010_0000), (r, (g as f32 * 0.816) as u8, (b as f32 * 0.816) as u8));
+
+        // the 2C07 has the red and green bits swapped, bit 5 emphasizes green
+        let (r, g, b) = PALETTE_PAL[0x16];
+        assert_eq!(backdrop_pixel(Region::PAL, 0b0010_0000), ((r as f32 * 0.816) as u8, g, (b as f32 * 0.816) as u8));
+    }
+
+    #[test]
+    fn validate_vblank_set_on_dot_one() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_control_register(0b1000_0000);
+
+        tick_scanlines(&mut ppu, 241);
+        assert_eq!((ppu.scanline(), ppu.dot()), (241, 0));
+        assert!(!ppu.nmi);
+
+        ppu.tick(1);
+        assert!(ppu.nmi);
+
+        // flag is cleared on dot 1 of the pre-render scanline
+        tick_scanlines(&mut ppu, 261 - 241);
+        assert_eq!((ppu.scanline(), ppu.dot()), (261, 1));
+        assert_eq!(ppu.status_register.value & 0x80, 0);
+        assert!(!ppu.nmi);
+    }
+
+    #[test]
+    fn validate_status_read_before_vblank_suppresses_flag() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_control_register(0b1000_0000);
+
+        tick_scanlines(&mut ppu, 241);
+        assert_eq!(ppu.read_status_register() & 0x80, 0);
+
+        ppu.tick(10);
+        assert!(!ppu.nmi);
+        assert_eq!(ppu.read_status_register() & 0x80, 0);
+    }
+
+    #[test]
+    fn validate_status_read_on_vblank_cancels_nmi() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_control_register(0b1000_0000);
+
+        tick_scanlines(&mut ppu, 241);
+        ppu.tick(2);
+        assert!(ppu.nmi);
+
+        assert_eq!(ppu.read_status_register() & 0x80, 0x80);
+        assert!(!ppu.nmi);
+    }
+
+    #[test]
+    fn validate_odd_frame_skipped_dot() {
+        let mut ppu = PPU::new_empty_rom();
+
+        // rendering disabled: every frame is 262 * 341 dots
+        tick_scanlines(&mut ppu, 262 * 2);
+        assert_eq!((ppu.scanline(), ppu.dot()), (0, 0));
+
+        // rendering enabled: the odd frame is one dot shorter
+        ppu.write_mask_register(0b0000_1000);
+        tick_scanlines(&mut ppu, 262);
+        assert_eq!((ppu.scanline(), ppu.dot()), (0, 0));
+        tick_scanlines(&mut ppu, 262);
+        assert_eq!((ppu.scanline(), ppu.dot()), (0, 1));
+    }
+
+    /// Creates an iNES image with the given number of PRG and CHR banks
+    fn ines_rom(prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, prg_banks, chr_banks, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.extend(vec![0; prg_banks as usize * 0x4000]);
+        rom.extend(vec![0x11; chr_banks as usize * 0x2000]);
+        rom
+    }
+
+    #[test]
+    fn validate_chr_ram_write() {
+        let cartridge = Cartridge::new(ines_rom(2, 0)).unwrap();
+        assert!(cartridge.chr_ram);
+        assert_eq!(cartridge.chr_rom.len(), 0x2000);
+
+        let mut ppu = PPU::new(cartridge.chr_rom, cartridge.mirroring);
+        ppu.set_chr_ram(cartridge.chr_ram);
+
+        ppu.write_address_register(0x1F);
+        ppu.write_address_register(0xF0);
+        ppu.write(0x5A);
+        assert_eq!(ppu.chr[0x1FF0], 0x5A);
+
+        ppu.write_address_register(0x1F);
+        ppu.write_address_register(0xF0);
+
+        // pre-fetch
+        ppu.read();
+        assert_eq!(ppu.read(), 0x5A);
+    }
+
+    #[test]
+    fn validate_chr_rom_is_read_only() {
+        let cartridge = Cartridge::new(ines_rom(1, 1)).unwrap();
+        assert!(!cartridge.chr_ram);
+
+        let mut ppu = PPU::new(cartridge.chr_rom, cartridge.mirroring);
+        ppu.set_chr_ram(cartridge.chr_ram);
+
+        ppu.write_address_register(0x00);
+        ppu.write_address_register(0x10);
+        ppu.write(0x5A);
+        assert_eq!(ppu.chr[0x0010], 0x11);
+    }
+
+    #[test]
+    fn validate_dirty_header_is_ignored() {
+        let mut rom = ines_rom(1, 1);
+        rom[7 .. 16].copy_from_slice(b"DiskDude!");
+
+        let cartridge = Cartridge::new(rom).unwrap();
+        assert_eq!(cartridge.region, Region::NTSC);
+        assert_eq!(cartridge.prg_ram_size, 0x2000);
+        assert_eq!(cartridge.corrections.len(), 1);
+        assert_eq!(cartridge.corrections[0].to_string(), "mapper: 64 -> 0");
+    }
+
+    #[test]
+    fn validate_game_database_corrections() {
+        let rom = ines_rom(1, 0);
+        let mut data = vec![0; 0x4000];
+        data[0] = 0xEA;
+
+        let mut rom_with_code = rom.clone();
+        rom_with_code[16] = 0xEA;
+        let db = GameDb::parse(&format!(
+            "{:08X} mirroring=vertical battery=1 chr_ram=16384 region=pal title=Test Game\n",
+            crc32(&data),
+        ))
+        .unwrap();
+
+        // the hash doesn't match, the header is kept
+        let cartridge = Cartridge::with_db(rom, &db).unwrap();
+        assert!(cartridge.corrections.is_empty());
+        assert_eq!(cartridge.title, None);
+
+        let cartridge = Cartridge::with_db(rom_with_code, &db).unwrap();
+        assert_eq!(cartridge.title.as_deref(), Some("Test Game"));
+        assert_eq!(cartridge.mirroring, Mirroring::Vertical);
+        assert_eq!(cartridge.region, Region::PAL);
+        assert!(cartridge.battery);
+        assert_eq!(cartridge.chr_rom.len(), 0x4000);
+
+        let fixed: Vec<String> = cartridge.corrections.iter().map(|correction| correction.to_string()).collect();
+        assert_eq!(
+            fixed,
+            vec!["mirroring: Horizontal -> Vertical", "battery: false -> true", "region: NTSC -> PAL", "CHR-RAM size: 8192 -> 16384"]
+        );
+    }
+
+    #[test]
+    fn validate_trainer_and_four_screen_flags() {
+        // four-screen is bit 3, it doesn't mean there is a trainer
+        let mut rom = ines_rom(1, 1);
+        rom[6] = 0x08;
+        let cartridge = Cartridge::new(rom).unwrap();
+        assert_eq!(cartridge.mirroring, Mirroring::FourScreen);
+        assert!(cartridge.trainer.is_none());
+
+        // the trainer is bit 2, its 512 bytes come before the PRG-ROM
+        let mut rom = ines_rom(1, 1);
+        rom[6] = 0x05;
+        rom.splice(16 .. 16, (0 .. 512).map(|i| i as u8));
+        rom[16 + 512] = 0xEA;
+
+        let cartridge = Cartridge::new(rom).unwrap();
+        assert_eq!(cartridge.mirroring, Mirroring::Vertical);
+        assert_eq!(cartridge.prg_rom[0], 0xEA);
+        assert_eq!(cartridge.chr_rom[0], 0x11);
+
+        let bus = Bus::new(cartridge, |_ppu: &PPU, _joy: &mut Joypad| {});
+        assert_eq!(bus.peek(0x6FFF), 0x00);
+        assert_eq!(bus.peek(0x7000), 0x00);
+        assert_eq!(bus.peek(0x7001), 0x01);
+        assert_eq!(bus.peek(0x71FF), 0xFF);
+        assert_eq!(bus.peek(0x7200), 0x00);
+    }
+
+    #[test]
+    fn validate_prg_ram_size() {
+        // NES 2.0 with 32kB of battery backed PRG-RAM
+        let mut rom = ines_rom(1, 1);
+        rom[6] |= 0x02;
+        rom[7] = 0x08;
+        rom[10] = 0x90;
+        let bus = Bus::new(Cartridge::new(rom).unwrap(), |_ppu: &PPU, _joy: &mut Joypad| {});
+        assert_eq!(bus.prg_ram().len(), 0x8000);
+
+        // the bus never allocates less than 8kB
+        let mut rom = ines_rom(1, 1);
+        rom[7] = 0x08;
+        rom[10] = 0x01;
+        let bus = Bus::new(Cartridge::new(rom).unwrap(), |_ppu: &PPU, _joy: &mut Joypad| {});
+        assert_eq!(bus.prg_ram().len(), 0x2000);
+    }
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use common::nrom;
+use rust_byte::cpu::bus::Bus;
+use rust_byte::cpu::cpu::CPU;
+use rust_byte::ppu::cartridge::Cartridge;
+use rust_byte::ppu::ppu::PPU;
+use rust_byte::render::input::joypad::Joypad;
+use rust_byte::state::{StateError, VERSION};
+use rust_byte::trace::test_rom;
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// Creates a CPU with a few values scattered over the machine
+    fn machine<'a>(rom: Cartridge) -> CPU<'a> {
+        let mut bus = Bus::new(rom, |_ppu: &PPU, _joy: &mut Joypad| {});
+        bus.write(0x0010, 0x42);
+        bus.write(0x2006, 0x21);
+        bus.write(0x2006, 0x08);
+        bus.write(0x2007, 0x99);
+        bus.tick(100);
+
+        let mut cpu = CPU::new(bus);
+        cpu.a.set(0x12);
+        cpu.x.set(0x34);
+        cpu.y.set(0x56);
+        cpu.prog_counter = 0x8123;
+        cpu.stack_pointer = 0xF0;
+        cpu
+    }
+
+    #[test]
+    fn validate_state_roundtrip() {
+        let mut cpu = machine(test_rom());
+        let state = cpu.save_state();
+
+        // change everything
+        cpu.a.set(0);
+        cpu.x.set(0);
+        cpu.y.set(0);
+        cpu.prog_counter = 0;
+        cpu.stack_pointer = 0;
+        cpu.write(0x0010, 0);
+        cpu.bus.tick(50);
+
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.a.value(), 0x12);
+        assert_eq!(cpu.x.value(), 0x34);
+        assert_eq!(cpu.y.value(), 0x56);
+        assert_eq!(cpu.prog_counter, 0x8123);
+        assert_eq!(cpu.stack_pointer, 0xF0);
+        assert_eq!(cpu.read(0x0010), 0x42);
+        assert_eq!(cpu.bus.cycles, 100);
+
+        // saving again produces the same state
+        assert_eq!(cpu.save_state(), state);
+    }
+
+    #[test]
+    fn validate_cheats_are_not_saved() {
+        let mut cpu = machine(test_rom());
+        let state = cpu.save_state();
+
+        cpu.bus.cheats_mut().add("ZGAAAA", "").unwrap();
+        cpu.bus.cheats_mut().add("0010:07", "").unwrap();
+        assert_eq!(cpu.save_state(), state);
+
+        // ZGAAAA reads $42 from $8000
+        assert_eq!(cpu.read(0x8000), 0x42);
+        cpu.bus.cheats_mut().active = false;
+        assert_ne!(cpu.read(0x8000), 0x42);
+        cpu.bus.cheats_mut().active = true;
+
+        // RAM cheats are applied at the start of the next frame, also after loading a state
+        cpu.bus.write(0x2000, 0x80);
+        for _ in 0 .. 30_000 {
+            cpu.bus.tick(1);
+        }
+        assert_eq!(cpu.read(0x0010), 0x07);
+
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.read(0x0010), 0x42);
+        assert_eq!(cpu.bus.cheats().cheats.len(), 2);
+    }
+
+    #[test]
+    fn validate_ram_cheats_without_nmi() {
+        // the game never enables NMI, the cheat is still written once per frame
+        let (rom, _) = nrom(".org $8000\nreset: inc $10\njmp reset\n.org $FFFA\n.word reset, reset, reset");
+        let mut cpu = CPU::new(Bus::new(rom, |_ppu: &PPU, _joy: &mut Joypad| {}));
+        cpu.reset();
+        cpu.bus.cheats_mut().add("0020:63", "").unwrap();
+
+        let frame = cpu.bus.ppu().frame();
+        while cpu.bus.ppu().frame() < frame + 2 {
+            cpu.bus.tick(1);
+        }
+        assert_eq!(cpu.read(0x0020), 0x63);
+    }
+
+    #[test]
+    fn validate_state_from_other_rom_is_rejected() {
+        let cpu = machine(test_rom());
+        let state = cpu.save_state();
+
+        let (rom, _) = nrom(".org $8000\nreset: nop\njmp reset\n.org $FFFA\n.word reset, reset, reset");
+        let mut other = machine(rom);
+
+        assert!(matches!(other.load_state(&state), Err(StateError::RomMismatch(_, _))));
+        assert_eq!(other.a.value(), 0x12);
+    }
+
+    #[test]
+    fn validate_invalid_states_are_rejected() {
+        let mut cpu = machine(test_rom());
+        let mut state = cpu.save_state();
+
+        assert_eq!(cpu.load_state(b"nope"), Err(StateError::InvalidMagic));
+
+        // truncated state leaves the machine untouched
+        cpu.a.set(0x77);
+        assert_eq!(cpu.load_state(&state[..state.len() - 10]), Err(StateError::UnexpectedEnd));
+        assert_eq!(cpu.a.value(), 0x77);
+
+        state[4] = (VERSION + 1) as u8;
+        assert_eq!(cpu.load_state(&state), Err(StateError::UnsupportedVersion(VERSION + 1)));
+    }
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use common::nrom;
+use rust_byte::cpu::bus::Bus;
+use rust_byte::cpu::cpu::CPU;
+use rust_byte::movie::{FrameInput, Movie, MovieSession, Reset, COMMAND_POWER, COMMAND_RESET};
+use rust_byte::ppu::cartridge::Cartridge;
+use rust_byte::ppu::ppu::PPU;
+use rust_byte::render::input::joypad::Joypad;
+use rust_byte::verify::{compare, parse_log, run_movie, to_log};
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// NROM cartridge that enables NMI and adds the A button to $20 every frame
+    fn counter_rom() -> Cartridge {
+        nrom("
+            .org $8000
+            reset:
+                lda #$80
+                sta $2000
+            spin:
+                inc $10
+                jmp spin
+
+            .org $8100
+            nmi:
+                lda #$01
+                sta $4016
+                lda #$00
+                sta $4016
+                lda $4016
+                clc
+                adc $20
+                sta $20
+                rti
+
+            .org $FFFA
+                .word nmi, reset, reset
+        ").0
+    }
+
+    /// NROM cartridge that never enables NMI, it polls vblank and adds the A button to $20
+    fn polling_rom() -> Cartridge {
+        nrom("
+            .org $8000
+            reset:
+                lda $2002
+                bpl reset
+                lda #$01
+                sta $4016
+                lda #$00
+                sta $4016
+                lda $4016
+                and #$01
+                clc
+                adc $20
+                sta $20
+                jmp reset
+
+            .org $FFFA
+                .word reset, reset, reset
+        ").0
+    }
+
+    fn movie(frames: usize, pressed: &[usize]) -> Movie {
+        Movie {
+            frames: (0 .. frames)
+                .map(|i| FrameInput {
+                    commands: 0,
+                    port0: if pressed.contains(&i) { 0x01 } else { 0 },
+                })
+                .collect(),
+            ..Movie::default()
+        }
+    }
+
+    #[test]
+    fn validate_deterministic_replay() {
+        let first = run_movie(counter_rom(), movie(20, &[3, 4])).unwrap();
+        let second = run_movie(counter_rom(), movie(20, &[3, 4])).unwrap();
+
+        assert_eq!(first.len(), 20);
+        assert_eq!(compare(&first, &second), None);
+    }
+
+    #[test]
+    fn validate_log_roundtrip() {
+        let hashes = run_movie(counter_rom(), movie(5, &[1])).unwrap();
+        let log = to_log(&hashes);
+
+        assert_eq!(log.lines().count(), 5);
+        assert_eq!(parse_log(&log).unwrap(), hashes);
+        assert_eq!(parse_log("0 00000000 zz"), Err(1));
+    }
+
+    #[test]
+    fn validate_first_divergence() {
+        let expected = run_movie(counter_rom(), movie(20, &[])).unwrap();
+        let actual = run_movie(counter_rom(), movie(20, &[10])).unwrap();
+
+        // the input of frame 10 is read by the NMI handler after the frame 10 boundary
+        let divergence = compare(&expected, &actual).unwrap();
+        assert_eq!(divergence.frame, 11);
+
+        let report = divergence.to_string();
+        assert!(report.contains("Diverged at frame 11"));
+        assert!(report.contains("ram:"));
+    }
+
+    #[test]
+    fn validate_shorter_run() {
+        let expected = run_movie(counter_rom(), movie(10, &[])).unwrap();
+        let actual = run_movie(counter_rom(), movie(8, &[])).unwrap();
+
+        let divergence = compare(&expected, &actual).unwrap();
+        assert_eq!(divergence.frame, 8);
+        assert!(divergence.actual.is_none());
+    }
+
+    #[test]
+    fn validate_replay_without_nmi() {
+        // the movie advances on every frame, the game never turns NMI on
+        let expected = run_movie(polling_rom(), movie(12, &[])).unwrap();
+        let actual = run_movie(polling_rom(), movie(12, &[5, 6])).unwrap();
+
+        assert_eq!(expected.len(), 12);
+        assert_eq!(actual.len(), 12);
+        assert!(compare(&expected, &actual).unwrap().frame > 5);
+    }
+
+    /// Function that plays the movie and returns the CPU RAM at its end
+    fn play(input: Movie) -> Vec<u8> {
+        let frames = input.frames.len() as u64;
+        let mut cpu = CPU::new(Bus::new(counter_rom(), |_ppu: &PPU, _joy: &mut Joypad| {}));
+        cpu.reset();
+        cpu.bus.set_movie(MovieSession::play(input));
+
+        while cpu.bus.ppu().frame() < frames {
+            cpu.poll_interrupts();
+            assert!(cpu.step());
+
+            match cpu.bus.movie().and_then(|session| session.take_reset()) {
+                Some(Reset::Soft) => cpu.reset(),
+                Some(Reset::Power) => cpu.power_cycle(),
+                None => {},
+            }
+        }
+        cpu.bus.ram().to_vec()
+    }
+
+    #[test]
+    fn validate_reset_and_power_commands() {
+        let mut soft = movie(10, &[1, 2]);
+        soft.frames[6].commands = COMMAND_RESET;
+        let mut power = movie(10, &[1, 2]);
+        power.frames[6].commands = COMMAND_POWER;
+
+        // a reset keeps the A presses counted in RAM, a power cycle clears them
+        assert_eq!(play(movie(10, &[1, 2]))[0x20], 2);
+        assert_eq!(play(soft)[0x20], 2);
+        assert_eq!(play(power)[0x20], 0);
+    }
+
+    #[test]
+    fn validate_spinning_rom_terminates() {
+        // JMP * with NMI off, the run still ends after the movie
+        let (rom, _) = nrom(".org $8000\nreset: jmp reset\n.org $FFFA\n.word reset, reset, reset");
+        let hashes = run_movie(rom, movie(5, &[])).unwrap();
+        assert_eq!(hashes.len(), 5);
+    }
+}